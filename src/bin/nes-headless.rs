@@ -0,0 +1,320 @@
+//! Runs a ROM without a window and reports hashes of the output, for CI.
+//!
+//! nes-headless <rom> [--frames N] [--until pc=ADDR|ADDR=VALUE]
+//!     [--input SCRIPT|--movie FM2] [--export-movie FM2] [--png FILE] [--wav FILE]
+//!     [--ram-init zeros|ff|alternating|random:SEED] [--hashes FILE] [--compare-hashes FILE]
+//! nes-headless nestest.nes --nestest nestest.log
+//! nes-headless <blargg test rom> --blargg [--frames TIMEOUT]
+//! nes-headless <rom> --golden FILE [--input SCRIPT] [--out DIR]
+//! nes-headless <rom> --disasm START-END
+//! nes-headless <rom> --trace FILE [--trace-from ADDR] [--trace-range START-END]
+
+use std::{
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    process,
+};
+
+use nes_emulator::{
+    emulator::{
+        cartridge::Cartridge,
+        desync::FrameHashes,
+        disasm,
+        movie::Movie,
+        ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+        ram::RamInit,
+        trace::TraceLogger,
+        Emulator,
+    },
+    headless::{self, GoldenFrames, InputScript, StopCondition},
+};
+
+const USAGE: &str = "Usage: nes-headless <rom> [--frames N] [--until pc=ADDR|ADDR=VALUE] \
+                     [--input SCRIPT|--movie FM2] [--export-movie FM2] [--png FILE] [--wav FILE] \
+                     [--ram-init PATTERN] [--hashes FILE] [--compare-hashes FILE] [--nestest LOG] [--blargg] \
+                     [--golden FILE [--out DIR]] [--disasm START-END] \
+                     [--trace FILE [--trace-from ADDR] [--trace-range START-END]]";
+
+/// Instructions printed when the emulator crashes while tracing
+const CRASH_DUMP_LINES: usize = 32;
+
+/// Frames run without `--frames`, unless a movie is played
+const DEFAULT_FRAMES: u64 = 60;
+
+/// `--blargg` timeout without `--frames`, most tests take several seconds
+const BLARGG_TIMEOUT_FRAMES: u64 = 60 * 60;
+
+struct Options {
+    rom: PathBuf,
+    frames: Option<u64>,
+    until: Option<StopCondition>,
+    input: Option<PathBuf>,
+    /// Play an `.fm2` movie, for as many frames as it has by default
+    movie: Option<PathBuf>,
+    /// Write the input as an `.fm2` movie
+    export_movie: Option<PathBuf>,
+    png: Option<PathBuf>,
+    wav: Option<PathBuf>,
+    /// What RAM holds at power on
+    ram_init: RamInit,
+    /// Write the state hash of every frame
+    hashes: Option<PathBuf>,
+    /// Compare the state hash of every frame against a file written by
+    /// `--hashes` and report the first one that differs
+    compare_hashes: Option<PathBuf>,
+    /// Compare a nestest trace against this log instead of running frames
+    nestest: Option<PathBuf>,
+    /// Run a blargg test ROM until it reports a result, `frames` is the timeout
+    blargg: bool,
+    /// Compare frames against a golden file, mismatches are written to `out`
+    golden: Option<PathBuf>,
+    out: PathBuf,
+    /// Print the disassembly of a CPU address range after loading the ROM
+    disasm: Option<(u16, u16)>,
+    /// Log every instruction to this file
+    trace: Option<PathBuf>,
+    trace_from: Option<u16>,
+    trace_range: Option<(u16, u16)>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+
+    let mut rom = None;
+    let mut options = Options {
+        rom: PathBuf::new(),
+        frames: None,
+        until: None,
+        input: None,
+        movie: None,
+        export_movie: None,
+        png: None,
+        wav: None,
+        ram_init: RamInit::Zeros,
+        hashes: None,
+        compare_hashes: None,
+        nestest: None,
+        blargg: false,
+        golden: None,
+        out: PathBuf::from("."),
+        disasm: None,
+        trace: None,
+        trace_from: None,
+        trace_range: None,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "--frames" => {
+                let frames = value()?;
+                options.frames = Some(
+                    frames
+                        .parse()
+                        .map_err(|_| format!("Invalid frame count: {}", frames))?,
+                );
+            }
+            "--until" => options.until = Some(StopCondition::parse(&value()?)?),
+            "--input" => options.input = Some(value()?.into()),
+            "--movie" => options.movie = Some(value()?.into()),
+            "--export-movie" => options.export_movie = Some(value()?.into()),
+            "--png" => options.png = Some(value()?.into()),
+            "--wav" => options.wav = Some(value()?.into()),
+            "--ram-init" => options.ram_init = RamInit::parse(&value()?)?,
+            "--hashes" => options.hashes = Some(value()?.into()),
+            "--compare-hashes" => options.compare_hashes = Some(value()?.into()),
+            "--nestest" => options.nestest = Some(value()?.into()),
+            "--blargg" => options.blargg = true,
+            "--golden" => options.golden = Some(value()?.into()),
+            "--out" => options.out = value()?.into(),
+            "--disasm" => options.disasm = Some(parse_range(&value()?)?),
+            "--trace" => options.trace = Some(value()?.into()),
+            "--trace-from" => options.trace_from = Some(parse_address(&value()?)?),
+            "--trace-range" => options.trace_range = Some(parse_range(&value()?)?),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => rom = Some(arg.into()),
+        }
+    }
+
+    if options.input.is_some() && options.movie.is_some() {
+        return Err("--input and --movie can't be used together".to_string());
+    }
+
+    options.rom = rom.ok_or("No ROM given")?;
+    Ok(options)
+}
+
+/// Parses a hex address with an optional `$`
+fn parse_address(text: &str) -> Result<u16, String> {
+    u16::from_str_radix(text.trim().trim_start_matches('$'), 16)
+        .map_err(|_| format!("Invalid hex address: {}", text))
+}
+
+/// Parses `START-END`, see [`parse_address`]
+fn parse_range(text: &str) -> Result<(u16, u16), String> {
+    let (start, end) = text
+        .split_once('-')
+        .ok_or_else(|| format!("Expected START-END, got {}", text))?;
+    Ok((parse_address(start)?, parse_address(end)?))
+}
+
+/// Returns whether the stop condition, if any, was met
+fn run(options: &Options) -> Result<bool, String> {
+    let cartridge = Cartridge::load(&options.rom)?;
+
+    if let Some(path) = &options.nestest {
+        let reference =
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let lines = headless::nestest(&cartridge, &reference)?;
+        println!("nestest: all {} lines match", lines);
+        return Ok(true);
+    }
+
+    if options.blargg {
+        let text = headless::blargg(&cartridge, options.frames.unwrap_or(BLARGG_TIMEOUT_FRAMES))?;
+        println!("passed: {}", text);
+        return Ok(true);
+    }
+
+    let input = match &options.input {
+        Some(path) => InputScript::load(path)?,
+        None => InputScript::default(),
+    };
+
+    if let Some(path) = &options.golden {
+        let golden = GoldenFrames::load(path)?;
+        let name = options
+            .rom
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy();
+        headless::check_golden(&cartridge, &input, &golden, &name, &options.out)?;
+        println!("all {} golden frames match", golden.frames.len());
+        return Ok(true);
+    }
+
+    let mut emulator = Emulator::with_ram_init(options.ram_init);
+    emulator.load_rom(&cartridge)?;
+    if options.hashes.is_some() || options.compare_hashes.is_some() {
+        emulator.hashes = Some(FrameHashes::new());
+    }
+
+    let mut frames = options.frames.unwrap_or(DEFAULT_FRAMES);
+    if let Some(path) = &options.movie {
+        let movie = Movie::load(path)?;
+        frames = options.frames.unwrap_or(movie.frames.len() as u64);
+        emulator.play_movie(movie, true)?;
+    }
+
+    if let Some((start, end)) = options.disasm {
+        let memory = emulator.memory.borrow();
+        for instruction in disasm::disassemble_range(&memory, start, end) {
+            let bytes: Vec<String> = instruction
+                .bytes()
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            println!(
+                "{:04X}  {:<8}  {}",
+                instruction.address,
+                bytes.join(" "),
+                instruction
+            );
+        }
+        return Ok(true);
+    }
+
+    if let Some(path) = &options.trace {
+        let mut trace = TraceLogger::to_file(path, CRASH_DUMP_LINES)?;
+        trace.trigger = options.trace_from;
+        trace
+            .ranges
+            .extend(options.trace_range.map(|(start, end)| start..=end));
+        emulator.trace = Some(trace);
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        headless::run(&mut emulator, frames, &input, options.until.as_ref())
+    }));
+
+    if let Some(trace) = &mut emulator.trace {
+        trace.flush();
+        if let Some(e) = &trace.error {
+            println!("{}, the trace stops there", e);
+        }
+    }
+
+    let (frames, stopped) = match result {
+        Ok(result) => result,
+        Err(_) => {
+            let dump = emulator.trace.as_ref().map(|trace| trace.dump());
+            return Err(format!(
+                "Emulator crashed, last instructions:\n{}",
+                dump.unwrap_or_else(|| "(run with --trace to record them)".to_string())
+            ));
+        }
+    };
+
+    let framebuffer = emulator.ppu.borrow().framebuffer.clone();
+    // there is no APU yet, so the audio is silence of the right length
+    let audio = vec![0i16; headless::samples_for_frames(frames)];
+    let audio_bytes: Vec<u8> = audio.iter().flat_map(|s| s.to_le_bytes()).collect();
+
+    println!("frames: {}", frames);
+    println!("framebuffer crc32: {:08x}", headless::crc32(&framebuffer));
+    println!("audio crc32: {:08x}", headless::crc32(&audio_bytes));
+
+    if let Some(path) = &options.export_movie {
+        let rom_filename = options.rom.file_name().unwrap_or_default();
+        let movie = match emulator.stop_movie() {
+            Some(movie) => movie,
+            None => input.to_movie(frames, &rom_filename.to_string_lossy()),
+        };
+        movie.save(path)?;
+    }
+
+    let hashes = emulator.hashes.take().unwrap_or_default();
+    if let Some(path) = &options.hashes {
+        hashes.save(path)?;
+    }
+    if let Some(path) = &options.compare_hashes {
+        let expected = FrameHashes::load(path)?;
+        match expected.first_divergence(&hashes) {
+            Some(frame) => return Err(format!("Desync: first divergent frame is {}", frame)),
+            None => println!("all {} frame hashes match", hashes.hashes.len()),
+        }
+    }
+
+    if let Some(path) = &options.png {
+        headless::write_png(path, SCREEN_WIDTH, SCREEN_HEIGHT, &framebuffer)?;
+    }
+    if let Some(path) = &options.wav {
+        headless::write_wav(path, &audio)?;
+    }
+
+    Ok(options.until.is_none() || stopped)
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{}\n{}", e, USAGE);
+            process::exit(1);
+        }
+    };
+
+    match run(&options) {
+        Ok(true) => {}
+        Ok(false) => {
+            println!("Stop condition not met");
+            process::exit(2);
+        }
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! A small two pass 6502 assembler, for tests and patching code from the
+//! debugger.
+//!
+//! ```text
+//! ; comments start with a semicolon
+//!         .org $8000
+//! start:  LDX #0
+//! loop:   LDA message,X       ; zero page modes are used when the address
+//!         STA $0200,X         ; is known to fit in a byte
+//!         INX
+//!         CPX #3
+//!         BNE loop
+//!         JMP (vector)
+//! message: .byte 'h', $69, %00100001
+//! vector:  .word start, >start, <start + 1
+//! ```
+//!
+//! Numbers are decimal, `$` hex or `%` binary, `'c'` is a character, and
+//! `<`/`>` take the low/high byte of an expression. Expressions add and
+//! subtract numbers and labels, a leading `-` negates, and negative bytes
+//! wrap so `#-1` is `#$FF`.
+
+use std::collections::HashMap;
+
+use super::{
+    cpu::AddressingMode::{self, *},
+    opcodes::{self, OPCODES},
+    ram::RAM,
+};
+
+/// Assembled bytes, starting at `origin`
+#[derive(Debug, PartialEq, Eq)]
+pub struct Image {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl Image {
+    /// Writes the image into memory, ROM included
+    pub fn patch(&self, memory: &mut RAM) {
+        for (i, byte) in self.bytes.iter().enumerate() {
+            memory[self.origin.wrapping_add(i as u16)] = *byte;
+        }
+    }
+}
+
+/// Origin used until the first `.org`
+pub const DEFAULT_ORIGIN: u16 = 0x8000;
+
+/// Assembles `source` into one image covering every `.org` section, gaps
+/// between sections are filled with zeros. The image has to fit in RAM
+/// ($0000-$1FFF) or PRG-RAM and ROM ($6000-$FFFF), so it can be patched in.
+pub fn assemble(source: &str) -> Result<Image, String> {
+    let statements = parse(source)?;
+
+    // the first pass finds the labels and decides each instruction's size,
+    // labels that aren't defined yet are assumed to need two bytes
+    let mut labels = HashMap::new();
+    let mut modes = Vec::with_capacity(statements.len());
+    let mut address = DEFAULT_ORIGIN;
+
+    for (line, statement) in &statements {
+        let error = |message: String| format!("Line {}: {}", line, message);
+
+        if let Some(label) = &statement.label {
+            if labels.insert(label.clone(), address).is_some() {
+                return Err(error(format!("label {} defined twice", label)));
+            }
+        }
+
+        match &statement.body {
+            Body::Empty => modes.push(None),
+            Body::Org(expression) => {
+                address = expression.eval(&labels).ok_or_else(|| {
+                    error(".org needs an address that is already known".to_string())
+                })?;
+                modes.push(None);
+            }
+            Body::Bytes(values) => {
+                address = address.wrapping_add(values.len() as u16);
+                modes.push(None);
+            }
+            Body::Words(values) => {
+                address = address.wrapping_add(values.len() as u16 * 2);
+                modes.push(None);
+            }
+            Body::Instruction(mnemonic, operand) => {
+                let (mode, opcode) = choose_mode(mnemonic, operand, &labels).map_err(error)?;
+                address = address.wrapping_add(opcodes::lookup(opcode).size());
+                modes.push(Some((mode, opcode)));
+            }
+        }
+    }
+
+    // the second pass emits bytes with every label known
+    let mut sections: Vec<(u16, Vec<u8>)> = vec![(DEFAULT_ORIGIN, Vec::new())];
+
+    for ((line, statement), mode) in statements.iter().zip(modes) {
+        let error = |message: String| format!("Line {}: {}", line, message);
+        let eval = |expression: &Expression| {
+            expression
+                .eval(&labels)
+                .ok_or_else(|| error(format!("undefined label in {}", expression.text)))
+        };
+        let eval_byte = |expression: &Expression| {
+            let value = expression
+                .value(&labels)
+                .ok_or_else(|| error(format!("undefined label in {}", expression.text)))?;
+            if !(-0x80..=0xFF).contains(&value) {
+                return Err(error(format!("{} doesn't fit in a byte", expression.text)));
+            }
+            Ok(value as u8)
+        };
+
+        let (origin, bytes) = sections.last_mut().unwrap();
+        let address = origin.wrapping_add(bytes.len() as u16);
+
+        match &statement.body {
+            Body::Empty => {}
+            Body::Org(expression) => sections.push((eval(expression)?, Vec::new())),
+            Body::Bytes(values) => {
+                for value in values {
+                    bytes.push(eval_byte(value)?);
+                }
+            }
+            Body::Words(values) => {
+                for value in values {
+                    bytes.extend_from_slice(&eval(value)?.to_le_bytes());
+                }
+            }
+            Body::Instruction(_, operand) => {
+                let (mode, opcode) = mode.unwrap();
+                bytes.push(opcode);
+
+                let expression = match operand {
+                    Operand::Value(_, expression) => Some(expression),
+                    Operand::None | Operand::Accumulator => None,
+                };
+                let value = match expression {
+                    Some(expression) => eval(expression)?,
+                    None => 0,
+                };
+
+                match mode {
+                    NoneAddressing | Accumulator => {}
+                    Relative => {
+                        let offset = value as i32 - (address as i32 + 2);
+                        if !(-128..=127).contains(&offset) {
+                            return Err(error(format!("branch target {} bytes away", offset)));
+                        }
+                        bytes.push(offset as i8 as u8);
+                    }
+                    Absolute | AbsoluteX | AbsoluteY | Indirect => {
+                        bytes.extend_from_slice(&value.to_le_bytes())
+                    }
+                    _ => bytes.push(eval_byte(expression.unwrap())?),
+                }
+            }
+        }
+    }
+
+    // merge the sections into one image
+    let mut sections: Vec<_> = sections
+        .into_iter()
+        .filter(|(_, bytes)| !bytes.is_empty())
+        .collect();
+    sections.sort_by_key(|(origin, _)| *origin);
+    for pair in sections.windows(2) {
+        let ((first, bytes), (second, _)) = (&pair[0], &pair[1]);
+        if *first as usize + bytes.len() > *second as usize {
+            return Err(format!(
+                ".org sections at ${:04X} and ${:04X} overlap",
+                first, second
+            ));
+        }
+    }
+    let Some(origin) = sections.iter().map(|(origin, _)| *origin).min() else {
+        return Ok(Image {
+            origin: DEFAULT_ORIGIN,
+            bytes: Vec::new(),
+        });
+    };
+    let end = sections
+        .iter()
+        .map(|(origin, bytes)| *origin as usize + bytes.len())
+        .max()
+        .unwrap();
+    if end > 0x10000 {
+        return Err(format!("Image at ${:04X} runs past $FFFF", origin));
+    }
+    if (origin as usize) < 0x6000 && end > 0x2000 {
+        return Err(format!(
+            "Image at ${:04X}-${:04X} overlaps IO registers and the expansion area ($2000-$5FFF)",
+            origin,
+            end - 1
+        ));
+    }
+
+    let mut image = vec![0; end - origin as usize];
+    for (start, bytes) in &sections {
+        let start = (*start - origin) as usize;
+        image[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+
+    Ok(Image {
+        origin,
+        bytes: image,
+    })
+}
+
+/// Picks the addressing mode and opcode for an instruction, using zero page
+/// modes when the operand is known to fit in a byte
+fn choose_mode(
+    mnemonic: &str,
+    operand: &Operand,
+    labels: &HashMap<String, u16>,
+) -> Result<(AddressingMode, u8), String> {
+    let find = |mode: AddressingMode| find_opcode(mnemonic, mode).map(|opcode| (mode, opcode));
+
+    // branches only have one mode
+    if let Some(found) = find(Relative) {
+        return Ok(found);
+    }
+
+    let candidates: &[AddressingMode] = match operand {
+        Operand::None => &[NoneAddressing, Accumulator],
+        Operand::Accumulator => &[Accumulator],
+        Operand::Value(syntax, expression) => {
+            let zero_page = expression.eval(labels).is_some_and(|value| value <= 0xFF);
+            match (syntax, zero_page) {
+                (Syntax::Immediate, _) => &[Immediate],
+                (Syntax::Direct, true) => &[ZeroPage, Absolute],
+                (Syntax::Direct, false) => &[Absolute],
+                (Syntax::IndexedX, true) => &[ZeroPageX, AbsoluteX],
+                (Syntax::IndexedX, false) => &[AbsoluteX],
+                (Syntax::IndexedY, true) => &[ZeroPageY, AbsoluteY],
+                (Syntax::IndexedY, false) => &[AbsoluteY],
+                (Syntax::Indirect, _) => &[Indirect],
+                (Syntax::IndirectX, _) => &[IndirectX],
+                (Syntax::IndirectY, _) => &[IndirectY],
+            }
+        }
+    };
+
+    candidates
+        .iter()
+        .find_map(|mode| find(*mode))
+        .ok_or_else(|| format!("{} doesn't support this addressing mode", mnemonic))
+}
+
+/// Prefers official opcodes, so `SBC #` assembles to $E9 rather than $EB
+fn find_opcode(mnemonic: &str, mode: AddressingMode) -> Option<u8> {
+    let matches = |unofficial: bool| {
+        OPCODES.iter().position(|opcode| {
+            opcode.mnemonic == mnemonic && opcode.mode == mode && opcode.unofficial == unofficial
+        })
+    };
+    matches(false).or_else(|| matches(true)).map(|i| i as u8)
+}
+
+struct Statement {
+    label: Option<String>,
+    body: Body,
+}
+
+enum Body {
+    Empty,
+    Org(Expression),
+    Bytes(Vec<Expression>),
+    Words(Vec<Expression>),
+    Instruction(String, Operand),
+}
+
+enum Operand {
+    None,
+    Accumulator,
+    Value(Syntax, Expression),
+}
+
+enum Syntax {
+    Immediate,
+    Direct,
+    IndexedX,
+    IndexedY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+}
+
+/// Terms added together, then optionally reduced to the low or high byte
+struct Expression {
+    text: String,
+    terms: Vec<(i32, Term)>,
+    byte: Option<Byte>,
+}
+
+enum Term {
+    Number(u16),
+    Label(String),
+}
+
+enum Byte {
+    Low,
+    High,
+}
+
+impl Expression {
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (byte, rest) = match text.chars().next() {
+            Some('<') => (Some(Byte::Low), &text[1..]),
+            Some('>') => (Some(Byte::High), &text[1..]),
+            _ => (None, text),
+        };
+
+        let mut terms = Vec::new();
+        let mut sign = 1;
+        let mut term = String::new();
+
+        let mut push = |sign: i32, term: &str| -> Result<(), String> {
+            let term = term.trim();
+            if term.is_empty() {
+                return Err(format!("missing value in {}", text));
+            }
+            terms.push((sign, parse_term(term)?));
+            Ok(())
+        };
+
+        let mut chars = rest.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                // characters can contain + and -
+                '\'' => {
+                    term.push(c);
+                    term.extend(chars.next());
+                    term.extend(chars.next());
+                }
+                // a sign before a value negates it
+                '-' if term.trim().is_empty() => sign = -sign,
+                '+' | '-' if !term.trim().is_empty() => {
+                    push(sign, &term)?;
+                    term.clear();
+                    sign = if c == '+' { 1 } else { -1 };
+                }
+                _ => term.push(c),
+            }
+        }
+        push(sign, &term)?;
+
+        Ok(Expression {
+            text: text.to_string(),
+            terms,
+            byte,
+        })
+    }
+
+    /// None if a label isn't known
+    fn eval(&self, labels: &HashMap<String, u16>) -> Option<u16> {
+        self.value(labels).map(|value| value as u16)
+    }
+
+    /// Like [`Expression::eval`], but keeps the sign
+    fn value(&self, labels: &HashMap<String, u16>) -> Option<i32> {
+        let mut value: i32 = 0;
+        for (sign, term) in &self.terms {
+            let term = match term {
+                Term::Number(number) => *number,
+                Term::Label(label) => *labels.get(label)?,
+            };
+            value += sign * term as i32;
+        }
+
+        Some(match self.byte {
+            Some(Byte::Low) => (value as u16 & 0xFF) as i32,
+            Some(Byte::High) => (value as u16 >> 8) as i32,
+            None => value,
+        })
+    }
+}
+
+fn parse_term(term: &str) -> Result<Term, String> {
+    let invalid = || format!("invalid number {}", term);
+
+    let number = if let Some(hex) = term.strip_prefix('$') {
+        u16::from_str_radix(hex, 16).map_err(|_| invalid())?
+    } else if let Some(binary) = term.strip_prefix('%') {
+        u16::from_str_radix(binary, 2).map_err(|_| invalid())?
+    } else if term.len() == 3 && term.starts_with('\'') && term.ends_with('\'') {
+        term.as_bytes()[1] as u16
+    } else if term.starts_with(|c: char| c.is_ascii_digit()) {
+        term.parse().map_err(|_| invalid())?
+    } else if is_identifier(term) {
+        return Ok(Term::Label(term.to_string()));
+    } else {
+        return Err(invalid());
+    };
+
+    Ok(Term::Number(number))
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits on commas outside of character literals
+fn split_list(text: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut in_char = false;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '\'' => in_char = !in_char,
+            ',' if !in_char => {
+                items.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&text[start..]);
+    items
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let text = text.trim();
+    let upper = text.to_ascii_uppercase();
+
+    if text.is_empty() {
+        return Ok(Operand::None);
+    }
+    if upper == "A" {
+        return Ok(Operand::Accumulator);
+    }
+    if let Some(value) = text.strip_prefix('#') {
+        return Ok(Operand::Value(Syntax::Immediate, Expression::parse(value)?));
+    }
+
+    let compact: String = upper.chars().filter(|c| !c.is_whitespace()).collect();
+    let inner = |start: usize, end_len: usize| {
+        // same offsets in the original text, which keeps label case
+        let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        Expression::parse(&text[start..text.len() - end_len])
+    };
+
+    if compact.starts_with('(') {
+        if compact.ends_with(",X)") {
+            Ok(Operand::Value(Syntax::IndirectX, inner(1, 3)?))
+        } else if compact.ends_with("),Y") {
+            Ok(Operand::Value(Syntax::IndirectY, inner(1, 3)?))
+        } else if compact.ends_with(')') {
+            Ok(Operand::Value(Syntax::Indirect, inner(1, 1)?))
+        } else {
+            Err(format!("invalid operand {}", text))
+        }
+    } else if compact.ends_with(",X") {
+        Ok(Operand::Value(Syntax::IndexedX, inner(0, 2)?))
+    } else if compact.ends_with(",Y") {
+        Ok(Operand::Value(Syntax::IndexedY, inner(0, 2)?))
+    } else {
+        Ok(Operand::Value(Syntax::Direct, Expression::parse(text)?))
+    }
+}
+
+/// Strips a comment, ignoring semicolons in character literals
+fn strip_comment(line: &str) -> &str {
+    let mut in_char = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\'' => in_char = !in_char,
+            ';' if !in_char => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse(source: &str) -> Result<Vec<(usize, Statement)>, String> {
+    let mut statements = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let error = |message: String| format!("Line {}: {}", line_number, message);
+
+        let mut rest = strip_comment(line).trim();
+
+        let mut label = None;
+        if let Some((name, after)) = rest.split_once(':') {
+            if is_identifier(name.trim()) {
+                label = Some(name.trim().to_string());
+                rest = after.trim();
+            }
+        }
+
+        let (word, operand) = match rest.split_once(char::is_whitespace) {
+            Some((word, operand)) => (word, operand.trim()),
+            None => (rest, ""),
+        };
+
+        let list = |operand: &str| {
+            split_list(operand)
+                .into_iter()
+                .map(Expression::parse)
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let body = match word.to_ascii_lowercase().as_str() {
+            "" => Body::Empty,
+            ".org" => Body::Org(Expression::parse(operand).map_err(error)?),
+            ".byte" | ".db" => Body::Bytes(list(operand).map_err(error)?),
+            ".word" | ".dw" => Body::Words(list(operand).map_err(error)?),
+            directive if directive.starts_with('.') => {
+                return Err(error(format!("unknown directive {}", word)))
+            }
+            _ => {
+                let mnemonic = word.to_ascii_uppercase();
+                if !OPCODES.iter().any(|opcode| opcode.mnemonic == mnemonic) {
+                    return Err(error(format!("unknown instruction {}", word)));
+                }
+                Body::Instruction(mnemonic, parse_operand(operand).map_err(error)?)
+            }
+        };
+
+        statements.push((line_number, Statement { label, body }));
+    }
+
+    Ok(statements)
+}
@@ -0,0 +1,70 @@
+//! Battery backed PRG-RAM, persisted to a `.sav` file next to the ROM.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+pub struct BatterySave {
+    path: PathBuf,
+    /// PRG-RAM as of the last load or flush, used to skip writes when the
+    /// game hasn't touched it
+    flushed: Vec<u8>,
+}
+
+impl BatterySave {
+    pub fn new(rom_path: &Path) -> Self {
+        BatterySave {
+            path: rom_path.with_extension("sav"),
+            flushed: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Fills PRG-RAM from the `.sav` file, leaving it untouched if there is
+    /// no save yet. Files of a different size are copied as far as they fit.
+    pub fn load(&mut self, prg_ram: &mut [u8]) -> Result<(), String> {
+        match fs::read(&self.path) {
+            Ok(data) => {
+                let len = data.len().min(prg_ram.len());
+                prg_ram[..len].copy_from_slice(&data[..len]);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("{}: {}", self.path.display(), e)),
+        }
+
+        self.flushed = prg_ram.to_vec();
+        Ok(())
+    }
+
+    /// Writes PRG-RAM to the `.sav` file if it changed since the last flush,
+    /// returns whether anything was written
+    pub fn flush(&mut self, prg_ram: &[u8]) -> Result<bool, String> {
+        if self.flushed == prg_ram {
+            return Ok(false);
+        }
+
+        write_atomic(&self.path, prg_ram).map_err(|e| format!("{}: {}", self.path.display(), e))?;
+        self.flushed = prg_ram.to_vec();
+
+        Ok(true)
+    }
+}
+
+/// Writes to a temporary file and renames it over `path`, so a crash mid
+/// write leaves the old save intact instead of a truncated one
+pub(super) fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+
+    fs::rename(&tmp_path, path)
+}
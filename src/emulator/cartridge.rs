@@ -0,0 +1,99 @@
+//! iNES and NES 2.0 ROM images.
+
+use std::{fs, path::Path};
+
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_ROM_BANK: usize = 16 * 1024;
+const CHR_ROM_BANK: usize = 8 * 1024;
+
+/// Size of the PRG-RAM window at $6000-$7FFF
+pub const PRG_RAM_WINDOW: usize = 8 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
+
+pub struct Cartridge {
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    pub mapper: u16,
+    pub mirroring: Mirroring,
+    /// PRG-RAM is battery backed and should be kept between runs
+    pub battery: bool,
+    /// PRG-RAM size in bytes, never smaller than the $6000-$7FFF window
+    pub prg_ram_size: usize,
+}
+
+impl Cartridge {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Cartridge::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        if data.len() < HEADER_SIZE || data[0..4] != *b"NES\x1A" {
+            return Err("Not an iNES ROM".to_string());
+        }
+
+        let flags6 = data[6];
+        let flags7 = data[7];
+        let nes2 = flags7 & 0b0000_1100 == 0b0000_1000;
+
+        let mut prg_banks = data[4] as usize;
+        let mut chr_banks = data[5] as usize;
+        let mut mapper = ((flags7 & 0xF0) | (flags6 >> 4)) as u16;
+
+        let prg_ram_size = if nes2 {
+            // NES 2.0 keeps the high bits of the sizes and mapper in bytes 8 and 9
+            mapper |= ((data[8] & 0x0F) as u16) << 8;
+            prg_banks |= ((data[9] & 0x0F) as usize) << 8;
+            chr_banks |= ((data[9] & 0xF0) as usize) << 4;
+
+            // sizes are 64 << shift, volatile RAM in the low nibble and
+            // battery backed RAM in the high nibble
+            let shift_size = |shift: u8| if shift == 0 { 0 } else { 64 << shift };
+            shift_size(data[10] & 0x0F) + shift_size(data[10] >> 4)
+        } else {
+            // size in 8 KiB units, 0 means 8 KiB for compatibility
+            data[8] as usize * PRG_RAM_WINDOW
+        };
+
+        let mirroring = if flags6 & 0b1000 != 0 {
+            Mirroring::FourScreen
+        } else if flags6 & 0b0001 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        let trainer = if flags6 & 0b0100 != 0 {
+            TRAINER_SIZE
+        } else {
+            0
+        };
+        let prg_start = HEADER_SIZE + trainer;
+        let chr_start = prg_start + prg_banks * PRG_ROM_BANK;
+        let chr_end = chr_start + chr_banks * CHR_ROM_BANK;
+
+        if data.len() < chr_end {
+            return Err(format!(
+                "ROM is {} bytes, header expects at least {}",
+                data.len(),
+                chr_end
+            ));
+        }
+
+        Ok(Cartridge {
+            prg_rom: data[prg_start..chr_start].to_vec(),
+            chr_rom: data[chr_start..chr_end].to_vec(),
+            mapper,
+            mirroring,
+            battery: flags6 & 0b0010 != 0,
+            prg_ram_size: prg_ram_size.max(PRG_RAM_WINDOW),
+        })
+    }
+}
@@ -0,0 +1,264 @@
+//! Game Genie and Pro Action Replay cheats. Game Genie codes substitute
+//! bytes the CPU reads from ROM, PAR codes force a RAM byte every frame.
+//!
+//! Cheats are kept in a `.cht` file next to the ROM, one per line:
+//!
+//! ```text
+//! # comment
+//! SXIOPO Infinite lives
+//! -0075:09 Disabled, starts with a minus
+//! ```
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{battery::write_atomic, Emulator};
+
+/// Game Genie letters, in the order of the values they stand for
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+/// Replaces the byte read from ROM at `address`. With a compare byte the
+/// substitution only happens while the ROM holds that byte, so codes for one
+/// bank don't corrupt others mapped at the same address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RomPatch {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+impl RomPatch {
+    /// `value` is what the ROM holds at the patched address
+    #[inline]
+    pub fn applies(&self, value: u8) -> bool {
+        self.compare.is_none_or(|compare| compare == value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Code {
+    Rom(RomPatch),
+    /// Written to RAM at the start of every frame
+    Ram {
+        address: u16,
+        value: u8,
+    },
+}
+
+impl Code {
+    /// Parses a 6 or 8 letter Game Genie code, or a raw code in the
+    /// `AAAA:VV` or `AAAA?CC:VV` form. Raw codes at $8000 and up patch ROM,
+    /// lower ones force RAM.
+    pub fn parse(text: &str) -> Result<Code, String> {
+        let text = text.trim();
+        if text.contains(':') {
+            parse_raw(text)
+        } else {
+            decode_game_genie(text).map(Code::Rom)
+        }
+    }
+}
+
+/// Decodes a 6 or 8 letter Game Genie code
+pub fn decode_game_genie(code: &str) -> Result<RomPatch, String> {
+    let n = code
+        .chars()
+        .map(|c| {
+            GAME_GENIE_LETTERS
+                .find(c.to_ascii_uppercase())
+                .map(|n| n as u16)
+                .ok_or_else(|| format!("Invalid Game Genie letter '{}' in {}", c, code))
+        })
+        .collect::<Result<Vec<u16>, String>>()?;
+
+    if n.len() != 6 && n.len() != 8 {
+        return Err(format!("Game Genie codes have 6 or 8 letters: {}", code));
+    }
+
+    let address = 0x8000
+        | ((n[3] & 7) << 12)
+        | ((n[4] & 8) << 8)
+        | ((n[5] & 7) << 8)
+        | ((n[1] & 8) << 4)
+        | ((n[2] & 7) << 4)
+        | (n[3] & 8)
+        | (n[4] & 7);
+
+    // the low bit of the value's high nibble moves to the last letter in
+    // 8 letter codes, making room for the compare byte
+    let last = if n.len() == 8 { n[7] } else { n[5] };
+    let value = ((n[0] & 8) << 4) | ((n[1] & 7) << 4) | (last & 8) | (n[0] & 7);
+
+    let compare = if n.len() == 8 {
+        Some(((n[6] & 8) << 4) | ((n[7] & 7) << 4) | (n[5] & 8) | (n[6] & 7))
+    } else {
+        None
+    };
+
+    Ok(RomPatch {
+        address,
+        value: value as u8,
+        compare: compare.map(|compare| compare as u8),
+    })
+}
+
+fn parse_raw(text: &str) -> Result<Code, String> {
+    let hex = |digits: &str| {
+        u16::from_str_radix(digits.trim().trim_start_matches('$'), 16)
+            .map_err(|_| format!("Invalid hex number '{}' in {}", digits, text))
+    };
+    let byte = |digits: &str| {
+        hex(digits).and_then(|value| {
+            u8::try_from(value).map_err(|_| format!("Expected a byte, got {} in {}", digits, text))
+        })
+    };
+
+    let (location, value) = text.split_once(':').unwrap();
+    let value = byte(value)?;
+    let (address, compare) = match location.split_once('?') {
+        Some((address, compare)) => (hex(address)?, Some(byte(compare)?)),
+        None => (hex(location)?, None),
+    };
+
+    match address {
+        0x8000..=0xFFFF => Ok(Code::Rom(RomPatch {
+            address,
+            value,
+            compare,
+        })),
+        _ if compare.is_some() => Err(format!("Compare bytes only work on ROM: {}", text)),
+        0x2000..=0x5FFF => Err(format!("IO registers can't be forced: {}", text)),
+        _ => Ok(Code::Ram { address, value }),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    /// As entered, so it can be saved and shown back unchanged
+    pub code: String,
+    pub decoded: Code,
+    pub description: String,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    pub fn new(code: &str, description: &str) -> Result<Cheat, String> {
+        Ok(Cheat {
+            decoded: Code::parse(code)?,
+            code: code.trim().to_uppercase(),
+            description: description.trim().to_string(),
+            enabled: true,
+        })
+    }
+}
+
+/// Cheats are stored as `<rom>.cht`
+pub fn cheat_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("cht")
+}
+
+/// Reads a cheat file, a missing file has no cheats
+pub fn load_cheats(path: &Path) -> Result<Vec<Cheat>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+
+    let mut cheats = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (enabled, line) = match line.strip_prefix('-') {
+            Some(line) => (false, line),
+            None => (true, line),
+        };
+        let (code, description) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+        let mut cheat = Cheat::new(code, description)
+            .map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?;
+        cheat.enabled = enabled;
+        cheats.push(cheat);
+    }
+
+    Ok(cheats)
+}
+
+pub fn save_cheats(path: &Path, cheats: &[Cheat]) -> Result<(), String> {
+    let mut text = String::new();
+    for cheat in cheats {
+        if !cheat.enabled {
+            text.push('-');
+        }
+        text.push_str(&cheat.code);
+        if !cheat.description.is_empty() {
+            text.push(' ');
+            text.push_str(&cheat.description);
+        }
+        text.push('\n');
+    }
+
+    write_atomic(path, text.as_bytes()).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+impl Emulator {
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// Replaces all cheats, e.g. with the ones from [`load_cheats`]
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        self.cheats = cheats;
+        self.sync_cheats();
+    }
+
+    /// Adds an enabled cheat and returns its index
+    pub fn add_cheat(&mut self, code: &str, description: &str) -> Result<usize, String> {
+        self.cheats.push(Cheat::new(code, description)?);
+        self.sync_cheats();
+        Ok(self.cheats.len() - 1)
+    }
+
+    pub fn remove_cheat(&mut self, index: usize) {
+        if index < self.cheats.len() {
+            self.cheats.remove(index);
+            self.sync_cheats();
+        }
+    }
+
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(cheat) = self.cheats.get_mut(index) {
+            cheat.enabled = enabled;
+            self.sync_cheats();
+        }
+    }
+
+    /// Hands the enabled ROM patches to the bus, which checks them on every
+    /// ROM read
+    fn sync_cheats(&mut self) {
+        self.memory.borrow_mut().rom_patches = self
+            .cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.decoded {
+                Code::Rom(patch) => Some(patch),
+                Code::Ram { .. } => None,
+            })
+            .collect();
+    }
+
+    /// Forces the enabled RAM codes, called at the start of every frame
+    pub(super) fn apply_ram_cheats(&mut self) {
+        let mut memory = self.memory.borrow_mut();
+        for cheat in self.cheats.iter().filter(|cheat| cheat.enabled) {
+            if let Code::Ram { address, value } = cheat.decoded {
+                memory[address] = value;
+            }
+        }
+    }
+}
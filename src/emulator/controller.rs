@@ -0,0 +1,92 @@
+use super::state::{Savestate, StateReader, StateWriter};
+
+/// Standard controller, read a button at a time through $4016/$4017
+#[derive(Clone, Copy, Default)]
+pub struct Controller {
+    /// Currently held buttons, see [`Button`]
+    pub buttons: u8,
+    /// Buttons latched by the last strobe, shifted out by reads
+    shift: u8,
+    strobe: bool,
+}
+
+impl Controller {
+    pub fn new() -> Self {
+        Controller::default()
+    }
+
+    /// Clears the latched buttons and the strobe, held buttons stay held
+    pub fn power_on(&mut self) {
+        self.shift = 0;
+        self.strobe = false;
+    }
+
+    /// $4016 write, bit 0 is the strobe
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    /// $4016/$4017 read, returns A, B, Select, Start, Up, Down, Left, Right
+    /// then 1s once all buttons are shifted out
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons & Button::A;
+        }
+
+        let value = self.shift & 1;
+        self.shift = (self.shift >> 1) | 0x80;
+        value
+    }
+
+    /// What [`Controller::read`] would return, without shifting
+    pub fn peek(&self) -> u8 {
+        if self.strobe {
+            self.buttons & Button::A
+        } else {
+            self.shift & 1
+        }
+    }
+}
+
+impl Savestate for [Controller; 2] {
+    const TAG: [u8; 4] = *b"CTRL";
+    const VERSION: u16 = 1;
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        for controller in self {
+            writer.write_u8(controller.buttons);
+            writer.write_u8(controller.shift);
+            writer.write_bool(controller.strobe);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader, _version: u16) -> Result<(), String> {
+        let mut controllers = [Controller::new(); 2];
+        for controller in &mut controllers {
+            controller.buttons = reader.read_u8()?;
+            controller.shift = reader.read_u8()?;
+            controller.strobe = reader.read_bool()?;
+        }
+
+        *self = controllers;
+        Ok(())
+    }
+}
+
+// same trick as the CPU flags, bits of `Controller::buttons`
+#[allow(non_upper_case_globals)]
+#[allow(dead_code)]
+#[allow(non_snake_case)]
+pub mod Button {
+    pub const A: u8 = 0b0000_0001;
+    pub const B: u8 = 0b0000_0010;
+    pub const Select: u8 = 0b0000_0100;
+    pub const Start: u8 = 0b0000_1000;
+    pub const Up: u8 = 0b0001_0000;
+    pub const Down: u8 = 0b0010_0000;
+    pub const Left: u8 = 0b0100_0000;
+    pub const Right: u8 = 0b1000_0000;
+}
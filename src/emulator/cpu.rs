@@ -1,6 +1,9 @@
 use std::{cell::RefCell, rc::Rc};
 
-use super::ram::RAM;
+use super::{
+    ram::RAM,
+    state::{Savestate, StateReader, StateWriter},
+};
 
 pub struct CPU {
     pub pc: u16,
@@ -9,8 +12,11 @@ pub struct CPU {
     pub idx_x: u8,
     pub idx_y: u8,
     pub status: u8,
+    /// CPU cycles run since power on
+    pub cycles: u64,
     memory: Rc<RefCell<RAM>>,
     sleep_cycles: u8, // counter for sleep cycles
+    nmi_pending: bool,
 }
 
 #[allow(dead_code)]
@@ -23,24 +29,79 @@ impl CPU {
             idx_x: 0,
             idx_y: 0,
             status: 0,
+            cycles: 0,
             memory,
             sleep_cycles: 0,
+            nmi_pending: false,
         }
     }
 
-    pub fn reset(&mut self) {
-        // reset registers
-        self.sp = 0xFD; // default value
+    /// Registers as the reset sequence leaves them after power on
+    pub fn power_on(&mut self) {
         self.acc = 0;
         self.idx_x = 0;
         self.idx_y = 0;
-        self.status = 0;
+        self.status = Flag::InterruptDisable | Flag::Break | Flag::Unused; // $34
+        // the reset sequence pulls SP down from 0 by 3 without writing
+        self.sp = 0xFD;
+        self.restart();
+    }
+
+    /// The reset button, only SP and the I flag change besides PC
+    pub fn soft_reset(&mut self) {
+        self.sp = self.sp.wrapping_sub(3);
+        self.set_flag(Flag::InterruptDisable);
+        self.restart();
+    }
 
+    fn restart(&mut self) {
         // reset program counter to address stored at 0xFFFC
         self.pc = self.memory.borrow().read_u16(0xFFFC);
 
         // reset sleep cycles
         self.sleep_cycles = 0;
+        self.nmi_pending = false;
+    }
+
+    /// Whether the current instruction still has cycles left to run
+    pub fn mid_instruction(&self) -> bool {
+        self.sleep_cycles > 0
+    }
+
+    /// Whether an NMI will be taken instead of the next instruction
+    pub fn nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    /// Requests an NMI, taken at the start of the next instruction
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    fn read(&self, addr: u16) -> u8 {
+        self.memory.borrow_mut().read(addr)
+    }
+
+    fn write(&self, addr: u16, value: u8) {
+        self.memory.borrow_mut().write(addr, value);
+    }
+
+    fn push(&mut self, value: u8) {
+        self.write(0x0100 + self.sp as u16, value);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    /// Pushes PC and status and jumps through the vector at `vector`
+    fn interrupt(&mut self, vector: u16) {
+        // 7 cycles
+        self.sleep_cycles = 6;
+
+        self.push((self.pc >> 8) as u8);
+        self.push(self.pc as u8);
+        self.push((self.status & !Flag::Break) | Flag::Unused);
+
+        self.set_flag(Flag::InterruptDisable);
+        self.pc = self.memory.borrow().read_u16(vector);
     }
 
     pub fn load(&mut self, program: Vec<u8>) {
@@ -82,6 +143,8 @@ impl CPU {
     }
 
     pub fn cycle(&mut self) {
+        self.cycles += 1;
+
         //print!("PC: {:04X} | ", self.pc);
         // sleep for cycles until sleep_cycles is 0
         if self.sleep_cycles > 0 {
@@ -91,8 +154,14 @@ impl CPU {
         }
         //println!();
 
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.interrupt(0xFFFA);
+            return;
+        }
+
         // Fetch
-        let opcode = self.memory.borrow_mut()[self.pc as u16];
+        let opcode = self.read(self.pc);
         let mut dont_increment_pc = false;
 
         // Decode
@@ -104,7 +173,7 @@ impl CPU {
                 self.sleep_cycles = 1;
 
                 let addr = self.get_operand_addr(AddressingMode::Immediate);
-                self.acc = self.memory.borrow()[addr];
+                self.acc = self.read(addr);
 
                 // skip next byte
                 self.pc += 1;
@@ -119,7 +188,7 @@ impl CPU {
 
                 // set accumulator to value
                 let addr = self.get_operand_addr(AddressingMode::ZeroPage);
-                self.acc = self.memory.borrow()[addr];
+                self.acc = self.read(addr);
 
                 // skip next byte
                 self.pc += 1;
@@ -134,7 +203,7 @@ impl CPU {
 
                 // set accumulator to value
                 let addr = self.get_operand_addr(AddressingMode::ZeroPageX);
-                self.acc = self.memory.borrow()[addr];
+                self.acc = self.read(addr);
 
                 // skip next byte
                 self.pc += 1;
@@ -149,7 +218,7 @@ impl CPU {
 
                 // set accumulator to value
                 let addr = self.get_operand_addr(AddressingMode::Absolute);
-                self.acc = self.memory.borrow()[addr];
+                self.acc = self.read(addr);
 
                 // skip next 2 bytes
                 self.pc += 2;
@@ -164,7 +233,7 @@ impl CPU {
 
                 // set accumulator to value
                 let addr = self.get_operand_addr(AddressingMode::AbsoluteX);
-                self.acc = self.memory.borrow()[addr];
+                self.acc = self.read(addr);
 
                 // if page crossed, add 1 cycle
                 let base_addr = self.get_operand_addr(AddressingMode::Absolute);
@@ -185,7 +254,7 @@ impl CPU {
 
                 // set accumulator to value
                 let addr = self.get_operand_addr(AddressingMode::AbsoluteY);
-                self.acc = self.memory.borrow()[addr];
+                self.acc = self.read(addr);
 
                 // if page crossed, add 1 cycle
                 let base_addr = self.get_operand_addr(AddressingMode::Absolute);
@@ -206,7 +275,7 @@ impl CPU {
 
                 // set accumulator to value
                 let addr = self.get_operand_addr(AddressingMode::IndirectX);
-                self.acc = self.memory.borrow()[addr];
+                self.acc = self.read(addr);
 
                 // skip next byte
                 self.pc += 1;
@@ -221,7 +290,7 @@ impl CPU {
 
                 // set accumulator to value
                 let addr = self.get_operand_addr(AddressingMode::IndirectY);
-                self.acc = self.memory.borrow()[addr];
+                self.acc = self.read(addr);
 
                 // if page crossed, add 1 cycle
                 let base_addr = self.get_operand_addr(AddressingMode::Indirect);
@@ -270,7 +339,7 @@ impl CPU {
 
                 // set memory to accumulator
                 let addr = self.get_operand_addr(AddressingMode::ZeroPage);
-                self.memory.borrow_mut()[addr] = self.acc;
+                self.write(addr, self.acc);
 
                 // skip next byte
                 self.pc += 1;
@@ -282,7 +351,7 @@ impl CPU {
 
                 // set memory to accumulator
                 let addr = self.get_operand_addr(AddressingMode::ZeroPageX);
-                self.memory.borrow_mut()[addr] = self.acc;
+                self.write(addr, self.acc);
 
                 // skip next byte
                 self.pc += 1;
@@ -294,7 +363,7 @@ impl CPU {
 
                 // set memory to accumulator
                 let addr = self.get_operand_addr(AddressingMode::Absolute);
-                self.memory.borrow_mut()[addr] = self.acc;
+                self.write(addr, self.acc);
 
                 // skip next 2 bytes
                 self.pc += 2;
@@ -306,7 +375,7 @@ impl CPU {
 
                 // set memory to accumulator
                 let addr = self.get_operand_addr(AddressingMode::AbsoluteX);
-                self.memory.borrow_mut()[addr] = self.acc;
+                self.write(addr, self.acc);
 
                 // skip next 2 bytes
                 self.pc += 2;
@@ -318,7 +387,7 @@ impl CPU {
 
                 // set memory to accumulator
                 let addr = self.get_operand_addr(AddressingMode::AbsoluteY);
-                self.memory.borrow_mut()[addr] = self.acc;
+                self.write(addr, self.acc);
 
                 // skip next 2 bytes
                 self.pc += 2;
@@ -330,7 +399,7 @@ impl CPU {
 
                 // set memory to accumulator
                 let addr = self.get_operand_addr(AddressingMode::IndirectX);
-                self.memory.borrow_mut()[addr] = self.acc;
+                self.write(addr, self.acc);
 
                 // skip next byte
                 self.pc += 1;
@@ -342,7 +411,7 @@ impl CPU {
 
                 // set memory to accumulator
                 let addr = self.get_operand_addr(AddressingMode::IndirectY);
-                self.memory.borrow_mut()[addr] = self.acc;
+                self.write(addr, self.acc);
 
                 // skip next byte
                 self.pc += 1;
@@ -437,6 +506,54 @@ impl CPU {
     }
 }
 
+impl Savestate for CPU {
+    const TAG: [u8; 4] = *b"CPU ";
+    const VERSION: u16 = 3;
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.pc);
+        writer.write_u8(self.sp);
+        writer.write_u8(self.acc);
+        writer.write_u8(self.idx_x);
+        writer.write_u8(self.idx_y);
+        writer.write_u8(self.status);
+        writer.write_u8(self.sleep_cycles);
+        writer.write_bool(self.nmi_pending);
+        writer.write_u64(self.cycles);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader, version: u16) -> Result<(), String> {
+        // read everything before touching the registers so a short chunk
+        // doesn't leave the CPU half loaded
+        let pc = reader.read_u16()?;
+        let sp = reader.read_u8()?;
+        let acc = reader.read_u8()?;
+        let idx_x = reader.read_u8()?;
+        let idx_y = reader.read_u8()?;
+        let status = reader.read_u8()?;
+        let sleep_cycles = reader.read_u8()?;
+        // version 1 predates NMIs
+        let nmi_pending = if version >= 2 {
+            reader.read_bool()?
+        } else {
+            false
+        };
+        let cycles = if version >= 3 { reader.read_u64()? } else { 0 };
+
+        self.pc = pc;
+        self.sp = sp;
+        self.acc = acc;
+        self.idx_x = idx_x;
+        self.idx_y = idx_y;
+        self.status = status;
+        self.sleep_cycles = sleep_cycles;
+        self.nmi_pending = nmi_pending;
+        self.cycles = cycles;
+
+        Ok(())
+    }
+}
+
 // stupid enum implementation, need it because rust doesn't support bitflags on enums
 #[allow(non_upper_case_globals)]
 #[allow(dead_code)]
@@ -453,7 +570,8 @@ mod Flag {
 }
 
 #[allow(dead_code)]
-enum AddressingMode {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressingMode {
     Immediate,
     ZeroPage,
     ZeroPageX,
@@ -464,5 +582,7 @@ enum AddressingMode {
     Indirect,
     IndirectX,
     IndirectY,
+    Accumulator,
+    Relative,
     NoneAddressing,
 }
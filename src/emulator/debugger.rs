@@ -0,0 +1,518 @@
+//! Breakpoints, watchpoints and stepping.
+//!
+//! Everything is checked from [`Emulator::cycle`], which only tests a single
+//! flag while no breakpoint is enabled and nothing is stepping. Breaking sets
+//! [`Debugger::stopped`], after which `cycle` does nothing until
+//! [`Emulator::resume`] or one of the step functions is called.
+
+use std::fmt;
+
+use super::{expr::Expression, Emulator};
+
+/// JSR, RTS and RTI, used by step over, step out and the call stack
+const JSR: u8 = 0x20;
+const RTS: u8 = 0x60;
+const RTI: u8 = 0x40;
+
+/// Deepest call stack tracked, older frames are dropped. Some games never
+/// return from subroutines and reset the stack pointer instead.
+const CALL_STACK_LIMIT: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Space {
+    Cpu,
+    /// PPU address space, as accessed by the CPU through $2007
+    Ppu,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Any,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Before the instruction at this address runs
+    Exec(u16),
+    /// After an instruction accesses memory in `start..=end`
+    Watch {
+        space: Space,
+        start: u16,
+        end: u16,
+        access: Access,
+    },
+    /// Before any instruction with this opcode runs
+    Opcode(u8),
+    /// Before an NMI is taken
+    Nmi,
+    /// Before an IRQ is taken. Nothing raises IRQs yet, so this never fires.
+    Irq,
+    /// When the PPU starts this scanline
+    Scanline(u16),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Breakpoint::Exec(address) => write!(f, "Exec ${:04X}", address),
+            Breakpoint::Watch {
+                space,
+                start,
+                end,
+                access,
+            } => {
+                let access = match access {
+                    Access::Read => "Read",
+                    Access::Write => "Write",
+                    Access::Any => "Access",
+                };
+                let space = match space {
+                    Space::Cpu => "",
+                    Space::Ppu => " PPU",
+                };
+
+                if start == end {
+                    write!(f, "{}{} ${:04X}", access, space, start)
+                } else {
+                    write!(f, "{}{} ${:04X}-${:04X}", access, space, start, end)
+                }
+            }
+            Breakpoint::Opcode(opcode) => write!(f, "Opcode ${:02X}", opcode),
+            Breakpoint::Nmi => write!(f, "NMI"),
+            Breakpoint::Irq => write!(f, "IRQ"),
+            Breakpoint::Scanline(scanline) => write!(f, "Scanline {}", scanline),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+/// Watched ranges of one address space, checked on every bus access of the
+/// device that owns it
+#[derive(Default)]
+pub struct Watchpoints {
+    /// (breakpoint id, start, end, access)
+    ranges: Vec<(usize, u16, u16, Access)>,
+    /// The first access that matched since the debugger last looked
+    pub hit: Option<(usize, WatchHit)>,
+}
+
+impl Watchpoints {
+    #[inline]
+    pub fn check(&mut self, address: u16, value: u8, write: bool) {
+        if self.ranges.is_empty() || self.hit.is_some() {
+            return;
+        }
+
+        for &(id, start, end, access) in &self.ranges {
+            let kind = match access {
+                Access::Read => !write,
+                Access::Write => write,
+                Access::Any => true,
+            };
+
+            if kind && (start..=end).contains(&address) {
+                let hit = WatchHit {
+                    address,
+                    value,
+                    write,
+                };
+                self.hit = Some((id, hit));
+                return;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// Paused from outside, see [`Emulator::pause`]
+    Pause,
+    Breakpoint(usize),
+    Watchpoint(usize, WatchHit),
+    /// A step or run to cursor finished
+    Step,
+}
+
+pub struct Entry {
+    pub id: usize,
+    pub breakpoint: Breakpoint,
+    pub enabled: bool,
+    /// Times the breakpoint was hit, whether the condition held or not
+    pub hits: u64,
+    /// Only stop when this holds
+    pub condition: Option<Expression>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallFrame {
+    /// Address of the JSR, or of the instruction an interrupt came before
+    pub caller: u16,
+    /// Start of the subroutine or interrupt handler
+    pub target: u16,
+    pub interrupt: bool,
+}
+
+/// What the CPU did at the last instruction boundary
+#[derive(Clone, Copy, Default)]
+enum Started {
+    #[default]
+    Unknown,
+    Instruction(u8),
+    Nmi,
+}
+
+#[derive(Clone, Copy)]
+enum Step {
+    Into,
+    /// Until the instruction after a JSR runs with the stack back where it was
+    Over {
+        pc: u16,
+        sp: u8,
+    },
+    /// Until an RTS or RTI pops above this stack pointer
+    Out {
+        sp: u8,
+    },
+    RunTo(u16),
+    /// Until the PPU starts the frame after this one
+    Frame(u64),
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    entries: Vec<Entry>,
+    next_id: usize,
+    step: Option<Step>,
+    /// Why execution is stopped, None while running
+    pub stopped: Option<Stop>,
+    /// The next instruction runs without checking breakpoints, so execution
+    /// can continue from a breakpoint
+    resuming: bool,
+    /// What started at the last instruction boundary, and where
+    last: Started,
+    last_pc: u16,
+    track_calls: bool,
+    call_stack: Vec<CallFrame>,
+    /// Anything to check at all
+    active: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    pub fn breakpoints(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn stop(&mut self, stop: Stop) {
+        self.stopped = Some(stop);
+        self.step = None;
+        self.update();
+    }
+
+    /// Subroutines and interrupt handlers the CPU is in, innermost last.
+    /// Only tracked while enabled with [`Debugger::set_call_tracking`].
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.call_stack
+    }
+
+    /// For resets, the calls the CPU was in won't return
+    pub(super) fn clear_call_stack(&mut self) {
+        self.call_stack.clear();
+    }
+
+    pub fn set_call_tracking(&mut self, enabled: bool) {
+        self.track_calls = enabled;
+        self.call_stack.clear();
+        self.update();
+    }
+
+    fn update(&mut self) {
+        let active = self.stopped.is_some()
+            || self.step.is_some()
+            || self.track_calls
+            || self.entries.iter().any(|entry| entry.enabled);
+
+        // instructions ran unseen in between
+        if active && !self.active {
+            self.last = Started::Unknown;
+        }
+        self.active = active;
+    }
+
+    /// Follows JSR, RTS, RTI and NMIs, called at every instruction boundary
+    /// with what started at the previous one
+    fn track_call(&mut self, last: Started, caller: u16, pc: u16) {
+        let interrupt = match last {
+            Started::Instruction(JSR) => false,
+            Started::Nmi => true,
+            Started::Instruction(RTS | RTI) => {
+                self.call_stack.pop();
+                return;
+            }
+            _ => return,
+        };
+
+        if self.call_stack.len() == CALL_STACK_LIMIT {
+            self.call_stack.remove(0);
+        }
+        self.call_stack.push(CallFrame {
+            caller,
+            target: pc,
+            interrupt,
+        });
+    }
+}
+
+impl Emulator {
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.debugger.next_id;
+        self.debugger.next_id += 1;
+
+        self.debugger.entries.push(Entry {
+            id,
+            breakpoint,
+            enabled: true,
+            hits: 0,
+            condition: None,
+        });
+        self.sync_breakpoints();
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) {
+        self.debugger.entries.retain(|entry| entry.id != id);
+        self.sync_breakpoints();
+    }
+
+    pub fn set_breakpoint_enabled(&mut self, id: usize, enabled: bool) {
+        for entry in &mut self.debugger.entries {
+            if entry.id == id {
+                entry.enabled = enabled;
+            }
+        }
+        self.sync_breakpoints();
+    }
+
+    /// Compiles `condition` for the breakpoint, or removes its condition when
+    /// it is empty
+    pub fn set_breakpoint_condition(&mut self, id: usize, condition: &str) -> Result<(), String> {
+        let condition = if condition.trim().is_empty() {
+            None
+        } else {
+            Some(Expression::compile(condition)?)
+        };
+
+        match self
+            .debugger
+            .entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+        {
+            Some(entry) => {
+                entry.condition = condition;
+                Ok(())
+            }
+            None => Err(format!("No breakpoint {}", id)),
+        }
+    }
+
+    /// Counts a hit on every enabled breakpoint matching `matches`, returns
+    /// the first whose condition holds
+    fn hit(&mut self, matches: impl Fn(&Entry) -> bool) -> Option<usize> {
+        let mut stop = None;
+
+        for index in 0..self.debugger.entries.len() {
+            let entry = &mut self.debugger.entries[index];
+            if !entry.enabled || !matches(entry) {
+                continue;
+            }
+            entry.hits += 1;
+
+            let entry = &self.debugger.entries[index];
+            let holds = entry
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.holds(self, entry.hits));
+            if holds && stop.is_none() {
+                stop = Some(entry.id);
+            }
+        }
+
+        stop
+    }
+
+    /// Copies enabled watchpoints to the devices that check them
+    fn sync_breakpoints(&mut self) {
+        let mut memory = self.memory.borrow_mut();
+        let mut ppu = self.ppu.borrow_mut();
+        memory.watchpoints.ranges.clear();
+        ppu.watchpoints.ranges.clear();
+
+        for entry in self.debugger.entries.iter().filter(|entry| entry.enabled) {
+            if let Breakpoint::Watch {
+                space,
+                start,
+                end,
+                access,
+            } = entry.breakpoint
+            {
+                let watchpoints = match space {
+                    Space::Cpu => &mut memory.watchpoints,
+                    Space::Ppu => &mut ppu.watchpoints,
+                };
+                watchpoints.ranges.push((entry.id, start, end, access));
+            }
+        }
+
+        self.debugger.update();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.debugger.stopped.is_some()
+    }
+
+    pub fn pause(&mut self) {
+        if self.debugger.stopped.is_none() {
+            self.debugger.stop(Stop::Pause);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.debugger.stopped = None;
+        // a watchpoint stops in the middle of an instruction, the next one
+        // is new and gets checked
+        self.debugger.resuming = !self.cpu.mid_instruction();
+        self.debugger.update();
+    }
+
+    fn start_step(&mut self, step: Step) {
+        self.resume();
+        self.debugger.step = Some(step);
+        self.debugger.update();
+    }
+
+    /// Runs one instruction
+    pub fn step_into(&mut self) {
+        self.start_step(Step::Into);
+    }
+
+    /// Runs one instruction, or a whole subroutine if it is a JSR
+    pub fn step_over(&mut self) {
+        let opcode = self.memory.borrow().peek(self.cpu.pc);
+        if opcode == JSR {
+            let step = Step::Over {
+                pc: self.cpu.pc.wrapping_add(3),
+                sp: self.cpu.sp,
+            };
+            self.start_step(step);
+        } else {
+            self.start_step(Step::Into);
+        }
+    }
+
+    /// Runs until the current subroutine returns
+    pub fn step_out(&mut self) {
+        self.start_step(Step::Out { sp: self.cpu.sp });
+    }
+
+    /// Runs until the CPU reaches `address`
+    pub fn run_to(&mut self, address: u16) {
+        self.start_step(Step::RunTo(address));
+    }
+
+    /// Runs until the next frame starts
+    pub fn frame_advance(&mut self) {
+        let frame = self.frame_count();
+        self.start_step(Step::Frame(frame));
+    }
+
+    /// Checks breakpoints and steps before an instruction starts, returns
+    /// whether execution stopped
+    pub(super) fn debug_before_instruction(&mut self) -> bool {
+        let pc = self.cpu.pc;
+        let opcode = self.memory.borrow().peek(pc);
+        let nmi = self.cpu.nmi_pending();
+
+        // an NMI is taken instead of the instruction at PC
+        let started = if nmi {
+            Started::Nmi
+        } else {
+            Started::Instruction(opcode)
+        };
+        let last = std::mem::replace(&mut self.debugger.last, started);
+        let last_pc = std::mem::replace(&mut self.debugger.last_pc, pc);
+        if self.debugger.track_calls {
+            self.debugger.track_call(last, last_pc, pc);
+        }
+
+        if std::mem::take(&mut self.debugger.resuming) {
+            return false;
+        }
+
+        let hit = self.hit(|entry| match entry.breakpoint {
+            Breakpoint::Exec(address) => address == pc && !nmi,
+            Breakpoint::Opcode(op) => op == opcode && !nmi,
+            Breakpoint::Nmi => nmi,
+            _ => false,
+        });
+        if let Some(id) = hit {
+            self.debugger.stop(Stop::Breakpoint(id));
+            return true;
+        }
+
+        let done = match self.debugger.step {
+            None => false,
+            Some(Step::Into) => true,
+            Some(Step::Over { pc: target, sp }) => pc == target && self.cpu.sp == sp,
+            Some(Step::Out { sp }) => {
+                matches!(last, Started::Instruction(RTS | RTI))
+                    && self.cpu.sp.wrapping_sub(sp) as i8 > 0
+            }
+            Some(Step::RunTo(address)) => pc == address,
+            Some(Step::Frame(frame)) => self.frame_count() != frame,
+        };
+        if done {
+            self.debugger.stop(Stop::Step);
+        }
+        done
+    }
+
+    /// Checks watchpoints and scanline breakpoints after a cycle ran
+    pub(super) fn debug_after_cycle(&mut self, last_scanline: u16) {
+        let hit = {
+            let memory_hit = self.memory.borrow_mut().watchpoints.hit.take();
+            let ppu_hit = self.ppu.borrow_mut().watchpoints.hit.take();
+            memory_hit.or(ppu_hit)
+        };
+        if let Some((id, hit)) = hit {
+            if self.hit(|entry| entry.id == id) == Some(id) {
+                self.debugger.stop(Stop::Watchpoint(id, hit));
+                return;
+            }
+        }
+
+        let scanline = self.ppu.borrow().scanline;
+        if scanline != last_scanline {
+            let hit = self.hit(|entry| entry.breakpoint == Breakpoint::Scanline(scanline));
+            if let Some(id) = hit {
+                self.debugger.stop(Stop::Breakpoint(id));
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Desync detection. Emulation only depends on the ROM, the power-on RAM
+//! pattern and the input, so two runs given the same ones must hash the same
+//! on every frame. Hashes are kept in a text file, one frame per line:
+//!
+//! ```text
+//! # frame  hash
+//! 1        8d3f0c2a91b4e657
+//! 2        02c4a1f9d8e3b670
+//! ```
+
+use std::{fs, path::Path};
+
+use super::{battery::write_atomic, Emulator};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Hash of the save state and the picture, the same across runs and builds
+pub fn state_hash(emulator: &Emulator) -> u64 {
+    let hash = fnv1a(FNV_OFFSET, &emulator.save_state());
+    fnv1a(hash, &emulator.ppu.borrow().framebuffer)
+}
+
+/// Hashes of a run, recorded whenever a frame starts while set as
+/// [`Emulator::hashes`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameHashes {
+    /// ([`Emulator::frame_count`], hash), in the order they were recorded
+    pub hashes: Vec<(u64, u64)>,
+}
+
+impl FrameHashes {
+    pub fn new() -> Self {
+        FrameHashes::default()
+    }
+
+    pub fn record(&mut self, emulator: &Emulator) {
+        self.hashes
+            .push((emulator.frame_count(), state_hash(emulator)));
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let mut hashes = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = || {
+                format!(
+                    "{}:{}: expected a frame and a hash",
+                    path.display(),
+                    number + 1
+                )
+            };
+            let (frame, hash) = line.split_once(char::is_whitespace).ok_or_else(error)?;
+            let frame = frame.parse().map_err(|_| error())?;
+            let hash = u64::from_str_radix(hash.trim(), 16).map_err(|_| error())?;
+            hashes.push((frame, hash));
+        }
+
+        Ok(FrameHashes { hashes })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut text = String::from("# frame  hash\n");
+        for (frame, hash) in &self.hashes {
+            text.push_str(&format!("{:<8} {:016x}\n", frame, hash));
+        }
+
+        write_atomic(path, text.as_bytes()).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The first frame the runs differ on, a frame only one of them has
+    /// counts as differing
+    pub fn first_divergence(&self, other: &FrameHashes) -> Option<u64> {
+        let mismatch = self
+            .hashes
+            .iter()
+            .zip(&other.hashes)
+            .find(|(ours, theirs)| ours != theirs);
+        if let Some((ours, theirs)) = mismatch {
+            return Some(ours.0.min(theirs.0));
+        }
+
+        let longer = if self.hashes.len() > other.hashes.len() {
+            self
+        } else {
+            other
+        };
+        longer
+            .hashes
+            .get(self.hashes.len().min(other.hashes.len()))
+            .map(|(frame, _)| *frame)
+    }
+}
@@ -0,0 +1,161 @@
+//! 6502 disassembler, reads memory with [`RAM::peek`] so it never disturbs
+//! the machine.
+
+use std::fmt;
+
+use super::{
+    cpu::{AddressingMode::*, CPU},
+    opcodes::{self, Opcode},
+    ram::RAM,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    /// Operand bytes as a little endian value, 0 when there are none
+    pub operand: u16,
+}
+
+impl Instruction {
+    pub fn info(&self) -> &'static Opcode {
+        opcodes::lookup(self.opcode)
+    }
+
+    /// Length in bytes, including the opcode
+    pub fn size(&self) -> u16 {
+        self.info().size()
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let [low, high] = self.operand.to_le_bytes();
+        [self.opcode, low, high][..self.size() as usize].to_vec()
+    }
+
+    /// Where a branch, JMP or JSR goes, for the ones that don't depend on
+    /// memory
+    pub fn target(&self) -> Option<u16> {
+        match self.info().mode {
+            Relative => Some(
+                self.address
+                    .wrapping_add(2)
+                    .wrapping_add(self.operand as u8 as i8 as u16),
+            ),
+            Absolute if matches!(self.info().mnemonic, "JMP" | "JSR") => Some(self.operand),
+            _ => None,
+        }
+    }
+
+    /// The address the instruction would access with the current registers
+    /// and memory. For JMP ($xxxx) this is the jump target.
+    pub fn effective_address(&self, cpu: &CPU, memory: &RAM) -> Option<u16> {
+        let zero_page = self.operand as u8;
+        // pointers in the zero page wrap around within it
+        let zp_pointer = |address: u8| {
+            u16::from_le_bytes([
+                memory.peek(address as u16),
+                memory.peek(address.wrapping_add(1) as u16),
+            ])
+        };
+
+        match self.info().mode {
+            NoneAddressing | Accumulator | Immediate => None,
+            ZeroPage => Some(zero_page as u16),
+            ZeroPageX => Some(zero_page.wrapping_add(cpu.idx_x) as u16),
+            ZeroPageY => Some(zero_page.wrapping_add(cpu.idx_y) as u16),
+            Absolute => Some(self.operand),
+            AbsoluteX => Some(self.operand.wrapping_add(cpu.idx_x as u16)),
+            AbsoluteY => Some(self.operand.wrapping_add(cpu.idx_y as u16)),
+            Indirect => {
+                // the high byte is fetched without carrying into the page
+                let high = (self.operand & 0xFF00) | (self.operand.wrapping_add(1) & 0x00FF);
+                Some(u16::from_le_bytes([
+                    memory.peek(self.operand),
+                    memory.peek(high),
+                ]))
+            }
+            IndirectX => Some(zp_pointer(zero_page.wrapping_add(cpu.idx_x))),
+            IndirectY => Some(zp_pointer(zero_page).wrapping_add(cpu.idx_y as u16)),
+            Relative => self.target(),
+        }
+    }
+
+    /// Formats the instruction followed by its effective address and the
+    /// value there, like `LDA ($20),Y @ $0305 = $5B`
+    pub fn format_resolved(&self, cpu: &CPU, memory: &RAM) -> String {
+        let text = self.to_string();
+
+        match (self.info().mode, self.effective_address(cpu, memory)) {
+            (Relative, _) | (_, None) => text,
+            (Indirect, Some(target)) => format!("{} @ ${:04X}", text, target),
+            (Absolute, Some(_)) if self.target().is_some() => text,
+            (_, Some(address)) => format!(
+                "{} @ ${:04X} = ${:02X}",
+                text,
+                address,
+                memory.peek(address)
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = self.info().mnemonic;
+        let operand = self.operand;
+
+        match self.info().mode {
+            NoneAddressing => write!(f, "{}", mnemonic),
+            Accumulator => write!(f, "{} A", mnemonic),
+            Immediate => write!(f, "{} #${:02X}", mnemonic, operand),
+            ZeroPage => write!(f, "{} ${:02X}", mnemonic, operand),
+            ZeroPageX => write!(f, "{} ${:02X},X", mnemonic, operand),
+            ZeroPageY => write!(f, "{} ${:02X},Y", mnemonic, operand),
+            Absolute => write!(f, "{} ${:04X}", mnemonic, operand),
+            AbsoluteX => write!(f, "{} ${:04X},X", mnemonic, operand),
+            AbsoluteY => write!(f, "{} ${:04X},Y", mnemonic, operand),
+            Indirect => write!(f, "{} (${:04X})", mnemonic, operand),
+            IndirectX => write!(f, "{} (${:02X},X)", mnemonic, operand),
+            IndirectY => write!(f, "{} (${:02X}),Y", mnemonic, operand),
+            Relative => write!(f, "{} ${:04X}", mnemonic, self.target().unwrap_or(0)),
+        }
+    }
+}
+
+/// Decodes the instruction at `address`, returns it and its length in bytes
+pub fn disassemble(memory: &RAM, address: u16) -> (Instruction, u16) {
+    let opcode = memory.peek(address);
+    let size = opcodes::lookup(opcode).size();
+
+    let operand = match size {
+        2 => memory.peek(address.wrapping_add(1)) as u16,
+        3 => memory.peek_u16(address.wrapping_add(1)),
+        _ => 0,
+    };
+
+    let instruction = Instruction {
+        address,
+        opcode,
+        operand,
+    };
+    (instruction, size)
+}
+
+/// Decodes instructions one after another from `start` until one starts
+/// after `end`, or memory wraps around
+pub fn disassemble_range(memory: &RAM, start: u16, end: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut address = start;
+
+    while address <= end {
+        let (instruction, size) = disassemble(memory, address);
+        instructions.push(instruction);
+
+        match address.checked_add(size) {
+            Some(next) => address = next,
+            None => break,
+        }
+    }
+
+    instructions
+}
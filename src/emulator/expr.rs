@@ -0,0 +1,420 @@
+//! Breakpoint condition expressions, like `A == $40 && X > 3`,
+//! `[$0300] & $80` or `scanline == 120 && hits > 2`.
+//!
+//! An expression is parsed once into a tree with constants folded, and
+//! evaluated against the emulator without touching it: memory is read with
+//! [`RAM::peek`](super::ram::RAM::peek). Values are signed 64 bit integers,
+//! comparisons and logic operators give 1 or 0, and an expression holds when
+//! it isn't 0.
+//!
+//! Names are case insensitive:
+//!
+//! - `A`, `X`, `Y`, `P`, `SP`, `PC`: CPU registers
+//! - `N`, `V`, `D`, `I`, `Z`, `C`: status flags, 0 or 1
+//! - `scanline`, `dot`: PPU position
+//! - `frame`: [`Emulator::frame_count`], the frame movies number by
+//! - `cycles`: CPU cycles since power on
+//! - `hits`: times the breakpoint was hit, including this one
+//! - `[address]`: the byte at a CPU address
+//!
+//! Numbers are decimal, `$` hex or `%` binary. Operators, from loosest to
+//! tightest: `||`, `&&`, `|`, `^`, `&`, `== !=`, `< <= > >=`, `<< >>`,
+//! `+ -`, `* / %`, and unary `! ~ -`.
+
+use super::Emulator;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Variable {
+    A,
+    X,
+    Y,
+    P,
+    Sp,
+    Pc,
+    /// Status flag with this mask
+    Flag(u8),
+    Scanline,
+    Dot,
+    Frame,
+    Cycles,
+    Hits,
+}
+
+impl Variable {
+    fn parse(name: &str) -> Option<Variable> {
+        let variable = match name.to_ascii_lowercase().as_str() {
+            "a" => Variable::A,
+            "x" => Variable::X,
+            "y" => Variable::Y,
+            "p" => Variable::P,
+            "sp" => Variable::Sp,
+            "pc" => Variable::Pc,
+            "n" => Variable::Flag(0x80),
+            "v" => Variable::Flag(0x40),
+            "d" => Variable::Flag(0x08),
+            "i" => Variable::Flag(0x04),
+            "z" => Variable::Flag(0x02),
+            "c" => Variable::Flag(0x01),
+            "scanline" => Variable::Scanline,
+            "dot" => Variable::Dot,
+            "frame" => Variable::Frame,
+            "cycles" => Variable::Cycles,
+            "hits" => Variable::Hits,
+            _ => return None,
+        };
+        Some(variable)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Unary {
+    Not,
+    Complement,
+    Negate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Binary {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl Binary {
+    /// Operators binding tighter have a higher precedence
+    fn precedence(self) -> u8 {
+        use Binary::*;
+        match self {
+            Or => 1,
+            And => 2,
+            BitOr => 3,
+            BitXor => 4,
+            BitAnd => 5,
+            Equal | NotEqual => 6,
+            Less | LessEqual | Greater | GreaterEqual => 7,
+            ShiftLeft | ShiftRight => 8,
+            Add | Subtract => 9,
+            Multiply | Divide | Remainder => 10,
+        }
+    }
+
+    fn apply(self, left: i64, right: i64) -> i64 {
+        use Binary::*;
+        match self {
+            Or => (left != 0 || right != 0) as i64,
+            And => (left != 0 && right != 0) as i64,
+            BitOr => left | right,
+            BitXor => left ^ right,
+            BitAnd => left & right,
+            Equal => (left == right) as i64,
+            NotEqual => (left != right) as i64,
+            Less => (left < right) as i64,
+            LessEqual => (left <= right) as i64,
+            Greater => (left > right) as i64,
+            GreaterEqual => (left >= right) as i64,
+            ShiftLeft => left.wrapping_shl(right as u32),
+            ShiftRight => left.wrapping_shr(right as u32),
+            Add => left.wrapping_add(right),
+            Subtract => left.wrapping_sub(right),
+            Multiply => left.wrapping_mul(right),
+            // dividing by zero gives 0 rather than stopping the emulator
+            Divide => left.checked_div(right).unwrap_or(0),
+            Remainder => left.checked_rem(right).unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    Constant(i64),
+    Variable(Variable),
+    Memory(Box<Node>),
+    Unary(Unary, Box<Node>),
+    Binary(Binary, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn evaluate(&self, emulator: &Emulator, hits: u64) -> i64 {
+        match self {
+            Node::Constant(value) => *value,
+            Node::Variable(variable) => {
+                let cpu = &emulator.cpu;
+                match variable {
+                    Variable::A => cpu.acc as i64,
+                    Variable::X => cpu.idx_x as i64,
+                    Variable::Y => cpu.idx_y as i64,
+                    Variable::P => cpu.status as i64,
+                    Variable::Sp => cpu.sp as i64,
+                    Variable::Pc => cpu.pc as i64,
+                    Variable::Flag(mask) => (cpu.status & mask != 0) as i64,
+                    Variable::Scanline => emulator.ppu.borrow().scanline as i64,
+                    Variable::Dot => emulator.ppu.borrow().dot as i64,
+                    Variable::Frame => emulator.frame_count() as i64,
+                    Variable::Cycles => cpu.cycles as i64,
+                    Variable::Hits => hits as i64,
+                }
+            }
+            Node::Memory(address) => {
+                let address = address.evaluate(emulator, hits) as u16;
+                emulator.memory.borrow().peek(address) as i64
+            }
+            Node::Unary(op, value) => {
+                let value = value.evaluate(emulator, hits);
+                match op {
+                    Unary::Not => (value == 0) as i64,
+                    Unary::Complement => !value,
+                    Unary::Negate => value.wrapping_neg(),
+                }
+            }
+            Node::Binary(Binary::And, left, right) => {
+                (left.evaluate(emulator, hits) != 0 && right.evaluate(emulator, hits) != 0) as i64
+            }
+            Node::Binary(Binary::Or, left, right) => {
+                (left.evaluate(emulator, hits) != 0 || right.evaluate(emulator, hits) != 0) as i64
+            }
+            Node::Binary(op, left, right) => op.apply(
+                left.evaluate(emulator, hits),
+                right.evaluate(emulator, hits),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Unary(Unary),
+    Binary(Binary),
+    /// `-`, subtraction or negation depending on where it is
+    Minus,
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '%' {
+            // `%` is the remainder operator unless a binary number follows
+            if c == '%' && !matches!(next, Some('0' | '1')) {
+                tokens.push(Token::Binary(Binary::Remainder));
+                i += 1;
+                continue;
+            }
+
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+
+            let number = if let Some(hex) = word.strip_prefix('$') {
+                i64::from_str_radix(hex, 16)
+            } else if let Some(binary) = word.strip_prefix('%') {
+                i64::from_str_radix(binary, 2)
+            } else if c.is_ascii_digit() {
+                word.parse()
+            } else {
+                tokens.push(Token::Name(word));
+                continue;
+            };
+            let number = number.map_err(|_| format!("Invalid number: {}", word))?;
+            tokens.push(Token::Number(number));
+            continue;
+        }
+
+        let (token, len) = match (c, next) {
+            ('|', Some('|')) => (Token::Binary(Binary::Or), 2),
+            ('&', Some('&')) => (Token::Binary(Binary::And), 2),
+            ('=', Some('=')) => (Token::Binary(Binary::Equal), 2),
+            ('!', Some('=')) => (Token::Binary(Binary::NotEqual), 2),
+            ('<', Some('=')) => (Token::Binary(Binary::LessEqual), 2),
+            ('>', Some('=')) => (Token::Binary(Binary::GreaterEqual), 2),
+            ('<', Some('<')) => (Token::Binary(Binary::ShiftLeft), 2),
+            ('>', Some('>')) => (Token::Binary(Binary::ShiftRight), 2),
+            ('|', _) => (Token::Binary(Binary::BitOr), 1),
+            ('^', _) => (Token::Binary(Binary::BitXor), 1),
+            ('&', _) => (Token::Binary(Binary::BitAnd), 1),
+            ('<', _) => (Token::Binary(Binary::Less), 1),
+            ('>', _) => (Token::Binary(Binary::Greater), 1),
+            ('+', _) => (Token::Binary(Binary::Add), 1),
+            ('-', _) => (Token::Minus, 1),
+            ('*', _) => (Token::Binary(Binary::Multiply), 1),
+            ('/', _) => (Token::Binary(Binary::Divide), 1),
+            ('!', _) => (Token::Unary(Unary::Not), 1),
+            ('~', _) => (Token::Unary(Unary::Complement), 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            ('[', _) => (Token::OpenBracket, 1),
+            (']', _) => (Token::CloseBracket, 1),
+            _ => return Err(format!("Unexpected character: {}", c)),
+        };
+        tokens.push(token);
+        i += len;
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek_binary(&self) -> Option<Binary> {
+        match self.tokens.get(self.position) {
+            Some(Token::Binary(op)) => Some(*op),
+            Some(Token::Minus) => Some(Binary::Subtract),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(format!("Expected {}", what)),
+        }
+    }
+
+    /// Parses operators binding at least as tight as `min_precedence`
+    fn binary(&mut self, min_precedence: u8) -> Result<Node, String> {
+        let mut left = self.unary()?;
+
+        while let Some(op) = self.peek_binary() {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.position += 1;
+
+            let right = self.binary(op.precedence() + 1)?;
+            left = fold(Node::Binary(op, Box::new(left), Box::new(right)));
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        let node = match self.next() {
+            Some(Token::Number(value)) => Node::Constant(value),
+            Some(Token::Name(name)) => match Variable::parse(&name) {
+                Some(variable) => Node::Variable(variable),
+                None => return Err(format!("Unknown name: {}", name)),
+            },
+            Some(Token::Unary(op)) => Node::Unary(op, Box::new(self.unary()?)),
+            Some(Token::Minus) => Node::Unary(Unary::Negate, Box::new(self.unary()?)),
+            Some(Token::Open) => {
+                let node = self.binary(0)?;
+                self.expect(Token::Close, ")")?;
+                node
+            }
+            Some(Token::OpenBracket) => {
+                let address = self.binary(0)?;
+                self.expect(Token::CloseBracket, "]")?;
+                Node::Memory(Box::new(address))
+            }
+            Some(token) => return Err(format!("Unexpected {:?}", token)),
+            None => return Err("Unexpected end of expression".to_string()),
+        };
+
+        Ok(fold(node))
+    }
+}
+
+/// Replaces operations on constants with their result
+fn fold(node: Node) -> Node {
+    match node {
+        Node::Unary(op, value) => match *value {
+            Node::Constant(value) => Node::Constant(match op {
+                Unary::Not => (value == 0) as i64,
+                Unary::Complement => !value,
+                Unary::Negate => value.wrapping_neg(),
+            }),
+            value => Node::Unary(op, Box::new(value)),
+        },
+        Node::Binary(op, left, right) => match (*left, *right) {
+            (Node::Constant(left), Node::Constant(right)) => Node::Constant(op.apply(left, right)),
+            (left, right) => Node::Binary(op, Box::new(left), Box::new(right)),
+        },
+        node => node,
+    }
+}
+
+/// A compiled condition
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expression {
+    /// The text it was compiled from, for display
+    pub text: String,
+    root: Node,
+}
+
+impl Expression {
+    pub fn compile(text: &str) -> Result<Expression, String> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+
+        let root = parser.binary(0)?;
+        if let Some(token) = parser.next() {
+            return Err(format!("Unexpected {:?}", token));
+        }
+
+        Ok(Expression {
+            text: text.trim().to_string(),
+            root,
+        })
+    }
+
+    pub fn evaluate(&self, emulator: &Emulator, hits: u64) -> i64 {
+        self.root.evaluate(emulator, hits)
+    }
+
+    pub fn holds(&self, emulator: &Emulator, hits: u64) -> bool {
+        self.evaluate(emulator, hits) != 0
+    }
+
+    /// Whether the expression is a constant, and what it is
+    pub fn constant(&self) -> Option<i64> {
+        match self.root {
+            Node::Constant(value) => Some(value),
+            _ => None,
+        }
+    }
+}
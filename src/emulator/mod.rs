@@ -1,32 +1,323 @@
 use std::{cell::RefCell, rc::Rc};
 
-use self::{cpu::CPU, ram::RAM};
+use self::{
+    cartridge::Cartridge,
+    cheats::Cheat,
+    cpu::CPU,
+    debugger::Debugger,
+    desync::FrameHashes,
+    movie::{Command, MovieSession},
+    ppu::PPU,
+    ram::{RamInit, RAM},
+    state::{Savestate, StateReader, StateWriter},
+    trace::TraceLogger,
+};
 
+pub mod asm;
+pub mod battery;
+pub mod cartridge;
+pub mod cheats;
+pub mod controller;
 pub mod cpu;
+pub mod debugger;
+pub mod desync;
+pub mod disasm;
+pub mod expr;
+pub mod movie;
+pub mod opcodes;
 pub mod ppu;
+pub mod ppu_viewer;
 pub mod ram;
+pub mod ram_search;
+pub mod region;
+pub mod rewind;
+pub mod slots;
+pub mod state;
+pub mod trace;
+pub mod watch;
 mod tests;
 
+/// CPU cycles in one NTSC frame, rounded
+pub const CPU_CYCLES_PER_FRAME: u32 = 29781;
+
+pub const FRAMES_PER_SECOND: u32 = 60;
+
 pub struct Emulator {
     pub memory: Rc<RefCell<RAM>>,
     pub cpu: CPU,
+    pub ppu: Rc<RefCell<PPU>>,
+    /// Logs each instruction before it runs while set
+    pub trace: Option<TraceLogger>,
+    /// Records a state hash whenever a frame starts while set
+    pub hashes: Option<FrameHashes>,
+    pub debugger: Debugger,
+    cheats: Vec<Cheat>,
+    movie: Option<MovieSession>,
+    /// What internal RAM holds after a power cycle
+    ram_init: RamInit,
+    frames: FrameCounter,
 }
 
 impl Emulator {
     pub fn new() -> Self {
-        let memory = Rc::new(RefCell::new(RAM::new()));
+        let ppu = Rc::new(RefCell::new(PPU::new()));
+        let memory = Rc::new(RefCell::new(RAM::new(ppu.clone())));
 
         Emulator {
             memory: memory.clone(),
             cpu: CPU::new(memory.clone()),
+            ppu,
+            trace: None,
+            hashes: None,
+            debugger: Debugger::new(),
+            cheats: Vec::new(),
+            movie: None,
+            ram_init: RamInit::Zeros,
+            frames: FrameCounter::default(),
         }
     }
 
+    /// Powers on with internal RAM filled as `init` says instead of zeros
+    pub fn with_ram_init(init: RamInit) -> Self {
+        let mut emulator = Emulator::new();
+        emulator.memory.borrow_mut().power_on(init);
+        emulator.ram_init = init;
+        emulator
+    }
+
+    /// Runs one CPU cycle and the three PPU dots that happen during it
+    pub fn cycle(&mut self) {
+        if self.debugger.is_active() {
+            if self.is_stopped() {
+                return;
+            }
+            if !self.cpu.mid_instruction() && self.debug_before_instruction() {
+                return;
+            }
+        }
+
+        if self.movie.is_some() && self.at_frame_start() {
+            self.movie_frame();
+        }
+
+        if self.trace.is_some() && !self.cpu.mid_instruction() {
+            let mut trace = self.trace.take().unwrap();
+            trace.log(self);
+            self.trace = Some(trace);
+        }
+
+        self.cpu.cycle();
+
+        let (last_scanline, new_frame) = {
+            let mut ppu = self.ppu.borrow_mut();
+            let (last_scanline, last_frame) = (ppu.scanline, ppu.frame);
+            for _ in 0..3 {
+                ppu.tick();
+            }
+
+            if ppu.take_nmi() {
+                self.cpu.trigger_nmi();
+            }
+            (last_scanline, ppu.frame != last_frame)
+        };
+
+        if new_frame {
+            self.frames.0 += 1;
+        }
+        if new_frame && !self.cheats.is_empty() {
+            self.apply_ram_cheats();
+        }
+        if new_frame && self.hashes.is_some() {
+            let mut hashes = self.hashes.take().unwrap();
+            hashes.record(self);
+            self.hashes = Some(hashes);
+        }
+
+        if self.debugger.is_active() {
+            self.debug_after_cycle(last_scanline);
+        }
+    }
+
+    /// Runs until the CPU finishes the current instruction
+    pub fn step(&mut self) {
+        self.cycle();
+        while self.cpu.mid_instruction() && !self.is_stopped() {
+            self.cycle();
+        }
+    }
+
+    /// Runs until the PPU starts the next frame, or the debugger stops
+    pub fn run_frame(&mut self) {
+        let frame = self.frame_count();
+        while self.frame_count() == frame && !self.is_stopped() {
+            self.cycle();
+        }
+    }
+
+    /// Frames started so far, see [`FrameCounter`]
+    pub fn frame_count(&self) -> u64 {
+        self.frames.0
+    }
+
+    /// Sets the held buttons of controller `port` (0 or 1), see
+    /// [`controller::Button`]. While a movie is active they are pressed
+    /// at the start of the next frame instead, see [`movie`].
+    pub fn set_buttons(&mut self, port: usize, buttons: u8) {
+        if let Some(session) = &mut self.movie {
+            session.live[port] = buttons;
+            return;
+        }
+        self.memory.borrow_mut().controllers[port].buttons = buttons;
+    }
+
     pub fn load(&mut self, program: Vec<u8>) {
         self.cpu.load(program);
     }
 
-    pub fn reset(&mut self) {
-        self.cpu.reset();
+    /// Maps a cartridge into memory and resets the CPU to its reset vector.
+    /// Only NROM (mapper 0) is supported so far.
+    pub fn load_rom(&mut self, cartridge: &Cartridge) -> Result<(), String> {
+        if cartridge.mapper != 0 {
+            return Err(format!("Mapper {} not supported", cartridge.mapper));
+        }
+
+        let prg_rom = &cartridge.prg_rom;
+        if prg_rom.is_empty() || 0x8000 % prg_rom.len() != 0 {
+            return Err(format!("Unexpected NROM PRG-ROM size: {}", prg_rom.len()));
+        }
+
+        {
+            let mut memory = self.memory.borrow_mut();
+
+            // 16 KiB PRG-ROM is mirrored into both halves of $8000-$FFFF
+            for bank in memory.array[0x8000..].chunks_mut(prg_rom.len()) {
+                bank.copy_from_slice(prg_rom);
+            }
+
+            memory.prg_ram = vec![0; cartridge.prg_ram_size];
+            memory.battery = cartridge.battery;
+            if !cartridge.battery {
+                self.ram_init.fill(&mut memory.prg_ram);
+            }
+        }
+
+        self.ppu
+            .borrow_mut()
+            .load_chr(&cartridge.chr_rom, cartridge.mirroring);
+
+        self.cpu.power_on();
+        Ok(())
+    }
+
+    /// The reset button. The CPU lowers SP by 3 and sets I, the PPU clears
+    /// some registers, RAM, PRG-RAM and the cartridge are kept. There is no
+    /// APU to silence yet. While a movie is active this happens at the
+    /// start of the next frame, see [`movie`].
+    pub fn soft_reset(&mut self) {
+        if !self.queue_movie_command(Command::SoftReset) {
+            self.soft_reset_now();
+        }
+    }
+
+    /// Turns the console off and on. CPU, PPU, controllers, internal RAM
+    /// and PRG-RAM without a battery start over, see
+    /// [`Emulator::with_ram_init`]. Battery backed PRG-RAM is kept, cheats
+    /// stay applied. While a movie is active this happens at the start of
+    /// the next frame, see [`movie`].
+    pub fn power_cycle(&mut self) {
+        if !self.queue_movie_command(Command::PowerCycle) {
+            self.power_cycle_now();
+        }
+    }
+
+    fn soft_reset_now(&mut self) {
+        self.ppu.borrow_mut().reset();
+        self.cpu.soft_reset();
+        self.debugger.clear_call_stack();
+    }
+
+    pub(super) fn power_cycle_now(&mut self) {
+        {
+            let mut memory = self.memory.borrow_mut();
+            memory.power_on(self.ram_init);
+            for controller in &mut memory.controllers {
+                controller.power_on();
+            }
+        }
+        self.ppu.borrow_mut().power_on();
+        self.cpu.power_on();
+        self.debugger.clear_call_stack();
+    }
+
+    /// Serializes the whole machine, see [`state`] for the format
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        writer.chunk(&self.cpu);
+        writer.chunk(&*self.memory.borrow());
+        writer.chunk(&self.memory.borrow().controllers);
+        writer.chunk(&*self.ppu.borrow());
+        writer.chunk(&self.frames);
+
+        writer.finish()
+    }
+
+    /// Restores a state written by [`Emulator::save_state`], including ones
+    /// written by older versions of the emulator
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        // split the whole state up front so a truncated file is rejected
+        // before any device is modified
+        let chunks = StateReader::chunks(data)?;
+
+        // devices load one at a time, so put back the ones already loaded
+        // when a later chunk is rejected
+        let backup = self.save_state();
+        if let Err(e) = self.load_chunks(&chunks) {
+            let chunks = StateReader::chunks(&backup).expect("own save state");
+            self.load_chunks(&chunks).expect("own save state");
+            return Err(e);
+        }
+
+        self.movie_state_loaded();
+        Ok(())
+    }
+
+    fn load_chunks(&mut self, chunks: &[state::Chunk]) -> Result<(), String> {
+        state::load_chunk(&mut self.cpu, chunks)?;
+        state::load_chunk(&mut *self.memory.borrow_mut(), chunks)?;
+        state::load_chunk(&mut self.memory.borrow_mut().controllers, chunks)?;
+        state::load_chunk(&mut *self.ppu.borrow_mut(), chunks)?;
+
+        // older states counted frames with the PPU
+        if !chunks.iter().any(|(tag, _, _)| *tag == FrameCounter::TAG) {
+            self.frames.0 = self.ppu.borrow().frame;
+        }
+        state::load_chunk(&mut self.frames, chunks)
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Frames started since the emulator was created. Unlike the PPU's frame it
+/// keeps counting across power cycles, so movies and frame hashes number
+/// frames by it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameCounter(pub u64);
+
+impl Savestate for FrameCounter {
+    const TAG: [u8; 4] = *b"FRMS";
+    const VERSION: u16 = 1;
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.0);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader, _version: u16) -> Result<(), String> {
+        self.0 = reader.read_u64()?;
+        Ok(())
     }
 }
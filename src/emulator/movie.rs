@@ -0,0 +1,421 @@
+//! Input movies, per frame controller state played back from power on or a
+//! save state. Movies are read and written in the FCEUX `.fm2` text format.
+//!
+//! A frame's input is pressed on its first CPU cycle, so input set between
+//! [`Emulator::run_frame`] calls lands on the frame about to run. A movie
+//! started there, like right after power on, starts with that frame,
+//! otherwise with the next one. While a movie is active
+//! [`Emulator::set_buttons`], [`Emulator::soft_reset`] and
+//! [`Emulator::power_cycle`] only take effect at the start of the next
+//! frame, so they land on the same frame when the movie is played back.
+
+use std::{fs, path::Path};
+
+use super::{battery::write_atomic, Emulator};
+
+/// Bits of [`Frame::commands`]
+#[allow(non_upper_case_globals)]
+#[allow(dead_code)]
+#[allow(non_snake_case)]
+pub mod Command {
+    pub const SoftReset: u8 = 0b0000_0001;
+    pub const PowerCycle: u8 = 0b0000_0010;
+}
+
+/// Buttons in the order `.fm2` input logs list them, from bit 7 down
+const BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    /// See [`Command`]
+    pub commands: u8,
+    /// Held buttons of both ports, see [`Button`](super::controller::Button)
+    pub buttons: [u8; 2],
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Movie {
+    pub frames: Vec<Frame>,
+    pub rerecords: u32,
+    /// [`Emulator::save_state`] the movie starts from, `None` for power on
+    pub savestate: Option<Vec<u8>>,
+    pub rom_filename: String,
+    /// `comment` lines, e.g. `author someone`
+    pub comments: Vec<String>,
+    /// Header lines not used here, like `romChecksum` and `guid`, kept so
+    /// imported movies are exported unchanged
+    pub extra: Vec<(String, String)>,
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(bits >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+
+    for c in text.trim_end_matches('=').bytes() {
+        let value = BASE64
+            .iter()
+            .position(|&b| b == c)
+            .ok_or_else(|| format!("Invalid base64 character '{}'", c as char))?;
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+
+    Ok(data)
+}
+
+/// A port of an input log line, `.` or space means released
+fn parse_buttons(text: &str) -> Result<u8, String> {
+    if text.is_empty() {
+        return Ok(0);
+    }
+    if text.len() != BUTTONS.len() {
+        return Err(format!("Expected 8 buttons, got '{}'", text));
+    }
+
+    Ok(text
+        .bytes()
+        .enumerate()
+        .filter(|&(_, c)| c != b'.' && c != b' ')
+        .fold(0, |buttons, (i, _)| buttons | (0x80 >> i)))
+}
+
+fn format_buttons(buttons: u8) -> String {
+    BUTTONS
+        .iter()
+        .enumerate()
+        .map(|(i, &name)| {
+            if buttons & (0x80 >> i) != 0 {
+                name as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+impl Movie {
+    pub fn load(path: &Path) -> Result<Movie, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Movie::parse_fm2(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        write_atomic(path, self.to_fm2().as_bytes())
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parses an `.fm2` movie. Only standard controllers are supported, and
+    /// save states must have been written by this emulator.
+    pub fn parse_fm2(text: &str) -> Result<Movie, String> {
+        let mut movie = Movie::default();
+        let mut ports = [1, 1, 0];
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+
+            if let Some(log) = line.strip_prefix('|') {
+                let fields: Vec<&str> = log.split('|').collect();
+                if fields.len() < 3 {
+                    return Err(error(format!("Invalid input log line: {}", line)));
+                }
+
+                let commands = fields[0]
+                    .parse()
+                    .map_err(|_| error(format!("Invalid commands: {}", fields[0])))?;
+                let mut buttons = [0; 2];
+                for (port, buttons) in buttons.iter_mut().enumerate() {
+                    if ports[port] == 1 {
+                        *buttons = parse_buttons(fields[port + 1]).map_err(error)?;
+                    }
+                }
+                movie.frames.push(Frame { commands, buttons });
+                continue;
+            }
+
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let number = || {
+                value
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| error(format!("Invalid {}: {}", key, value)))
+            };
+
+            match key {
+                "version" if value.trim() != "3" => {
+                    return Err(error(format!("Unsupported version {}", value)))
+                }
+                "version" | "emuVersion" | "palFlag" | "FDS" | "NewPPU" | "microphone" => {}
+                "binary" if number()? != 0 => {
+                    return Err(error("Binary input logs aren't supported".to_string()))
+                }
+                "binary" => {}
+                "fourscore" if number()? != 0 => {
+                    return Err(error("Four Score movies aren't supported".to_string()))
+                }
+                "fourscore" => {}
+                "port0" | "port1" | "port2" => {
+                    let port = key.as_bytes()[4] - b'0';
+                    ports[port as usize] = number()?;
+                    if port < 2 && ports[port as usize] > 1 {
+                        return Err(error(format!("Only gamepads are supported, {}", line)));
+                    }
+                }
+                "rerecordCount" => movie.rerecords = number()?,
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "comment" => movie.comments.push(value.to_string()),
+                "savestate" => {
+                    let data = value
+                        .strip_prefix("base64:")
+                        .ok_or_else(|| error("Expected a base64: save state".to_string()))?;
+                    movie.savestate = Some(base64_decode(data).map_err(error)?);
+                }
+                _ => movie.extra.push((key.to_string(), value.to_string())),
+            }
+        }
+
+        Ok(movie)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut text = String::from("version 3\nemuVersion 0\n");
+        text.push_str(&format!("rerecordCount {}\n", self.rerecords));
+        text.push_str("palFlag 0\n");
+        text.push_str(&format!("romFilename {}\n", self.rom_filename));
+        for (key, value) in &self.extra {
+            text.push_str(&format!("{} {}\n", key, value));
+        }
+        text.push_str("fourscore 0\nmicrophone 0\nport0 1\nport1 1\nport2 0\nFDS 0\nNewPPU 0\n");
+        for comment in &self.comments {
+            text.push_str(&format!("comment {}\n", comment));
+        }
+        if let Some(savestate) = &self.savestate {
+            text.push_str(&format!("savestate base64:{}\n", base64_encode(savestate)));
+        }
+
+        for frame in &self.frames {
+            text.push_str(&format!(
+                "|{}|{}|{}||\n",
+                frame.commands,
+                format_buttons(frame.buttons[0]),
+                format_buttons(frame.buttons[1])
+            ));
+        }
+        text
+    }
+}
+
+/// A movie being recorded or played back
+pub struct MovieSession {
+    pub movie: Movie,
+    /// Loading a state while read only keeps playing, otherwise the movie
+    /// is cut at that frame and recording takes over
+    pub read_only: bool,
+    recording: bool,
+    /// [`Emulator::frame_count`] the movie's first frame is
+    start_frame: u64,
+    /// Buttons from [`Emulator::set_buttons`], pressed at the next frame
+    pub(super) live: [u8; 2],
+    /// Resets asked for since the last frame, see [`Command`]
+    pub(super) commands: u8,
+}
+
+impl MovieSession {
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Movie frame the emulator is in, `None` before the movie starts
+    pub fn frame(&self, emulator: &Emulator) -> Option<usize> {
+        emulator
+            .frame_count()
+            .checked_sub(self.start_frame)
+            .map(|frame| frame as usize)
+    }
+
+    /// Whether playback went past the last frame
+    pub fn is_finished(&self, emulator: &Emulator) -> bool {
+        !self.recording
+            && self
+                .frame(emulator)
+                .is_some_and(|frame| frame >= self.movie.frames.len())
+    }
+}
+
+impl Emulator {
+    pub fn movie(&self) -> Option<&MovieSession> {
+        self.movie.as_ref()
+    }
+
+    /// Starts recording a new movie, from a save state of the current
+    /// machine or, when `from_savestate` is false, from a power cycle
+    pub fn record_movie(&mut self, from_savestate: bool, rom_filename: &str) {
+        self.movie = None;
+        if !from_savestate {
+            self.power_cycle_now();
+        }
+        let movie = Movie {
+            savestate: from_savestate.then(|| self.save_state()),
+            rom_filename: rom_filename.to_string(),
+            ..Movie::default()
+        };
+        self.start_movie_session(movie, false, true);
+    }
+
+    /// Plays a movie back, loading its save state first or power cycling
+    /// for movies that start at power on
+    pub fn play_movie(&mut self, movie: Movie, read_only: bool) -> Result<(), String> {
+        self.movie = None;
+        match &movie.savestate {
+            Some(savestate) => self.load_state(savestate)?,
+            None => self.power_cycle_now(),
+        }
+        self.start_movie_session(movie, read_only, false);
+        Ok(())
+    }
+
+    /// Whether the PPU started a frame in the last cycle, or was just
+    /// powered on, and nothing of the frame has run yet
+    pub(super) fn at_frame_start(&self) -> bool {
+        let ppu = self.ppu.borrow();
+        // a CPU cycle is three dots
+        ppu.scanline == 0 && ppu.dot < 3
+    }
+
+    fn start_movie_session(&mut self, movie: Movie, read_only: bool, recording: bool) {
+        let at_frame_start = self.at_frame_start();
+        let frame = self.frame_count();
+        let live = {
+            let memory = self.memory.borrow();
+            [memory.controllers[0].buttons, memory.controllers[1].buttons]
+        };
+
+        self.movie = Some(MovieSession {
+            movie,
+            read_only,
+            recording,
+            start_frame: if at_frame_start { frame } else { frame + 1 },
+            live,
+            commands: 0,
+        });
+    }
+
+    /// Stops recording or playback and returns the movie
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(|session| session.movie)
+    }
+
+    pub fn set_movie_read_only(&mut self, read_only: bool) {
+        if let Some(session) = &mut self.movie {
+            session.read_only = read_only;
+        }
+    }
+
+    /// Records or plays back the input of the frame that is starting
+    pub(super) fn movie_frame(&mut self) {
+        let mut session = match self.movie.take() {
+            Some(session) => session,
+            None => return,
+        };
+
+        // resets asked for during playback are dropped like live input
+        let commands = std::mem::take(&mut session.commands);
+        if let Some(index) = session.frame(self) {
+            if session.recording {
+                session.movie.frames.truncate(index);
+                session.movie.frames.push(Frame {
+                    commands,
+                    buttons: session.live,
+                });
+                self.run_commands(commands);
+                self.press(session.live);
+            } else if let Some(&frame) = session.movie.frames.get(index) {
+                self.run_commands(frame.commands);
+                self.press(frame.buttons);
+            }
+        }
+
+        self.movie = Some(session);
+    }
+
+    /// Called after a state was loaded, see [`MovieSession::read_only`]
+    pub(super) fn movie_state_loaded(&mut self) {
+        let mut session = match self.movie.take() {
+            Some(session) => session,
+            None => return,
+        };
+
+        if session.read_only {
+            session.recording = false;
+        } else {
+            // unless the frame the state is in is just starting, it has had
+            // its input pressed already
+            let keep = match session.frame(self) {
+                Some(frame) if !self.at_frame_start() => frame + 1,
+                Some(frame) => frame,
+                None => 0,
+            };
+            session.movie.frames.truncate(keep);
+            session.movie.rerecords += 1;
+            session.recording = true;
+        }
+
+        self.movie = Some(session);
+    }
+
+    /// Defers a reset to the next frame while a movie is active, returns
+    /// whether it was
+    pub(super) fn queue_movie_command(&mut self, command: u8) -> bool {
+        match &mut self.movie {
+            Some(session) => {
+                session.commands |= command;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn run_commands(&mut self, commands: u8) {
+        if commands & Command::PowerCycle != 0 {
+            self.power_cycle_now();
+        } else if commands & Command::SoftReset != 0 {
+            self.soft_reset_now();
+        }
+    }
+
+    fn press(&mut self, buttons: [u8; 2]) {
+        let mut memory = self.memory.borrow_mut();
+        memory.controllers[0].buttons = buttons[0];
+        memory.controllers[1].buttons = buttons[1];
+    }
+}
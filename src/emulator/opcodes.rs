@@ -0,0 +1,311 @@
+//! Static information about every 6502 opcode, official or not.
+
+use super::cpu::AddressingMode::{self, *};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    /// Base cycle count, without page crossing or branch penalties
+    pub cycles: u8,
+    /// Undocumented opcodes, which nestest style traces mark with `*`
+    pub unofficial: bool,
+}
+
+impl Opcode {
+    /// Instruction length in bytes, including the opcode
+    pub fn size(&self) -> u16 {
+        operand_len(self.mode) + 1
+    }
+}
+
+/// Number of operand bytes following the opcode
+pub fn operand_len(mode: AddressingMode) -> u16 {
+    match mode {
+        NoneAddressing | Accumulator => 0,
+        Immediate | ZeroPage | ZeroPageX | ZeroPageY | IndirectX | IndirectY | Relative => 1,
+        Absolute | AbsoluteX | AbsoluteY | Indirect => 2,
+    }
+}
+
+pub fn lookup(opcode: u8) -> &'static Opcode {
+    &OPCODES[opcode as usize]
+}
+
+const fn official(mnemonic: &'static str, mode: AddressingMode, cycles: u8) -> Opcode {
+    Opcode {
+        mnemonic,
+        mode,
+        cycles,
+        unofficial: false,
+    }
+}
+
+const fn unofficial(mnemonic: &'static str, mode: AddressingMode, cycles: u8) -> Opcode {
+    Opcode {
+        mnemonic,
+        mode,
+        cycles,
+        unofficial: true,
+    }
+}
+
+#[rustfmt::skip]
+pub const OPCODES: [Opcode; 256] = [
+    official("BRK", NoneAddressing, 7), // 00
+    official("ORA", IndirectX, 6), // 01
+    unofficial("STP", NoneAddressing, 2), // 02
+    unofficial("SLO", IndirectX, 8), // 03
+    unofficial("NOP", ZeroPage, 3), // 04
+    official("ORA", ZeroPage, 3), // 05
+    official("ASL", ZeroPage, 5), // 06
+    unofficial("SLO", ZeroPage, 5), // 07
+    official("PHP", NoneAddressing, 3), // 08
+    official("ORA", Immediate, 2), // 09
+    official("ASL", Accumulator, 2), // 0A
+    unofficial("ANC", Immediate, 2), // 0B
+    unofficial("NOP", Absolute, 4), // 0C
+    official("ORA", Absolute, 4), // 0D
+    official("ASL", Absolute, 6), // 0E
+    unofficial("SLO", Absolute, 6), // 0F
+    official("BPL", Relative, 2), // 10
+    official("ORA", IndirectY, 5), // 11
+    unofficial("STP", NoneAddressing, 2), // 12
+    unofficial("SLO", IndirectY, 8), // 13
+    unofficial("NOP", ZeroPageX, 4), // 14
+    official("ORA", ZeroPageX, 4), // 15
+    official("ASL", ZeroPageX, 6), // 16
+    unofficial("SLO", ZeroPageX, 6), // 17
+    official("CLC", NoneAddressing, 2), // 18
+    official("ORA", AbsoluteY, 4), // 19
+    unofficial("NOP", NoneAddressing, 2), // 1A
+    unofficial("SLO", AbsoluteY, 7), // 1B
+    unofficial("NOP", AbsoluteX, 4), // 1C
+    official("ORA", AbsoluteX, 4), // 1D
+    official("ASL", AbsoluteX, 7), // 1E
+    unofficial("SLO", AbsoluteX, 7), // 1F
+    official("JSR", Absolute, 6), // 20
+    official("AND", IndirectX, 6), // 21
+    unofficial("STP", NoneAddressing, 2), // 22
+    unofficial("RLA", IndirectX, 8), // 23
+    official("BIT", ZeroPage, 3), // 24
+    official("AND", ZeroPage, 3), // 25
+    official("ROL", ZeroPage, 5), // 26
+    unofficial("RLA", ZeroPage, 5), // 27
+    official("PLP", NoneAddressing, 4), // 28
+    official("AND", Immediate, 2), // 29
+    official("ROL", Accumulator, 2), // 2A
+    unofficial("ANC", Immediate, 2), // 2B
+    official("BIT", Absolute, 4), // 2C
+    official("AND", Absolute, 4), // 2D
+    official("ROL", Absolute, 6), // 2E
+    unofficial("RLA", Absolute, 6), // 2F
+    official("BMI", Relative, 2), // 30
+    official("AND", IndirectY, 5), // 31
+    unofficial("STP", NoneAddressing, 2), // 32
+    unofficial("RLA", IndirectY, 8), // 33
+    unofficial("NOP", ZeroPageX, 4), // 34
+    official("AND", ZeroPageX, 4), // 35
+    official("ROL", ZeroPageX, 6), // 36
+    unofficial("RLA", ZeroPageX, 6), // 37
+    official("SEC", NoneAddressing, 2), // 38
+    official("AND", AbsoluteY, 4), // 39
+    unofficial("NOP", NoneAddressing, 2), // 3A
+    unofficial("RLA", AbsoluteY, 7), // 3B
+    unofficial("NOP", AbsoluteX, 4), // 3C
+    official("AND", AbsoluteX, 4), // 3D
+    official("ROL", AbsoluteX, 7), // 3E
+    unofficial("RLA", AbsoluteX, 7), // 3F
+    official("RTI", NoneAddressing, 6), // 40
+    official("EOR", IndirectX, 6), // 41
+    unofficial("STP", NoneAddressing, 2), // 42
+    unofficial("SRE", IndirectX, 8), // 43
+    unofficial("NOP", ZeroPage, 3), // 44
+    official("EOR", ZeroPage, 3), // 45
+    official("LSR", ZeroPage, 5), // 46
+    unofficial("SRE", ZeroPage, 5), // 47
+    official("PHA", NoneAddressing, 3), // 48
+    official("EOR", Immediate, 2), // 49
+    official("LSR", Accumulator, 2), // 4A
+    unofficial("ALR", Immediate, 2), // 4B
+    official("JMP", Absolute, 3), // 4C
+    official("EOR", Absolute, 4), // 4D
+    official("LSR", Absolute, 6), // 4E
+    unofficial("SRE", Absolute, 6), // 4F
+    official("BVC", Relative, 2), // 50
+    official("EOR", IndirectY, 5), // 51
+    unofficial("STP", NoneAddressing, 2), // 52
+    unofficial("SRE", IndirectY, 8), // 53
+    unofficial("NOP", ZeroPageX, 4), // 54
+    official("EOR", ZeroPageX, 4), // 55
+    official("LSR", ZeroPageX, 6), // 56
+    unofficial("SRE", ZeroPageX, 6), // 57
+    official("CLI", NoneAddressing, 2), // 58
+    official("EOR", AbsoluteY, 4), // 59
+    unofficial("NOP", NoneAddressing, 2), // 5A
+    unofficial("SRE", AbsoluteY, 7), // 5B
+    unofficial("NOP", AbsoluteX, 4), // 5C
+    official("EOR", AbsoluteX, 4), // 5D
+    official("LSR", AbsoluteX, 7), // 5E
+    unofficial("SRE", AbsoluteX, 7), // 5F
+    official("RTS", NoneAddressing, 6), // 60
+    official("ADC", IndirectX, 6), // 61
+    unofficial("STP", NoneAddressing, 2), // 62
+    unofficial("RRA", IndirectX, 8), // 63
+    unofficial("NOP", ZeroPage, 3), // 64
+    official("ADC", ZeroPage, 3), // 65
+    official("ROR", ZeroPage, 5), // 66
+    unofficial("RRA", ZeroPage, 5), // 67
+    official("PLA", NoneAddressing, 4), // 68
+    official("ADC", Immediate, 2), // 69
+    official("ROR", Accumulator, 2), // 6A
+    unofficial("ARR", Immediate, 2), // 6B
+    official("JMP", Indirect, 5), // 6C
+    official("ADC", Absolute, 4), // 6D
+    official("ROR", Absolute, 6), // 6E
+    unofficial("RRA", Absolute, 6), // 6F
+    official("BVS", Relative, 2), // 70
+    official("ADC", IndirectY, 5), // 71
+    unofficial("STP", NoneAddressing, 2), // 72
+    unofficial("RRA", IndirectY, 8), // 73
+    unofficial("NOP", ZeroPageX, 4), // 74
+    official("ADC", ZeroPageX, 4), // 75
+    official("ROR", ZeroPageX, 6), // 76
+    unofficial("RRA", ZeroPageX, 6), // 77
+    official("SEI", NoneAddressing, 2), // 78
+    official("ADC", AbsoluteY, 4), // 79
+    unofficial("NOP", NoneAddressing, 2), // 7A
+    unofficial("RRA", AbsoluteY, 7), // 7B
+    unofficial("NOP", AbsoluteX, 4), // 7C
+    official("ADC", AbsoluteX, 4), // 7D
+    official("ROR", AbsoluteX, 7), // 7E
+    unofficial("RRA", AbsoluteX, 7), // 7F
+    unofficial("NOP", Immediate, 2), // 80
+    official("STA", IndirectX, 6), // 81
+    unofficial("NOP", Immediate, 2), // 82
+    unofficial("SAX", IndirectX, 6), // 83
+    official("STY", ZeroPage, 3), // 84
+    official("STA", ZeroPage, 3), // 85
+    official("STX", ZeroPage, 3), // 86
+    unofficial("SAX", ZeroPage, 3), // 87
+    official("DEY", NoneAddressing, 2), // 88
+    unofficial("NOP", Immediate, 2), // 89
+    official("TXA", NoneAddressing, 2), // 8A
+    unofficial("XAA", Immediate, 2), // 8B
+    official("STY", Absolute, 4), // 8C
+    official("STA", Absolute, 4), // 8D
+    official("STX", Absolute, 4), // 8E
+    unofficial("SAX", Absolute, 4), // 8F
+    official("BCC", Relative, 2), // 90
+    official("STA", IndirectY, 6), // 91
+    unofficial("STP", NoneAddressing, 2), // 92
+    unofficial("AHX", IndirectY, 6), // 93
+    official("STY", ZeroPageX, 4), // 94
+    official("STA", ZeroPageX, 4), // 95
+    official("STX", ZeroPageY, 4), // 96
+    unofficial("SAX", ZeroPageY, 4), // 97
+    official("TYA", NoneAddressing, 2), // 98
+    official("STA", AbsoluteY, 5), // 99
+    official("TXS", NoneAddressing, 2), // 9A
+    unofficial("TAS", AbsoluteY, 5), // 9B
+    unofficial("SHY", AbsoluteX, 5), // 9C
+    official("STA", AbsoluteX, 5), // 9D
+    unofficial("SHX", AbsoluteY, 5), // 9E
+    unofficial("AHX", AbsoluteY, 5), // 9F
+    official("LDY", Immediate, 2), // A0
+    official("LDA", IndirectX, 6), // A1
+    official("LDX", Immediate, 2), // A2
+    unofficial("LAX", IndirectX, 6), // A3
+    official("LDY", ZeroPage, 3), // A4
+    official("LDA", ZeroPage, 3), // A5
+    official("LDX", ZeroPage, 3), // A6
+    unofficial("LAX", ZeroPage, 3), // A7
+    official("TAY", NoneAddressing, 2), // A8
+    official("LDA", Immediate, 2), // A9
+    official("TAX", NoneAddressing, 2), // AA
+    unofficial("LAX", Immediate, 2), // AB
+    official("LDY", Absolute, 4), // AC
+    official("LDA", Absolute, 4), // AD
+    official("LDX", Absolute, 4), // AE
+    unofficial("LAX", Absolute, 4), // AF
+    official("BCS", Relative, 2), // B0
+    official("LDA", IndirectY, 5), // B1
+    unofficial("STP", NoneAddressing, 2), // B2
+    unofficial("LAX", IndirectY, 5), // B3
+    official("LDY", ZeroPageX, 4), // B4
+    official("LDA", ZeroPageX, 4), // B5
+    official("LDX", ZeroPageY, 4), // B6
+    unofficial("LAX", ZeroPageY, 4), // B7
+    official("CLV", NoneAddressing, 2), // B8
+    official("LDA", AbsoluteY, 4), // B9
+    official("TSX", NoneAddressing, 2), // BA
+    unofficial("LAS", AbsoluteY, 4), // BB
+    official("LDY", AbsoluteX, 4), // BC
+    official("LDA", AbsoluteX, 4), // BD
+    official("LDX", AbsoluteY, 4), // BE
+    unofficial("LAX", AbsoluteY, 4), // BF
+    official("CPY", Immediate, 2), // C0
+    official("CMP", IndirectX, 6), // C1
+    unofficial("NOP", Immediate, 2), // C2
+    unofficial("DCP", IndirectX, 8), // C3
+    official("CPY", ZeroPage, 3), // C4
+    official("CMP", ZeroPage, 3), // C5
+    official("DEC", ZeroPage, 5), // C6
+    unofficial("DCP", ZeroPage, 5), // C7
+    official("INY", NoneAddressing, 2), // C8
+    official("CMP", Immediate, 2), // C9
+    official("DEX", NoneAddressing, 2), // CA
+    unofficial("AXS", Immediate, 2), // CB
+    official("CPY", Absolute, 4), // CC
+    official("CMP", Absolute, 4), // CD
+    official("DEC", Absolute, 6), // CE
+    unofficial("DCP", Absolute, 6), // CF
+    official("BNE", Relative, 2), // D0
+    official("CMP", IndirectY, 5), // D1
+    unofficial("STP", NoneAddressing, 2), // D2
+    unofficial("DCP", IndirectY, 8), // D3
+    unofficial("NOP", ZeroPageX, 4), // D4
+    official("CMP", ZeroPageX, 4), // D5
+    official("DEC", ZeroPageX, 6), // D6
+    unofficial("DCP", ZeroPageX, 6), // D7
+    official("CLD", NoneAddressing, 2), // D8
+    official("CMP", AbsoluteY, 4), // D9
+    unofficial("NOP", NoneAddressing, 2), // DA
+    unofficial("DCP", AbsoluteY, 7), // DB
+    unofficial("NOP", AbsoluteX, 4), // DC
+    official("CMP", AbsoluteX, 4), // DD
+    official("DEC", AbsoluteX, 7), // DE
+    unofficial("DCP", AbsoluteX, 7), // DF
+    official("CPX", Immediate, 2), // E0
+    official("SBC", IndirectX, 6), // E1
+    unofficial("NOP", Immediate, 2), // E2
+    unofficial("ISB", IndirectX, 8), // E3
+    official("CPX", ZeroPage, 3), // E4
+    official("SBC", ZeroPage, 3), // E5
+    official("INC", ZeroPage, 5), // E6
+    unofficial("ISB", ZeroPage, 5), // E7
+    official("INX", NoneAddressing, 2), // E8
+    official("SBC", Immediate, 2), // E9
+    official("NOP", NoneAddressing, 2), // EA
+    unofficial("SBC", Immediate, 2), // EB
+    official("CPX", Absolute, 4), // EC
+    official("SBC", Absolute, 4), // ED
+    official("INC", Absolute, 6), // EE
+    unofficial("ISB", Absolute, 6), // EF
+    official("BEQ", Relative, 2), // F0
+    official("SBC", IndirectY, 5), // F1
+    unofficial("STP", NoneAddressing, 2), // F2
+    unofficial("ISB", IndirectY, 8), // F3
+    unofficial("NOP", ZeroPageX, 4), // F4
+    official("SBC", ZeroPageX, 4), // F5
+    official("INC", ZeroPageX, 6), // F6
+    unofficial("ISB", ZeroPageX, 6), // F7
+    official("SED", NoneAddressing, 2), // F8
+    official("SBC", AbsoluteY, 4), // F9
+    unofficial("NOP", NoneAddressing, 2), // FA
+    unofficial("ISB", AbsoluteY, 7), // FB
+    unofficial("NOP", AbsoluteX, 4), // FC
+    official("SBC", AbsoluteX, 4), // FD
+    official("INC", AbsoluteX, 7), // FE
+    unofficial("ISB", AbsoluteX, 7), // FF
+];
@@ -0,0 +1,618 @@
+use super::{
+    cartridge::Mirroring,
+    debugger::Watchpoints,
+    state::{Savestate, StateReader, StateWriter},
+};
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+/// Dots per scanline and scanlines per frame, NTSC
+pub const DOTS: u16 = 341;
+pub const SCANLINES: u16 = 262;
+const PRE_RENDER_SCANLINE: u16 = 261;
+const VBLANK_SCANLINE: u16 = 241;
+
+/// Picture processing unit, rendered a scanline at a time into `framebuffer`
+pub struct PPU {
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub oam_addr: u8,
+    /// Current VRAM address, also holds the scroll position while rendering
+    pub v: u16,
+    /// Temporary VRAM address, copied into `v` during rendering
+    pub t: u16,
+    /// Fine X scroll
+    pub x: u8,
+    /// Write toggle shared by $2005 and $2006
+    pub w: bool,
+    /// $2007 reads return the previously read byte
+    data_buffer: u8,
+    /// Last value written to any register, returned by write only registers
+    open_bus: u8,
+
+    pub vram: [u8; 0x800],
+    pub palette: [u8; 32],
+    pub oam: [u8; 256],
+    /// Pattern tables, CHR-ROM from the cartridge or 8 KiB of CHR-RAM
+    pub chr: Vec<u8>,
+    chr_ram: bool,
+    pub mirroring: Mirroring,
+
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame: u64,
+    nmi: bool,
+
+    /// RGB8, `SCREEN_WIDTH` x `SCREEN_HEIGHT`
+    pub framebuffer: Vec<u8>,
+
+    /// Checked on CPU accesses through $2007
+    pub watchpoints: Watchpoints,
+}
+
+impl PPU {
+    pub fn new() -> Self {
+        PPU {
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam_addr: 0,
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            data_buffer: 0,
+            open_bus: 0,
+            vram: [0; 0x800],
+            palette: [0; 32],
+            oam: [0; 256],
+            chr: vec![0; 0x2000],
+            chr_ram: true,
+            mirroring: Mirroring::Horizontal,
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            nmi: false,
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 3],
+            watchpoints: Watchpoints::default(),
+        }
+    }
+
+    /// Uses the cartridge's CHR-ROM, or CHR-RAM if it has none
+    pub fn load_chr(&mut self, chr_rom: &[u8], mirroring: Mirroring) {
+        self.chr_ram = chr_rom.is_empty();
+        self.chr = if self.chr_ram {
+            vec![0; 0x2000]
+        } else {
+            chr_rom.to_vec()
+        };
+        self.mirroring = mirroring;
+    }
+
+    /// The reset button clears the control, mask and scroll registers, the
+    /// write toggle and the read buffer
+    pub fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.t = 0;
+        self.x = 0;
+        self.w = false;
+        self.data_buffer = 0;
+    }
+
+    /// Registers, memory and timing as at power on, the frame count starts
+    /// over and the picture is black. CHR-ROM and mirroring come from the
+    /// cartridge and are kept, CHR-RAM is cleared.
+    pub fn power_on(&mut self) {
+        let mut ppu = PPU::new();
+        ppu.chr = if self.chr_ram {
+            vec![0; self.chr.len()]
+        } else {
+            std::mem::take(&mut self.chr)
+        };
+        ppu.chr_ram = self.chr_ram;
+        ppu.mirroring = self.mirroring;
+        ppu.watchpoints = std::mem::take(&mut self.watchpoints);
+        *self = ppu;
+    }
+
+    /// Returns true once for every NMI the PPU raised
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi)
+    }
+
+    /// CPU read of $2000-$3FFF
+    pub fn read_register(&mut self, address: u16) -> u8 {
+        match address & 0x0007 {
+            0x0002 => {
+                // PPUSTATUS, reading clears vblank and the write toggle
+                let value = (self.status & 0xE0) | (self.open_bus & 0x1F);
+                self.status &= !Status::VBlank;
+                self.w = false;
+                value
+            }
+            0x0004 => self.oam[self.oam_addr as usize],
+            0x0007 => {
+                // PPUDATA, buffered except for palette reads
+                let address = self.v & 0x3FFF;
+                let value = if address >= 0x3F00 {
+                    self.data_buffer = self.read_vram(address - 0x1000);
+                    self.read_vram(address)
+                } else {
+                    let value = self.data_buffer;
+                    self.data_buffer = self.read_vram(address);
+                    value
+                };
+
+                self.watchpoints.check(address, value, false);
+                self.increment_v();
+                value
+            }
+            _ => self.open_bus,
+        }
+    }
+
+    /// What [`PPU::read_register`] would return, without clearing vblank,
+    /// touching the data buffer or moving the VRAM address
+    pub fn peek_register(&self, address: u16) -> u8 {
+        match address & 0x0007 {
+            0x0002 => (self.status & 0xE0) | (self.open_bus & 0x1F),
+            0x0004 => self.oam[self.oam_addr as usize],
+            0x0007 => {
+                let address = self.v & 0x3FFF;
+                if address >= 0x3F00 {
+                    self.read_vram(address)
+                } else {
+                    self.data_buffer
+                }
+            }
+            _ => self.open_bus,
+        }
+    }
+
+    /// CPU write of $2000-$3FFF
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        self.open_bus = value;
+
+        match address & 0x0007 {
+            0x0000 => {
+                // enabling NMI during vblank raises one straight away
+                if self.ctrl & Ctrl::Nmi == 0
+                    && value & Ctrl::Nmi != 0
+                    && self.status & Status::VBlank != 0
+                {
+                    self.nmi = true;
+                }
+
+                self.ctrl = value;
+                self.t = (self.t & !0x0C00) | ((value as u16 & 0x03) << 10);
+            }
+            0x0001 => self.mask = value,
+            0x0003 => self.oam_addr = value,
+            0x0004 => {
+                self.oam[self.oam_addr as usize] = value;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            0x0005 => {
+                // PPUSCROLL, X then Y
+                if !self.w {
+                    self.t = (self.t & !0x001F) | (value as u16 >> 3);
+                    self.x = value & 0x07;
+                } else {
+                    self.t = (self.t & !0x73E0)
+                        | ((value as u16 & 0x07) << 12)
+                        | ((value as u16 & 0xF8) << 2);
+                }
+                self.w = !self.w;
+            }
+            0x0006 => {
+                // PPUADDR, high byte then low byte
+                if !self.w {
+                    self.t = (self.t & 0x00FF) | ((value as u16 & 0x3F) << 8);
+                } else {
+                    self.t = (self.t & 0xFF00) | value as u16;
+                    self.v = self.t;
+                }
+                self.w = !self.w;
+            }
+            0x0007 => {
+                self.watchpoints.check(self.v & 0x3FFF, value, true);
+                self.write_vram(self.v & 0x3FFF, value);
+                self.increment_v();
+            }
+            _ => {}
+        }
+    }
+
+    fn increment_v(&mut self) {
+        let step = if self.ctrl & Ctrl::Increment != 0 { 32 } else { 1 };
+        self.v = self.v.wrapping_add(step) & 0x7FFF;
+    }
+
+    /// Maps a nametable address to an index into `vram`
+    fn nametable_index(&self, address: u16) -> usize {
+        let address = (address - 0x2000) & 0x0FFF;
+        let table = address / 0x400;
+
+        // only 2 KiB of VRAM, four screen carts are treated as vertical
+        let physical = match self.mirroring {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertical | Mirroring::FourScreen => table % 2,
+        };
+
+        (physical * 0x400 + address % 0x400) as usize
+    }
+
+    fn palette_index(address: u16) -> usize {
+        let index = (address & 0x1F) as usize;
+
+        // sprite backdrop entries mirror the background ones
+        if index >= 0x10 && index & 0x03 == 0 {
+            index - 0x10
+        } else {
+            index
+        }
+    }
+
+    /// Reads the PPU address space, $0000-$3FFF
+    pub fn read_vram(&self, address: u16) -> u8 {
+        let address = address & 0x3FFF;
+        match address {
+            0x0000..=0x1FFF => self.chr[address as usize % self.chr.len()],
+            0x2000..=0x3EFF => self.vram[self.nametable_index(address)],
+            _ => self.palette[PPU::palette_index(address)],
+        }
+    }
+
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        let address = address & 0x3FFF;
+        match address {
+            0x0000..=0x1FFF => {
+                if self.chr_ram {
+                    let len = self.chr.len();
+                    self.chr[address as usize % len] = value;
+                }
+            }
+            0x2000..=0x3EFF => {
+                let index = self.nametable_index(address);
+                self.vram[index] = value;
+            }
+            _ => self.palette[PPU::palette_index(address)] = value,
+        }
+    }
+
+    fn rendering(&self) -> bool {
+        self.mask & (Mask::Background | Mask::Sprites) != 0
+    }
+
+    /// Advances one dot
+    pub fn tick(&mut self) {
+        let visible = self.scanline < SCREEN_HEIGHT as u16;
+
+        if visible && self.dot == 256 {
+            self.render_scanline();
+        }
+
+        if self.rendering() && (visible || self.scanline == PRE_RENDER_SCANLINE) {
+            match self.dot {
+                256 => self.increment_y(),
+                257 => self.v = (self.v & !0x041F) | (self.t & 0x041F),
+                280..=304 if self.scanline == PRE_RENDER_SCANLINE => {
+                    self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+                }
+                _ => {}
+            }
+        }
+
+        if self.dot == 1 {
+            if self.scanline == VBLANK_SCANLINE {
+                self.status |= Status::VBlank;
+                if self.ctrl & Ctrl::Nmi != 0 {
+                    self.nmi = true;
+                }
+            } else if self.scanline == PRE_RENDER_SCANLINE {
+                self.status &= !(Status::VBlank | Status::SpriteZeroHit | Status::SpriteOverflow);
+            }
+        }
+
+        self.dot += 1;
+        if self.dot == DOTS {
+            self.dot = 0;
+            self.scanline += 1;
+
+            if self.scanline == SCANLINES {
+                self.scanline = 0;
+                self.frame += 1;
+            }
+        }
+    }
+
+    /// Moves `v` down one pixel row, wrapping into the next nametable
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    /// Background pixel at screen column `x` of the current scanline, as a
+    /// palette index where 0 is transparent
+    fn background_pixel(&self, x: usize) -> u8 {
+        let fine_x = self.x as usize + x;
+
+        let mut tile_x = (self.v & 0x001F) as usize + fine_x / 8;
+        let mut nametable = ((self.v >> 10) & 0x03) as usize;
+        if tile_x >= 32 {
+            tile_x -= 32;
+            nametable ^= 1;
+        }
+        let tile_y = ((self.v >> 5) & 0x1F) as usize;
+        let fine_y = (self.v >> 12) as usize & 0x07;
+
+        let base = 0x2000 | (nametable << 10);
+        let tile = self.read_vram((base | (tile_y << 5) | tile_x) as u16) as usize;
+        let attribute = self.read_vram((base | 0x03C0 | ((tile_y >> 2) << 3) | (tile_x >> 2)) as u16);
+        let palette = (attribute >> (((tile_y & 0x02) << 1) | (tile_x & 0x02))) & 0x03;
+
+        let table = if self.ctrl & Ctrl::BackgroundTable != 0 { 0x1000 } else { 0 };
+        let pattern = table + tile * 16 + fine_y;
+        let bit = 7 - (fine_x % 8);
+        let low = (self.read_vram(pattern as u16) >> bit) & 1;
+        let high = (self.read_vram(pattern as u16 + 8) >> bit) & 1;
+
+        let color = low | (high << 1);
+        if color == 0 {
+            0
+        } else {
+            palette * 4 + color
+        }
+    }
+
+    fn sprite_height(&self) -> usize {
+        if self.ctrl & Ctrl::SpriteSize != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Color of one row of a sprite at column `column`, 0 is transparent
+    fn sprite_color(&self, sprite: usize, row: usize, column: usize) -> u8 {
+        let tile = self.oam[sprite * 4 + 1] as usize;
+        let attributes = self.oam[sprite * 4 + 2];
+        let height = self.sprite_height();
+
+        let row = if attributes & 0x80 != 0 { height - 1 - row } else { row };
+        let column = if attributes & 0x40 != 0 { column } else { 7 - column };
+
+        let pattern = if height == 16 {
+            // 8x16 sprites pick their table with bit 0 of the tile number
+            (tile & 1) * 0x1000 + (tile & 0xFE) * 16 + (row / 8) * 16 + row % 8
+        } else {
+            let table = if self.ctrl & Ctrl::SpriteTable != 0 { 0x1000 } else { 0 };
+            table + tile * 16 + row
+        };
+
+        let low = (self.read_vram(pattern as u16) >> column) & 1;
+        let high = (self.read_vram(pattern as u16 + 8) >> column) & 1;
+        low | (high << 1)
+    }
+
+    fn render_scanline(&mut self) {
+        let y = self.scanline as usize;
+        let height = self.sprite_height();
+
+        // sprite evaluation, the first 8 sprites on this line are drawn
+        let mut sprites = Vec::with_capacity(8);
+        for sprite in 0..64 {
+            let top = self.oam[sprite * 4] as usize + 1;
+            if (top..top + height).contains(&y) {
+                if sprites.len() == 8 {
+                    self.status |= Status::SpriteOverflow;
+                    break;
+                }
+                sprites.push(sprite);
+            }
+        }
+
+        for x in 0..SCREEN_WIDTH {
+            let left_column = x < 8;
+
+            let background = if self.mask & Mask::Background != 0
+                && (!left_column || self.mask & Mask::BackgroundLeft != 0)
+            {
+                self.background_pixel(x)
+            } else {
+                0
+            };
+
+            let mut sprite_pixel = None;
+            if self.mask & Mask::Sprites != 0 && (!left_column || self.mask & Mask::SpritesLeft != 0)
+            {
+                for &sprite in &sprites {
+                    let left = self.oam[sprite * 4 + 3] as usize;
+                    if !(left..left + 8).contains(&x) {
+                        continue;
+                    }
+
+                    let top = self.oam[sprite * 4] as usize + 1;
+                    let color = self.sprite_color(sprite, y - top, x - left);
+                    if color == 0 {
+                        continue;
+                    }
+
+                    if sprite == 0 && background != 0 && x != 255 {
+                        self.status |= Status::SpriteZeroHit;
+                    }
+
+                    let attributes = self.oam[sprite * 4 + 2];
+                    let behind = attributes & 0x20 != 0;
+                    sprite_pixel = Some((0x10 + (attributes & 0x03) * 4 + color, behind));
+                    break;
+                }
+            }
+
+            let index = match sprite_pixel {
+                Some((sprite, behind)) if !behind || background == 0 => sprite,
+                _ => background,
+            };
+
+            let color = self.read_vram(0x3F00 + index as u16) & 0x3F;
+            let pixel = (y * SCREEN_WIDTH + x) * 3;
+            self.framebuffer[pixel..pixel + 3].copy_from_slice(&SYSTEM_PALETTE[color as usize]);
+        }
+    }
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Savestate for PPU {
+    const TAG: [u8; 4] = *b"PPU ";
+    const VERSION: u16 = 1;
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.ctrl);
+        writer.write_u8(self.mask);
+        writer.write_u8(self.status);
+        writer.write_u8(self.oam_addr);
+        writer.write_u16(self.v);
+        writer.write_u16(self.t);
+        writer.write_u8(self.x);
+        writer.write_bool(self.w);
+        writer.write_u8(self.data_buffer);
+        writer.write_u8(self.open_bus);
+        writer.write_bytes(&self.vram);
+        writer.write_bytes(&self.palette);
+        writer.write_bytes(&self.oam);
+        // CHR-ROM comes from the cartridge, only CHR-RAM is state
+        writer.write_bytes(if self.chr_ram { &self.chr } else { &[] });
+        writer.write_u16(self.scanline);
+        writer.write_u16(self.dot);
+        writer.write_u64(self.frame);
+        writer.write_bool(self.nmi);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader, _version: u16) -> Result<(), String> {
+        let ctrl = reader.read_u8()?;
+        let mask = reader.read_u8()?;
+        let status = reader.read_u8()?;
+        let oam_addr = reader.read_u8()?;
+        let v = reader.read_u16()?;
+        let t = reader.read_u16()?;
+        let x = reader.read_u8()?;
+        let w = reader.read_bool()?;
+        let data_buffer = reader.read_u8()?;
+        let open_bus = reader.read_u8()?;
+        let vram = reader.read_bytes()?;
+        let palette = reader.read_bytes()?;
+        let oam = reader.read_bytes()?;
+        let chr_ram = reader.read_bytes()?;
+        let scanline = reader.read_u16()?;
+        let dot = reader.read_u16()?;
+        let frame = reader.read_u64()?;
+        let nmi = reader.read_bool()?;
+
+        if vram.len() != self.vram.len()
+            || palette.len() != self.palette.len()
+            || oam.len() != self.oam.len()
+            || (self.chr_ram && chr_ram.len() != self.chr.len())
+        {
+            return Err("PPU chunk doesn't match this PPU".to_string());
+        }
+
+        self.ctrl = ctrl;
+        self.mask = mask;
+        self.status = status;
+        self.oam_addr = oam_addr;
+        self.v = v;
+        self.t = t;
+        self.x = x;
+        self.w = w;
+        self.data_buffer = data_buffer;
+        self.open_bus = open_bus;
+        self.vram.copy_from_slice(vram);
+        self.palette.copy_from_slice(palette);
+        self.oam.copy_from_slice(oam);
+        if self.chr_ram {
+            self.chr.copy_from_slice(chr_ram);
+        }
+        self.scanline = scanline;
+        self.dot = dot;
+        self.frame = frame;
+        self.nmi = nmi;
+
+        Ok(())
+    }
+}
+
+// same trick as the CPU flags, bits of PPUCTRL, PPUMASK and PPUSTATUS
+#[allow(non_upper_case_globals)]
+#[allow(dead_code)]
+#[allow(non_snake_case)]
+pub mod Ctrl {
+    pub const Nametable: u8 = 0b0000_0011;
+    pub const Increment: u8 = 0b0000_0100;
+    pub const SpriteTable: u8 = 0b0000_1000;
+    pub const BackgroundTable: u8 = 0b0001_0000;
+    pub const SpriteSize: u8 = 0b0010_0000;
+    pub const Nmi: u8 = 0b1000_0000;
+}
+
+#[allow(non_upper_case_globals)]
+#[allow(dead_code)]
+#[allow(non_snake_case)]
+pub mod Mask {
+    pub const Grayscale: u8 = 0b0000_0001;
+    pub const BackgroundLeft: u8 = 0b0000_0010;
+    pub const SpritesLeft: u8 = 0b0000_0100;
+    pub const Background: u8 = 0b0000_1000;
+    pub const Sprites: u8 = 0b0001_0000;
+}
+
+#[allow(non_upper_case_globals)]
+#[allow(dead_code)]
+#[allow(non_snake_case)]
+pub mod Status {
+    pub const SpriteOverflow: u8 = 0b0010_0000;
+    pub const SpriteZeroHit: u8 = 0b0100_0000;
+    pub const VBlank: u8 = 0b1000_0000;
+}
+
+/// RGB values of the 64 colors the 2C02 can output
+#[rustfmt::skip]
+pub const SYSTEM_PALETTE: [[u8; 3]; 64] = [
+    [0x80, 0x80, 0x80], [0x00, 0x3D, 0xA6], [0x00, 0x12, 0xB0], [0x44, 0x00, 0x96],
+    [0xA1, 0x00, 0x5E], [0xC7, 0x00, 0x28], [0xBA, 0x06, 0x00], [0x8C, 0x17, 0x00],
+    [0x5C, 0x2F, 0x00], [0x10, 0x45, 0x00], [0x05, 0x4A, 0x00], [0x00, 0x47, 0x2E],
+    [0x00, 0x41, 0x66], [0x00, 0x00, 0x00], [0x05, 0x05, 0x05], [0x05, 0x05, 0x05],
+    [0xC7, 0xC7, 0xC7], [0x00, 0x77, 0xFF], [0x21, 0x55, 0xFF], [0x82, 0x37, 0xFA],
+    [0xEB, 0x2F, 0xB5], [0xFF, 0x29, 0x50], [0xFF, 0x22, 0x00], [0xD6, 0x32, 0x00],
+    [0xC4, 0x62, 0x00], [0x35, 0x80, 0x00], [0x05, 0x8F, 0x00], [0x00, 0x8A, 0x55],
+    [0x00, 0x99, 0xCC], [0x21, 0x21, 0x21], [0x09, 0x09, 0x09], [0x09, 0x09, 0x09],
+    [0xFF, 0xFF, 0xFF], [0x0F, 0xD7, 0xFF], [0x69, 0xA2, 0xFF], [0xD4, 0x80, 0xFF],
+    [0xFF, 0x45, 0xF3], [0xFF, 0x61, 0x8B], [0xFF, 0x88, 0x33], [0xFF, 0x9C, 0x12],
+    [0xFA, 0xBC, 0x20], [0x9F, 0xE3, 0x0E], [0x2B, 0xF0, 0x35], [0x0C, 0xF0, 0xA4],
+    [0x05, 0xFB, 0xFF], [0x5E, 0x5E, 0x5E], [0x0D, 0x0D, 0x0D], [0x0D, 0x0D, 0x0D],
+    [0xFF, 0xFF, 0xFF], [0xA6, 0xFC, 0xFF], [0xB3, 0xEC, 0xFF], [0xDA, 0xAB, 0xEB],
+    [0xFF, 0xA8, 0xF9], [0xFF, 0xAB, 0xB3], [0xFF, 0xD2, 0xB0], [0xFF, 0xEF, 0xA6],
+    [0xFF, 0xF7, 0x9C], [0xD7, 0xE8, 0x95], [0xA6, 0xED, 0xAF], [0xA2, 0xF2, 0xDA],
+    [0x99, 0xFF, 0xFC], [0xDD, 0xDD, 0xDD], [0x11, 0x11, 0x11], [0x11, 0x11, 0x11],
+];
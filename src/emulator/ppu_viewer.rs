@@ -0,0 +1,189 @@
+//! Images of PPU state for the graphics viewers, decoded from pattern,
+//! nametable and OAM memory rather than taken from the screen. Pixels are
+//! palette RAM indices, see [`color`] to turn them into RGB.
+
+use super::ppu::{Ctrl, PPU, SYSTEM_PALETTE};
+
+/// A pattern table is 16x16 tiles
+pub const PATTERN_TABLE_SIZE: usize = 128;
+/// The four nametables, two across and two down
+pub const NAMETABLES_WIDTH: usize = 512;
+pub const NAMETABLES_HEIGHT: usize = 480;
+
+/// Colors 0-3 of one row of a tile
+fn tile_row(ppu: &PPU, tile_address: usize, row: usize) -> [u8; 8] {
+    let low = ppu.read_vram((tile_address + row) as u16);
+    let high = ppu.read_vram((tile_address + row + 8) as u16);
+
+    let mut colors = [0; 8];
+    for (column, color) in colors.iter_mut().enumerate() {
+        let bit = 7 - column;
+        *color = ((low >> bit) & 1) | (((high >> bit) & 1) << 1);
+    }
+    colors
+}
+
+/// Pattern table 0 or 1 as 128x128 palette RAM indices, colored with
+/// palette 0-7
+pub fn pattern_table(ppu: &PPU, table: usize, palette: u8) -> Vec<u8> {
+    let mut pixels = vec![0; PATTERN_TABLE_SIZE * PATTERN_TABLE_SIZE];
+
+    for tile in 0..256 {
+        let address = table * 0x1000 + tile * 16;
+        let (left, top) = (tile % 16 * 8, tile / 16 * 8);
+
+        for row in 0..8 {
+            let colors = tile_row(ppu, address, row);
+            let start = (top + row) * PATTERN_TABLE_SIZE + left;
+            for (pixel, color) in pixels[start..start + 8].iter_mut().zip(colors) {
+                *pixel = palette * 4 + color;
+            }
+        }
+    }
+
+    pixels
+}
+
+/// All four nametables as 512x480 palette RAM indices, using the
+/// background pattern table selected in PPUCTRL
+pub fn nametables(ppu: &PPU) -> Vec<u8> {
+    let mut pixels = vec![0; NAMETABLES_WIDTH * NAMETABLES_HEIGHT];
+    let table = if ppu.ctrl & Ctrl::BackgroundTable != 0 {
+        0x1000
+    } else {
+        0
+    };
+
+    for nametable in 0..4 {
+        let base = 0x2000 + nametable * 0x400;
+        let (left, top) = (nametable % 2 * 256, nametable / 2 * 240);
+
+        for tile_y in 0..30 {
+            for tile_x in 0..32 {
+                let tile = ppu.read_vram((base + tile_y * 32 + tile_x) as u16) as usize;
+                let attribute = ppu.read_vram((base + 0x03C0 + tile_y / 4 * 8 + tile_x / 4) as u16);
+                let shift = ((tile_y & 0x02) << 1) | (tile_x & 0x02);
+                let palette = (attribute >> shift) & 0x03;
+
+                for row in 0..8 {
+                    let colors = tile_row(ppu, table + tile * 16, row);
+                    let start = (top + tile_y * 8 + row) * NAMETABLES_WIDTH + left + tile_x * 8;
+                    for (pixel, color) in pixels[start..start + 8].iter_mut().zip(colors) {
+                        // color 0 of every palette is the backdrop
+                        *pixel = if color == 0 { 0 } else { palette * 4 + color };
+                    }
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+/// Top left of the screen within the nametables, from the scroll the next
+/// frame starts with
+pub fn scroll(ppu: &PPU) -> (usize, usize) {
+    let t = ppu.t as usize;
+    let coarse_x = t & 0x1F;
+    let coarse_y = (t >> 5) & 0x1F;
+    let nametable = (t >> 10) & 0x03;
+    let fine_y = (t >> 12) & 0x07;
+
+    let x = (nametable & 1) * 256 + coarse_x * 8 + ppu.x as usize;
+    let y = (nametable >> 1) * 240 + coarse_y * 8 + fine_y;
+    (x, y)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sprite {
+    pub x: u8,
+    /// One less than the first line the sprite is drawn on
+    pub y: u8,
+    pub tile: u8,
+    pub attributes: u8,
+}
+
+impl Sprite {
+    pub fn palette(&self) -> u8 {
+        self.attributes & 0x03
+    }
+
+    pub fn behind_background(&self) -> bool {
+        self.attributes & 0x20 != 0
+    }
+
+    pub fn flip_x(&self) -> bool {
+        self.attributes & 0x40 != 0
+    }
+
+    pub fn flip_y(&self) -> bool {
+        self.attributes & 0x80 != 0
+    }
+}
+
+pub fn sprites(ppu: &PPU) -> Vec<Sprite> {
+    ppu.oam
+        .chunks_exact(4)
+        .map(|bytes| Sprite {
+            y: bytes[0],
+            tile: bytes[1],
+            attributes: bytes[2],
+            x: bytes[3],
+        })
+        .collect()
+}
+
+/// 8 or 16, from PPUCTRL
+pub fn sprite_height(ppu: &PPU) -> usize {
+    if ppu.ctrl & Ctrl::SpriteSize != 0 {
+        16
+    } else {
+        8
+    }
+}
+
+/// A sprite as 8 pixel wide rows of palette RAM indices, unflipped.
+/// Transparent pixels are 0.
+pub fn sprite_pixels(ppu: &PPU, sprite: &Sprite) -> Vec<u8> {
+    let height = sprite_height(ppu);
+    let tile = sprite.tile as usize;
+
+    let address = if height == 16 {
+        // 8x16 sprites pick their table with bit 0 of the tile number
+        (tile & 1) * 0x1000 + (tile & 0xFE) * 16
+    } else {
+        let table = if ppu.ctrl & Ctrl::SpriteTable != 0 {
+            0x1000
+        } else {
+            0
+        };
+        table + tile * 16
+    };
+
+    let mut pixels = Vec::with_capacity(8 * height);
+    for row in 0..height {
+        // the bottom half of an 8x16 sprite is the next tile
+        let colors = tile_row(ppu, address + row / 8 * 16, row % 8);
+        pixels.extend(colors.iter().map(|&color| {
+            if color == 0 {
+                0
+            } else {
+                0x10 + sprite.palette() * 4 + color
+            }
+        }));
+    }
+
+    pixels
+}
+
+/// RGB of a palette RAM index
+pub fn color(palette: &[u8; 32], index: u8) -> [u8; 3] {
+    let index = index as usize & 0x1F;
+    // color 0 of the sprite palettes mirrors the background ones
+    let index = if index >= 0x10 && index.is_multiple_of(4) {
+        index - 0x10
+    } else {
+        index
+    };
+    SYSTEM_PALETTE[palette[index] as usize & 0x3F]
+}
@@ -1,15 +1,114 @@
-use std::ops::{Index, IndexMut, Range};
+use std::{
+    cell::RefCell,
+    ops::{Index, IndexMut, Range},
+    rc::Rc,
+};
+
+use super::{
+    cartridge::PRG_RAM_WINDOW,
+    cheats::RomPatch,
+    controller::Controller,
+    debugger::Watchpoints,
+    ppu::PPU,
+    state::{Savestate, StateReader, StateWriter},
+};
+
+/// Internal RAM, without its mirrors
+pub const INTERNAL_RAM: usize = 0x800;
+
+/// What internal RAM holds at power on. Real consoles power up with
+/// semi-random RAM, the patterns catch games that read it uninitialized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RamInit {
+    #[default]
+    Zeros,
+    /// Every byte $FF
+    Ones,
+    /// Four bytes of $00 then four of $FF, what many consoles power up with
+    Alternating,
+    /// Bytes from a seed, the same seed always gives the same bytes
+    Random(u64),
+}
+
+impl RamInit {
+    /// Parses `zeros`, `ff`, `alternating` or `random:SEED`
+    pub fn parse(text: &str) -> Result<RamInit, String> {
+        match text {
+            "zeros" => Ok(RamInit::Zeros),
+            "ff" => Ok(RamInit::Ones),
+            "alternating" => Ok(RamInit::Alternating),
+            _ => text
+                .strip_prefix("random:")
+                .and_then(|seed| seed.parse().ok())
+                .map(RamInit::Random)
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown RAM init, expected zeros, ff, alternating or random:SEED: {}",
+                        text
+                    )
+                }),
+        }
+    }
+
+    pub fn fill(self, bytes: &mut [u8]) {
+        match self {
+            RamInit::Zeros => bytes.fill(0),
+            RamInit::Ones => bytes.fill(0xFF),
+            RamInit::Alternating => {
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = if i & 4 == 0 { 0x00 } else { 0xFF };
+                }
+            }
+            RamInit::Random(seed) => {
+                // splitmix64
+                let mut state = seed;
+                for chunk in bytes.chunks_mut(8) {
+                    state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                    let mut z = state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                    z ^= z >> 31;
+                    chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+                }
+            }
+        }
+    }
+}
 
 pub struct RAM {
-    pub array: [u8; 0xFFFF],
+    pub array: [u8; 0x10000],
+    /// Cartridge PRG-RAM, the first 8 KiB are mapped to $6000-$7FFF
+    pub prg_ram: Vec<u8>,
+    /// PRG-RAM is battery backed and kept across power cycles
+    pub battery: bool,
+    pub ppu: Rc<RefCell<PPU>>,
+    pub controllers: [Controller; 2],
+    /// Treat all 64 KiB as plain memory, for CPU tests that don't expect a NES
+    pub flat: bool,
+    /// Records every bus read and write while set
+    pub bus_log: Option<Vec<BusAccess>>,
+    pub watchpoints: Watchpoints,
+    /// Enabled Game Genie codes, see [`cheats`](super::cheats)
+    pub rom_patches: Vec<RomPatch>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusAccess {
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
 }
 
 impl IndexMut<u16> for RAM {
     fn index_mut(&mut self, index: u16) -> &mut Self::Output {
+        if self.flat {
+            return &mut self.array[index as usize];
+        }
+
         match index {
             0x0000..=0x1FFF => {
-                // CPU ram access
-                &mut self.array[index as usize]
+                // CPU ram access, mirrored every 2 KiB
+                &mut self.array[index as usize % INTERNAL_RAM]
             },
             0x2000..=0x401F => {
                 // IO registers
@@ -21,7 +120,7 @@ impl IndexMut<u16> for RAM {
             },
             0x6000..=0x7FFF => {
                 // Save RAM
-                unimplemented!("SRAM not implemented")
+                &mut self.prg_ram[(index - 0x6000) as usize]
             },
             0x8000..=0xFFFF => {
                 // PRG-ROM
@@ -36,10 +135,14 @@ impl Index<u16> for RAM {
     type Output = u8;
 
     fn index(&self, index: u16) -> &Self::Output {
+        if self.flat {
+            return &self.array[index as usize];
+        }
+
         match index {
             0x0000..=0x1FFF => {
-                // CPU ram access
-                &self.array[index as usize]
+                // CPU ram access, mirrored every 2 KiB
+                &self.array[index as usize % INTERNAL_RAM]
             },
             0x2000..=0x401F => {
                 // IO registers
@@ -51,7 +154,7 @@ impl Index<u16> for RAM {
             },
             0x6000..=0x7FFF => {
                 // Save RAM
-                unimplemented!("SRAM not implemented")
+                &self.prg_ram[(index - 0x6000) as usize]
             },
             0x8000..=0xFFFF => {
                 // PRG-ROM
@@ -66,8 +169,9 @@ impl IndexMut<Range<u16>> for RAM {
     fn index_mut(&mut self, index: Range<u16>) -> &mut Self::Output {
         match index.start {
             0x0000..=0x1FFF => {
-                // CPU ram access
-                &mut self.array[index.start as usize..index.end as usize]
+                // CPU ram access, mirrored every 2 KiB
+                let start = index.start as usize % INTERNAL_RAM;
+                &mut self.array[start..start + index.len()]
             },
             0x2000..=0x401F => {
                 // IO registers
@@ -79,7 +183,7 @@ impl IndexMut<Range<u16>> for RAM {
             },
             0x6000..=0x7FFF => {
                 // Save RAM
-                unimplemented!("SRAM not implemented")
+                &mut self.prg_ram[(index.start - 0x6000) as usize..(index.end - 0x6000) as usize]
             },
             0x8000..=0xFFFF => {
                 // PRG-ROM
@@ -96,8 +200,9 @@ impl Index<Range<u16>> for RAM {
     fn index(&self, index: Range<u16>) -> &Self::Output {
         match index.start {
             0x0000..=0x1FFF => {
-                // CPU ram access
-                &self.array[index.start as usize..index.end as usize]
+                // CPU ram access, mirrored every 2 KiB
+                let start = index.start as usize % INTERNAL_RAM;
+                &self.array[start..start + index.len()]
             },
             0x2000..=0x401F => {
                 // IO registers
@@ -109,7 +214,7 @@ impl Index<Range<u16>> for RAM {
             },
             0x6000..=0x7FFF => {
                 // Save RAM
-                unimplemented!("SRAM not implemented")
+                &self.prg_ram[(index.start - 0x6000) as usize..(index.end - 0x6000) as usize]
             },
             0x8000..=0xFFFF => {
                 // PRG-ROM
@@ -121,9 +226,126 @@ impl Index<Range<u16>> for RAM {
 }
 
 impl RAM {
-    pub fn new() -> Self {
+    pub fn new(ppu: Rc<RefCell<PPU>>) -> Self {
         RAM {
-            array: [0; 0xFFFF],
+            array: [0; 0x10000],
+            prg_ram: vec![0; PRG_RAM_WINDOW],
+            battery: false,
+            ppu,
+            controllers: [Controller::new(); 2],
+            flat: false,
+            bus_log: None,
+            watchpoints: Watchpoints::default(),
+            rom_patches: Vec::new(),
+        }
+    }
+
+    /// Fills internal RAM, and PRG-RAM without a battery, the way `init`
+    /// says, as at power on
+    pub fn power_on(&mut self, init: RamInit) {
+        init.fill(&mut self.array[..INTERNAL_RAM]);
+        if !self.battery {
+            init.fill(&mut self.prg_ram);
+        }
+    }
+
+    /// CPU bus read, IO registers are routed to their devices and may have
+    /// side effects, everything else reads memory
+    pub fn read(&mut self, address: u16) -> u8 {
+        let value = match address {
+            _ if self.flat => self[address],
+            0x2000..=0x3FFF => self.ppu.borrow_mut().read_register(address),
+            0x4016 => self.controllers[0].read(),
+            0x4017 => self.controllers[1].read(),
+            // APU and other IO registers aren't emulated yet
+            0x4000..=0x401F => 0,
+            // nothing is mapped in the expansion area
+            0x4020..=0x5FFF => 0xFF,
+            0x8000..=0xFFFF if !self.rom_patches.is_empty() => self.read_rom(address),
+            _ => self[address],
+        };
+
+        if let Some(log) = &mut self.bus_log {
+            log.push(BusAccess {
+                address,
+                value,
+                write: false,
+            });
+        }
+        self.watchpoints.check(address, value, false);
+
+        value
+    }
+
+    /// Returns what [`RAM::read`] would, without side effects, for debugging
+    /// tools. Peeking $2002 doesn't clear vblank, $2007 doesn't move the
+    /// VRAM address and $4016 doesn't shift the controller. The expansion
+    /// area has nothing mapped and reads as $FF.
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            _ if self.flat => self[address],
+            0x2000..=0x3FFF => self.ppu.borrow().peek_register(address),
+            0x4016 => self.controllers[0].peek(),
+            0x4017 => self.controllers[1].peek(),
+            // the APU isn't emulated, its registers read as 0 too
+            0x4000..=0x401F => 0,
+            0x4020..=0x5FFF => 0xFF,
+            0x8000..=0xFFFF if !self.rom_patches.is_empty() => self.read_rom(address),
+            _ => self[address],
+        }
+    }
+
+    /// ROM with the first Game Genie patch that applies to `address`
+    fn read_rom(&self, address: u16) -> u8 {
+        let value = self[address];
+        self.rom_patches
+            .iter()
+            .find(|patch| patch.address == address && patch.applies(value))
+            .map_or(value, |patch| patch.value)
+    }
+
+    /// Little endian [`RAM::peek`] of two bytes, wrapping at the end of memory
+    pub fn peek_u16(&self, address: u16) -> u16 {
+        u16::from_le_bytes([self.peek(address), self.peek(address.wrapping_add(1))])
+    }
+
+    /// CPU bus write, see [`RAM::read`]
+    pub fn write(&mut self, address: u16, value: u8) {
+        if let Some(log) = &mut self.bus_log {
+            log.push(BusAccess {
+                address,
+                value,
+                write: true,
+            });
+        }
+        self.watchpoints.check(address, value, true);
+
+        match address {
+            _ if self.flat => self[address] = value,
+            0x2000..=0x3FFF => self.ppu.borrow_mut().write_register(address, value),
+            0x4014 => {
+                // OAM DMA, copies a page of CPU memory into OAM
+                let page = (value as u16) << 8;
+                let mut bytes = [0; 256];
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = self.read(page + i as u16);
+                }
+
+                let mut ppu = self.ppu.borrow_mut();
+                for (i, byte) in bytes.iter().enumerate() {
+                    let oam_addr = ppu.oam_addr.wrapping_add(i as u8);
+                    ppu.oam[oam_addr as usize] = *byte;
+                }
+            }
+            0x4016 => {
+                // both controllers share the strobe line
+                self.controllers[0].write(value);
+                self.controllers[1].write(value);
+            }
+            // APU and other IO registers aren't emulated yet, nothing is
+            // mapped in the expansion area
+            0x4000..=0x5FFF => {}
+            _ => self[address] = value,
         }
     }
 
@@ -141,6 +363,55 @@ impl RAM {
     }
 
     pub fn reset(&mut self) {
-        self.array = [0; 0xFFFF];
+        self.array = [0; 0x10000];
+    }
+}
+
+impl Savestate for RAM {
+    const TAG: [u8; 4] = *b"RAM ";
+    const VERSION: u16 = 2;
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.array);
+        writer.write_bytes(&self.prg_ram);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader, version: u16) -> Result<(), String> {
+        let bytes = reader.read_bytes()?;
+
+        // version 1 stored one byte less memory and no PRG-RAM
+        let expected = if version == 1 { 0xFFFF } else { self.array.len() };
+        if bytes.len() != expected {
+            return Err(format!(
+                "RAM chunk is {} bytes, expected {}",
+                bytes.len(),
+                expected
+            ));
+        }
+
+        let prg_ram = if version >= 2 {
+            Some(reader.read_bytes()?)
+        } else {
+            None
+        };
+
+        // the cartridge decides the PRG-RAM size, a state for another one
+        // would leave $6000-$7FFF partly unbacked
+        if let Some(prg_ram) = prg_ram {
+            if prg_ram.len() != self.prg_ram.len() {
+                return Err(format!(
+                    "PRG-RAM is {} bytes, expected {}",
+                    prg_ram.len(),
+                    self.prg_ram.len()
+                ));
+            }
+        }
+
+        self.array[..bytes.len()].copy_from_slice(bytes);
+        if let Some(prg_ram) = prg_ram {
+            self.prg_ram.copy_from_slice(prg_ram);
+        }
+
+        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,192 @@
+//! RAM search, for finding where a game keeps a value. Every address of
+//! internal RAM and PRG-RAM starts out as a candidate, each filter keeps the
+//! ones whose value compares as asked to the last snapshot or a given value.
+
+use std::ops::RangeInclusive;
+
+use super::{cartridge::PRG_RAM_WINDOW, ram::INTERNAL_RAM, Emulator};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    Byte,
+    /// Two bytes, little endian
+    Word,
+}
+
+impl Size {
+    /// Values a candidate of this size can have
+    pub fn range(self, signed: bool) -> RangeInclusive<i64> {
+        match (self, signed) {
+            (Size::Byte, false) => 0..=0xFF,
+            (Size::Byte, true) => -0x80..=0x7F,
+            (Size::Word, false) => 0..=0xFFFF,
+            (Size::Word, true) => -0x8000..=0x7FFF,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    pub const ALL: [Comparison; 6] = [
+        Comparison::Equal,
+        Comparison::NotEqual,
+        Comparison::Less,
+        Comparison::Greater,
+        Comparison::LessOrEqual,
+        Comparison::GreaterOrEqual,
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::Greater => ">",
+            Comparison::LessOrEqual => "<=",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+
+    pub fn holds(self, left: i64, right: i64) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::Greater => left > right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+/// What candidates are compared to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    /// Their value in the last snapshot, e.g. `NotEqual` keeps the ones
+    /// that changed
+    Previous,
+    Value(i64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    pub address: u16,
+    pub previous: i64,
+    pub current: i64,
+}
+
+pub struct RamSearch {
+    pub size: Size,
+    pub signed: bool,
+    /// Searched memory as of the last filter, see [`snapshot`]
+    previous: Vec<u8>,
+    /// Offsets into the snapshot
+    candidates: Vec<usize>,
+}
+
+/// Internal RAM followed by the PRG-RAM mapped at $6000
+fn snapshot(emulator: &Emulator) -> Vec<u8> {
+    let memory = emulator.memory.borrow();
+    let prg_ram = &memory.prg_ram[..memory.prg_ram.len().min(PRG_RAM_WINDOW)];
+
+    let mut bytes = memory.array[..INTERNAL_RAM].to_vec();
+    bytes.extend_from_slice(prg_ram);
+    bytes
+}
+
+/// CPU address of a snapshot offset
+fn address(offset: usize) -> u16 {
+    if offset < INTERNAL_RAM {
+        offset as u16
+    } else {
+        (0x6000 + offset - INTERNAL_RAM) as u16
+    }
+}
+
+impl RamSearch {
+    /// Starts a search with every address as a candidate
+    pub fn new(emulator: &Emulator, size: Size, signed: bool) -> Self {
+        let previous = snapshot(emulator);
+
+        // words don't straddle internal RAM and PRG-RAM
+        let candidates = (0..previous.len())
+            .filter(|&offset| {
+                size == Size::Byte || (offset != INTERNAL_RAM - 1 && offset != previous.len() - 1)
+            })
+            .collect();
+
+        RamSearch {
+            size,
+            signed,
+            previous,
+            candidates,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    fn value(&self, bytes: &[u8], offset: usize) -> i64 {
+        match (self.size, self.signed) {
+            (Size::Byte, false) => bytes[offset] as i64,
+            (Size::Byte, true) => bytes[offset] as i8 as i64,
+            (Size::Word, signed) => {
+                let word = u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+                if signed {
+                    word as i16 as i64
+                } else {
+                    word as i64
+                }
+            }
+        }
+    }
+
+    /// Keeps the candidates whose current value compares to `operand` as
+    /// asked, then takes a new snapshot
+    pub fn filter(&mut self, emulator: &Emulator, comparison: Comparison, operand: Operand) {
+        let current = snapshot(emulator);
+
+        let candidates = std::mem::take(&mut self.candidates);
+        self.candidates = candidates
+            .into_iter()
+            .filter(|&offset| {
+                let right = match operand {
+                    Operand::Previous => self.value(&self.previous, offset),
+                    Operand::Value(value) => value,
+                };
+                comparison.holds(self.value(&current, offset), right)
+            })
+            .collect();
+
+        self.previous = current;
+    }
+
+    /// Up to `limit` candidates with their snapshot and current values
+    pub fn candidates(&self, emulator: &Emulator, limit: usize) -> Vec<Candidate> {
+        let current = snapshot(emulator);
+
+        self.candidates
+            .iter()
+            .take(limit)
+            .map(|&offset| Candidate {
+                address: address(offset),
+                previous: self.value(&self.previous, offset),
+                current: self.value(&current, offset),
+            })
+            .collect()
+    }
+}
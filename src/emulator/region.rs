@@ -0,0 +1,125 @@
+//! Memory regions debugging tools can view and edit. Everything here reads
+//! and writes the underlying storage directly, so looking at a register or
+//! patching a byte never has side effects.
+
+use super::Emulator;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Region {
+    /// The CPU address space, see [`RAM::peek`](super::ram::RAM::peek) for
+    /// how IO registers are shown
+    Cpu,
+    /// The PPU address space, $0000-$3FFF
+    Ppu,
+    Oam,
+    Palette,
+    /// PRG-ROM as mapped at $8000-$FFFF
+    PrgRom,
+    Chr,
+    /// Cartridge PRG-RAM, battery backed on some carts
+    PrgRam,
+}
+
+impl Region {
+    pub const ALL: [Region; 7] = [
+        Region::Cpu,
+        Region::Ppu,
+        Region::Oam,
+        Region::Palette,
+        Region::PrgRom,
+        Region::Chr,
+        Region::PrgRam,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Cpu => "CPU",
+            Region::Ppu => "PPU",
+            Region::Oam => "OAM",
+            Region::Palette => "Palette",
+            Region::PrgRom => "PRG-ROM",
+            Region::Chr => "CHR",
+            Region::PrgRam => "PRG-RAM",
+        }
+    }
+}
+
+impl Emulator {
+    /// Size of the region in bytes
+    pub fn region_len(&self, region: Region) -> usize {
+        match region {
+            Region::Cpu => 0x10000,
+            Region::Ppu => 0x4000,
+            Region::Oam => 256,
+            Region::Palette => 32,
+            Region::PrgRom => 0x8000,
+            Region::Chr => self.ppu.borrow().chr.len(),
+            Region::PrgRam => self.memory.borrow().prg_ram.len(),
+        }
+    }
+
+    /// Reads a byte of a region without side effects. Offsets past the end
+    /// read as 0.
+    pub fn peek_region(&self, region: Region, offset: usize) -> u8 {
+        if offset >= self.region_len(region) {
+            return 0;
+        }
+
+        match region {
+            Region::Cpu => self.memory.borrow().peek(offset as u16),
+            Region::Ppu => self.ppu.borrow().read_vram(offset as u16),
+            Region::Oam => self.ppu.borrow().oam[offset],
+            Region::Palette => self.ppu.borrow().palette[offset],
+            Region::PrgRom => self.memory.borrow().array[0x8000 + offset],
+            Region::Chr => self.ppu.borrow().chr[offset],
+            Region::PrgRam => self.memory.borrow().prg_ram[offset],
+        }
+    }
+
+    /// Writes a byte of a region directly, ROMs included. Writes to IO
+    /// registers and offsets past the end are ignored.
+    pub fn poke_region(&mut self, region: Region, offset: usize, value: u8) {
+        if offset >= self.region_len(region) {
+            return;
+        }
+
+        match region {
+            Region::Cpu => {
+                let mut memory = self.memory.borrow_mut();
+                let address = offset as u16;
+                if memory.flat || !(0x2000..=0x5FFF).contains(&address) {
+                    memory[address] = value;
+                }
+            }
+            Region::Ppu => self.ppu.borrow_mut().write_vram(offset as u16, value),
+            Region::Oam => self.ppu.borrow_mut().oam[offset] = value,
+            Region::Palette => self.ppu.borrow_mut().palette[offset] = value,
+            Region::PrgRom => self.memory.borrow_mut().array[0x8000 + offset] = value,
+            Region::Chr => self.ppu.borrow_mut().chr[offset] = value,
+            Region::PrgRam => self.memory.borrow_mut().prg_ram[offset] = value,
+        }
+    }
+
+    /// Peeks `len` bytes from `start`, stopping at the end of the region
+    pub fn peek_region_range(&self, region: Region, start: usize, len: usize) -> Vec<u8> {
+        let end = (start + len).min(self.region_len(region));
+        (start..end)
+            .map(|offset| self.peek_region(region, offset))
+            .collect()
+    }
+
+    /// Finds `pattern` in a region, starting at `from` and wrapping around
+    /// to the start. Returns the offset of the first match.
+    pub fn search_region(&self, region: Region, pattern: &[u8], from: usize) -> Option<usize> {
+        let len = self.region_len(region);
+        if pattern.is_empty() || pattern.len() > len {
+            return None;
+        }
+
+        let bytes = self.peek_region_range(region, 0, len);
+        let starts = len - pattern.len() + 1;
+        (0..starts)
+            .map(|i| (from + i) % starts)
+            .find(|&start| bytes[start..].starts_with(pattern))
+    }
+}
@@ -0,0 +1,239 @@
+//! Rewind buffer of delta compressed save states.
+//!
+//! Only the newest snapshot is kept whole. Every older snapshot is stored as
+//! the XOR of itself and the snapshot after it, run length encoded. Most of
+//! the machine doesn't change between snapshots so the XOR is almost all
+//! zeros and compresses down to a few bytes. Stepping back XORs the newest
+//! snapshot with the last delta, which gives the snapshot before it.
+
+use std::collections::VecDeque;
+
+use super::{Emulator, FRAMES_PER_SECOND};
+
+pub struct RewindConfig {
+    /// How far back gameplay can be rewound
+    pub seconds: u32,
+    /// Frames between two snapshots
+    pub interval: u32,
+    /// Memory cap for all snapshots, the oldest are dropped first
+    pub max_bytes: usize,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        RewindConfig {
+            seconds: 30,
+            interval: 6,
+            max_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
+
+pub struct Rewind {
+    config: RewindConfig,
+    /// Newest snapshot, uncompressed
+    latest: Option<Vec<u8>>,
+    /// Compressed deltas, oldest first
+    deltas: VecDeque<Vec<u8>>,
+    /// Total size of `latest` and `deltas`
+    bytes: usize,
+    /// Frames since the last snapshot, or rewound frames since the last
+    /// restored snapshot while rewinding
+    frames: f32,
+    /// Snapshots restored per `interval` frames while rewinding
+    pub speed: f32,
+}
+
+impl Rewind {
+    pub fn new(config: RewindConfig) -> Self {
+        Rewind {
+            config,
+            latest: None,
+            deltas: VecDeque::new(),
+            bytes: 0,
+            frames: 0.0,
+            speed: 1.0,
+        }
+    }
+
+    /// Maximum number of snapshots kept, including the newest one
+    pub fn capacity(&self) -> usize {
+        (self.config.seconds * FRAMES_PER_SECOND / self.config.interval.max(1)).max(1) as usize
+    }
+
+    /// Number of snapshots that can be stepped back through
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.latest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Bytes used by all snapshots
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.bytes = 0;
+        self.frames = 0.0;
+    }
+
+    /// Adds a new snapshot, dropping the oldest ones if the buffer is full
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(latest) = self.latest.take() {
+            let delta = encode_delta(&latest, &state);
+            self.bytes += delta.len();
+            self.bytes -= latest.len();
+            self.deltas.push_back(delta);
+        }
+
+        self.bytes += state.len();
+        self.latest = Some(state);
+
+        while !self.deltas.is_empty()
+            && (self.len() > self.capacity() || self.bytes > self.config.max_bytes)
+        {
+            let oldest = self.deltas.pop_front().unwrap();
+            self.bytes -= oldest.len();
+        }
+    }
+
+    /// Removes and returns the newest snapshot
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let latest = self.latest.take()?;
+        self.bytes -= latest.len();
+
+        if let Some(delta) = self.deltas.pop_back() {
+            self.bytes -= delta.len();
+
+            let previous = decode_delta(&latest, &delta);
+            self.bytes += previous.len();
+            self.latest = Some(previous);
+        }
+
+        Some(latest)
+    }
+
+    /// Call once per emulated frame, takes a snapshot every `interval` frames
+    pub fn on_frame(&mut self, emulator: &Emulator) {
+        self.frames += 1.0;
+
+        if self.frames >= self.config.interval as f32 {
+            self.frames = 0.0;
+            self.push(emulator.save_state());
+        }
+    }
+
+    /// Call once per frame while rewinding instead of running the emulator,
+    /// restores older snapshots at `speed`. Returns false once there is
+    /// nothing left to rewind.
+    pub fn rewind_frame(&mut self, emulator: &mut Emulator) -> Result<bool, String> {
+        self.frames += self.speed;
+
+        while self.frames >= self.config.interval as f32 {
+            self.frames -= self.config.interval as f32;
+
+            // keep the oldest snapshot around so holding the key at the end
+            // of the buffer stays there instead of running again
+            let state = if self.len() > 1 {
+                self.pop()
+            } else {
+                self.latest.clone()
+            };
+
+            match state {
+                Some(state) => emulator.load_state(&state)?,
+                None => return Ok(false),
+            }
+        }
+
+        Ok(self.len() > 1)
+    }
+}
+
+/// XORs `older` against `newer` and run length encodes the zeros. The result
+/// is a list of (zero run, literal count, literals) with LEB128 counts,
+/// prefixed by the length of `older`.
+pub fn encode_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let len = older.len().max(newer.len());
+    let xor: Vec<u8> = (0..len)
+        .map(|i| older.get(i).copied().unwrap_or(0) ^ newer.get(i).copied().unwrap_or(0))
+        .collect();
+
+    let mut out = Vec::new();
+    write_varint(&mut out, older.len());
+
+    let mut i = 0;
+    while i < xor.len() {
+        let zeros = xor[i..].iter().take_while(|&&byte| byte == 0).count();
+        i += zeros;
+
+        // a literal run ends at the next pair of zeros, single zeros are
+        // cheaper to keep inline than to start a new run for
+        let start = i;
+        while i < xor.len() && (xor[i] != 0 || xor.get(i + 1).is_some_and(|&b| b != 0)) {
+            i += 1;
+        }
+
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, i - start);
+        out.extend_from_slice(&xor[start..i]);
+    }
+
+    out
+}
+
+/// Undoes [`encode_delta`], turning `newer` back into `older`
+pub fn decode_delta(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let older_len = read_varint(delta, &mut pos);
+
+    let mut xor = Vec::with_capacity(older_len.max(newer.len()));
+    while pos < delta.len() {
+        let zeros = read_varint(delta, &mut pos);
+        xor.resize(xor.len() + zeros, 0);
+
+        let literals = read_varint(delta, &mut pos);
+        xor.extend_from_slice(&delta[pos..pos + literals]);
+        pos += literals;
+    }
+
+    (0..older_len)
+        .map(|i| newer.get(i).copied().unwrap_or(0) ^ xor.get(i).copied().unwrap_or(0))
+        .collect()
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+
+        value |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return value;
+        }
+    }
+}
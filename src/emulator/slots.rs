@@ -0,0 +1,162 @@
+//! Numbered save state slots stored on disk next to the ROM.
+//!
+//! A slot file is a regular save state with an extra `SLOT` chunk holding
+//! the time it was saved and a small thumbnail of the screen. The emulator
+//! skips chunks it doesn't know, so slot files load like any other state.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    battery::write_atomic,
+    state::{self, Savestate, StateReader, StateWriter},
+};
+
+pub const SLOT_COUNT: usize = 10;
+
+pub const THUMBNAIL_WIDTH: usize = 64;
+pub const THUMBNAIL_HEIGHT: usize = 60;
+
+/// RGB8 image, row major
+#[derive(Clone, Default)]
+pub struct Thumbnail {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Thumbnail {
+    /// Box filters an RGB8 image of any size down to the thumbnail size
+    pub fn downscale(pixels: &[u8], width: usize, height: usize) -> Self {
+        assert_eq!(pixels.len(), width * height * 3);
+
+        let mut thumbnail = Vec::with_capacity(THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 3);
+
+        for ty in 0..THUMBNAIL_HEIGHT {
+            // source rows and columns covered by this thumbnail pixel, at least one
+            let y0 = ty * height / THUMBNAIL_HEIGHT;
+            let y1 = ((ty + 1) * height / THUMBNAIL_HEIGHT)
+                .max(y0 + 1)
+                .min(height);
+
+            for tx in 0..THUMBNAIL_WIDTH {
+                let x0 = tx * width / THUMBNAIL_WIDTH;
+                let x1 = ((tx + 1) * width / THUMBNAIL_WIDTH).max(x0 + 1).min(width);
+
+                let mut sum = [0usize; 3];
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        let i = (y * width + x) * 3;
+                        sum[0] += pixels[i] as usize;
+                        sum[1] += pixels[i + 1] as usize;
+                        sum[2] += pixels[i + 2] as usize;
+                    }
+                }
+
+                let count = ((y1 - y0) * (x1 - x0)).max(1);
+                thumbnail.extend(sum.iter().map(|channel| (channel / count) as u8));
+            }
+        }
+
+        Thumbnail {
+            width: THUMBNAIL_WIDTH,
+            height: THUMBNAIL_HEIGHT,
+            pixels: thumbnail,
+        }
+    }
+}
+
+/// Everything about a slot except the state itself
+#[derive(Clone, Default)]
+pub struct SlotInfo {
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+    pub thumbnail: Thumbnail,
+}
+
+impl Savestate for SlotInfo {
+    const TAG: [u8; 4] = *b"SLOT";
+    const VERSION: u16 = 1;
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.timestamp);
+        writer.write_u16(self.thumbnail.width as u16);
+        writer.write_u16(self.thumbnail.height as u16);
+        writer.write_bytes(&self.thumbnail.pixels);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader, _version: u16) -> Result<(), String> {
+        let timestamp = reader.read_u64()?;
+        let width = reader.read_u16()? as usize;
+        let height = reader.read_u16()? as usize;
+        let pixels = reader.read_bytes()?;
+
+        if pixels.len() != width * height * 3 {
+            return Err("Thumbnail size doesn't match its dimensions".to_string());
+        }
+
+        self.timestamp = timestamp;
+        self.thumbnail = Thumbnail {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        };
+
+        Ok(())
+    }
+}
+
+/// Slots are numbered 1 to `SLOT_COUNT` and stored as `<rom>.ss<slot>`
+pub struct SaveSlots {
+    rom_path: PathBuf,
+}
+
+impl SaveSlots {
+    pub fn new(rom_path: &Path) -> Self {
+        SaveSlots {
+            rom_path: rom_path.to_path_buf(),
+        }
+    }
+
+    pub fn path(&self, slot: usize) -> PathBuf {
+        assert!((1..=SLOT_COUNT).contains(&slot), "Invalid slot: {}", slot);
+        self.rom_path.with_extension(format!("ss{}", slot))
+    }
+
+    /// Writes a state from [`Emulator::save_state`](super::Emulator::save_state)
+    /// into a slot, stamped with the current time
+    pub fn save(&self, slot: usize, state: Vec<u8>, thumbnail: Thumbnail) -> Result<(), String> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+
+        let mut writer = StateWriter::append(state);
+        writer.chunk(&SlotInfo {
+            timestamp,
+            thumbnail,
+        });
+
+        write_atomic(&self.path(slot), &writer.finish()).map_err(|e| e.to_string())
+    }
+
+    /// Reads the state in a slot, ready for [`Emulator::load_state`](super::Emulator::load_state)
+    pub fn load(&self, slot: usize) -> Result<Vec<u8>, String> {
+        fs::read(self.path(slot)).map_err(|e| e.to_string())
+    }
+
+    /// Returns the timestamp and thumbnail of a slot, `None` if the slot is
+    /// empty or unreadable
+    pub fn info(&self, slot: usize) -> Option<SlotInfo> {
+        let data = fs::read(self.path(slot)).ok()?;
+        let chunks = StateReader::chunks(&data).ok()?;
+
+        let mut info = SlotInfo::default();
+        state::load_chunk(&mut info, &chunks).ok()?;
+
+        Some(info)
+    }
+}
@@ -0,0 +1,210 @@
+//! Save state serialization.
+//!
+//! A state is a small header followed by a list of chunks:
+//!
+//! ```text
+//! header: "NESS" magic, u16 format version
+//! chunk:  [u8; 4] tag, u16 chunk version, u32 length, `length` bytes of data
+//! ```
+//!
+//! All integers are little endian. Every device owns one chunk and its own
+//! chunk version, so a device can change its layout without touching the
+//! others. Chunks with unknown tags are skipped and devices without a chunk
+//! keep their current state, which lets states from older builds load after
+//! new devices are added.
+
+pub const MAGIC: [u8; 4] = *b"NESS";
+pub const VERSION: u16 = 1;
+
+/// Implemented by every device whose state is stored in a save state
+pub trait Savestate {
+    /// Tag identifying the device's chunk
+    const TAG: [u8; 4];
+    /// Current layout version of the device's chunk
+    const VERSION: u16;
+
+    fn save_state(&self, writer: &mut StateWriter);
+
+    /// `version` is the chunk version the data was written with, it is never
+    /// newer than `Self::VERSION`
+    fn load_state(&mut self, reader: &mut StateReader, version: u16) -> Result<(), String>;
+}
+
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&MAGIC);
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+
+        StateWriter { buf }
+    }
+
+    /// Continues writing chunks after the end of an existing state
+    pub fn append(state: Vec<u8>) -> Self {
+        StateWriter { buf: state }
+    }
+
+    /// Writes a device into its own chunk
+    pub fn chunk<T: Savestate>(&mut self, device: &T) {
+        self.buf.extend_from_slice(&T::TAG);
+        self.buf.extend_from_slice(&T::VERSION.to_le_bytes());
+
+        // length is patched in once the device has written its data
+        let len_pos = self.buf.len();
+        self.buf.extend_from_slice(&[0; 4]);
+
+        device.save_state(self);
+
+        let len = (self.buf.len() - len_pos - 4) as u32;
+        self.buf[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    /// Writes a length prefixed byte slice
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Loads a device from its chunk in `chunks`, a device without a chunk is
+/// left untouched
+pub fn load_chunk<T: Savestate>(device: &mut T, chunks: &[Chunk]) -> Result<(), String> {
+    let chunk = match chunks.iter().find(|(tag, _, _)| *tag == T::TAG) {
+        Some(chunk) => chunk,
+        None => return Ok(()),
+    };
+
+    let (tag, version, data) = *chunk;
+    if version > T::VERSION {
+        return Err(format!(
+            "Chunk {} version {} is newer than supported version {}",
+            String::from_utf8_lossy(&tag),
+            version,
+            T::VERSION
+        ));
+    }
+
+    device.load_state(&mut StateReader::new(data), version)
+}
+
+/// A chunk split out of a save state, (tag, version, data)
+pub type Chunk<'a> = ([u8; 4], u16, &'a [u8]);
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, pos: 0 }
+    }
+
+    /// Checks the header and splits the rest of the state into
+    /// (tag, version, data) chunks, without handing anything to the devices
+    pub fn chunks(data: &'a [u8]) -> Result<Vec<Chunk<'a>>, String> {
+        let mut reader = StateReader::new(data);
+
+        if reader.read_array::<4>()? != MAGIC {
+            return Err("Not a save state".to_string());
+        }
+
+        let version = reader.read_u16()?;
+        if version > VERSION {
+            return Err(format!(
+                "Save state version {} is newer than supported version {}",
+                version, VERSION
+            ));
+        }
+
+        let mut chunks = Vec::new();
+        while !reader.is_empty() {
+            let tag = reader.read_array::<4>()?;
+            let version = reader.read_u16()?;
+            let len = reader.read_u32()? as usize;
+            chunks.push((tag, version, reader.take(len)?));
+        }
+
+        Ok(chunks)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < len {
+            return Err("Unexpected end of save state".to_string());
+        }
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    /// Reads a length prefixed byte slice
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+}
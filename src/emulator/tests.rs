@@ -1,11 +1,26 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 pub mod tests {
-    use crate::emulator::Emulator;
+    use crate::emulator::{
+        battery::BatterySave,
+        cartridge::{Cartridge, Mirroring},
+        controller::{Button, Controller},
+        ppu::{self, PPU},
+        ram::BusAccess,
+        rewind::{decode_delta, encode_delta, Rewind, RewindConfig},
+        slots::{SaveSlots, Thumbnail, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH},
+        trace::nintendulator_line,
+        Emulator,
+    };
 
     fn run(emulator: &mut Emulator, program: Vec<u8>, cycles: usize) {
         // load test program to memory and set PC to it
         emulator.load(program);
 
+        run_cycles(emulator, cycles);
+    }
+
+    fn run_cycles(emulator: &mut Emulator, cycles: usize) {
         for _ in 0..cycles {
             emulator.cpu.cycle();
         }
@@ -181,10 +196,10 @@ pub mod tests {
     fn jmp_indirect() {
         let mut emulator = Emulator::new();
 
-        emulator.memory.borrow_mut()[0x0001] = 0x10;
-        emulator.memory.borrow_mut()[0x0000] = 0x00; // 0x1000 is the target address
-        emulator.memory.borrow_mut()[0x1001] = 0x10;
-        emulator.memory.borrow_mut()[0x1000] = 0x20; // target address points to 0x1020
+        emulator.memory.borrow_mut()[0x0001] = 0x02;
+        emulator.memory.borrow_mut()[0x0000] = 0x00; // 0x0200 is the target address
+        emulator.memory.borrow_mut()[0x0201] = 0x10;
+        emulator.memory.borrow_mut()[0x0200] = 0x20; // target address points to 0x1020
 
         run(&mut emulator, vec![0x6C, 0x00, 0x00], 5);
 
@@ -282,4 +297,1801 @@ pub mod tests {
         // Perform assertions
         assert_eq!(emulator.memory.borrow()[0x1025], 0x21);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn save_state_round_trip() {
+        let mut emulator = Emulator::new();
+
+        emulator.memory.borrow_mut()[0x0010] = 0x2A;
+
+        // stop in the middle of LDA zero page so sleep_cycles is non zero
+        run(&mut emulator, vec![0xA5, 0x10, 0xA9, 0x12], 2);
+        let state = emulator.save_state();
+
+        // finish the program and clobber memory
+        run_cycles(&mut emulator, 3);
+        emulator.memory.borrow_mut()[0x0010] = 0x00;
+        assert_eq!(emulator.cpu.acc, 0x12);
+
+        emulator.load_state(&state).unwrap();
+        assert_eq!(emulator.cpu.acc, 0x2A);
+        assert_eq!(emulator.memory.borrow()[0x0010], 0x2A);
+
+        // the remaining sleep cycles of LDA are restored too
+        run_cycles(&mut emulator, 1);
+        assert_eq!(emulator.cpu.acc, 0x2A);
+        run_cycles(&mut emulator, 1);
+        assert_eq!(emulator.cpu.acc, 0x12);
+    }
+
+    #[test]
+    fn load_state_skips_unknown_chunks() {
+        let mut emulator = Emulator::new();
+        emulator.cpu.acc = 0x21;
+
+        // append a chunk from a hypothetical newer device
+        let mut state = emulator.save_state();
+        state.extend_from_slice(b"NEW ");
+        state.extend_from_slice(&1u16.to_le_bytes());
+        state.extend_from_slice(&2u32.to_le_bytes());
+        state.extend_from_slice(&[0xAB, 0xCD]);
+
+        let mut other = Emulator::new();
+        other.load_state(&state).unwrap();
+
+        assert_eq!(other.cpu.acc, 0x21);
+    }
+
+    #[test]
+    fn load_state_rejects_bad_data() {
+        let mut emulator = Emulator::new();
+        emulator.cpu.acc = 0x21;
+        let state = emulator.save_state();
+
+        emulator.cpu.acc = 0x00;
+
+        assert!(emulator.load_state(b"not a state").is_err());
+        assert!(emulator.load_state(&state[..state.len() - 1]).is_err());
+
+        // a PPU chunk from a newer version is rejected after the CPU chunk
+        // was loaded
+        let mut newer = state.clone();
+        let ppu = newer.windows(4).position(|tag| tag == b"PPU ").unwrap();
+        newer[ppu + 4..ppu + 6].copy_from_slice(&99u16.to_le_bytes());
+        assert!(emulator.load_state(&newer).is_err());
+
+        // a failed load leaves the machine untouched
+        assert_eq!(emulator.cpu.acc, 0x00);
+    }
+
+    #[test]
+    fn thumbnail_downscale() {
+        // left half black, right half white
+        let (width, height) = (256, 240);
+        let mut pixels = vec![0u8; width * height * 3];
+        for y in 0..height {
+            for x in width / 2..width {
+                let i = (y * width + x) * 3;
+                pixels[i..i + 3].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+            }
+        }
+
+        let thumbnail = Thumbnail::downscale(&pixels, width, height);
+
+        assert_eq!(
+            thumbnail.pixels.len(),
+            THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 3
+        );
+        assert_eq!(thumbnail.pixels[0], 0x00);
+        assert_eq!(thumbnail.pixels[thumbnail.pixels.len() - 1], 0xFF);
+    }
+
+    #[test]
+    fn save_slot_round_trip() {
+        let rom_path = std::env::temp_dir().join(format!("slot_test_{}.nes", std::process::id()));
+        let save_slots = SaveSlots::new(&rom_path);
+
+        let mut emulator = Emulator::new();
+        emulator.cpu.acc = 0x21;
+
+        let thumbnail = Thumbnail::downscale(&[0x80; 8 * 8 * 3], 8, 8);
+        save_slots
+            .save(3, emulator.save_state(), thumbnail)
+            .unwrap();
+
+        assert!(save_slots.info(4).is_none());
+        let info = save_slots.info(3).unwrap();
+        assert!(info.timestamp > 0);
+        assert_eq!(info.thumbnail.pixels[0], 0x80);
+
+        // slot files load as regular states
+        let mut other = Emulator::new();
+        other.load_state(&save_slots.load(3).unwrap()).unwrap();
+        assert_eq!(other.cpu.acc, 0x21);
+
+        std::fs::remove_file(save_slots.path(3)).unwrap();
+    }
+
+    #[test]
+    fn rewind_delta_round_trip() {
+        let older = vec![0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
+        let newer = vec![0x00, 0x01, 0xFF, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+
+        let delta = encode_delta(&older, &newer);
+        assert_eq!(decode_delta(&newer, &delta), older);
+
+        // identical states compress to a handful of bytes
+        let state = Emulator::new().save_state();
+        assert!(encode_delta(&state, &state).len() < 8);
+    }
+
+    #[test]
+    fn rewind_steps_back() {
+        let mut rewind = Rewind::new(RewindConfig {
+            seconds: 1,
+            interval: 1,
+            max_bytes: usize::MAX,
+        });
+
+        let mut emulator = Emulator::new();
+        for acc in 0..5 {
+            emulator.cpu.acc = acc;
+            rewind.on_frame(&emulator);
+        }
+
+        assert_eq!(rewind.len(), 5);
+
+        rewind.rewind_frame(&mut emulator).unwrap();
+        assert_eq!(emulator.cpu.acc, 4);
+        rewind.rewind_frame(&mut emulator).unwrap();
+        assert_eq!(emulator.cpu.acc, 3);
+
+        // double speed restores two snapshots per frame
+        rewind.speed = 2.0;
+        rewind.rewind_frame(&mut emulator).unwrap();
+        assert_eq!(emulator.cpu.acc, 1);
+
+        // the oldest snapshot is kept once the buffer runs out
+        assert!(!rewind.rewind_frame(&mut emulator).unwrap());
+        assert_eq!(emulator.cpu.acc, 0);
+    }
+
+    #[test]
+    fn rewind_drops_oldest_snapshots() {
+        let mut rewind = Rewind::new(RewindConfig {
+            seconds: 1,
+            interval: 20,
+            max_bytes: usize::MAX,
+        });
+
+        for i in 0..10 {
+            rewind.push(vec![i; 16]);
+        }
+
+        // one second at one snapshot every 20 frames
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.pop(), Some(vec![9; 16]));
+        assert_eq!(rewind.pop(), Some(vec![8; 16]));
+        assert_eq!(rewind.pop(), Some(vec![7; 16]));
+        assert_eq!(rewind.pop(), None);
+        assert_eq!(rewind.bytes(), 0);
+    }
+
+    /// Builds an iNES image with one 16 KiB PRG-ROM bank holding `program`
+    /// at $8000 and the reset vector pointing to it
+    fn ines_rom(flags6: u8, program: &[u8]) -> Vec<u8> {
+        let mut rom = vec![
+            b'N', b'E', b'S', 0x1A, 1, 0, flags6, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(program);
+        prg_rom[0x3FFC..0x3FFE].copy_from_slice(&0x8000u16.to_le_bytes());
+
+        rom.extend_from_slice(&prg_rom);
+        rom
+    }
+
+    #[test]
+    fn cartridge_header() {
+        let cartridge = Cartridge::from_bytes(&ines_rom(0b0000_0011, &[])).unwrap();
+
+        assert_eq!(cartridge.prg_rom.len(), 0x4000);
+        assert!(cartridge.chr_rom.is_empty());
+        assert_eq!(cartridge.mapper, 0);
+        assert_eq!(cartridge.mirroring, Mirroring::Vertical);
+        assert!(cartridge.battery);
+        assert_eq!(cartridge.prg_ram_size, 0x2000);
+
+        assert!(Cartridge::from_bytes(b"not a rom").is_err());
+        assert!(Cartridge::from_bytes(&ines_rom(0, &[])[..0x1000]).is_err());
+    }
+
+    #[test]
+    fn prg_ram_write() {
+        let mut emulator = Emulator::new();
+
+        // LDA #$5A, STA $6001, with 16 KiB PRG-ROM mirrored at $C000
+        let rom = ines_rom(0b0000_0010, &[0xA9, 0x5A, 0x8D, 0x01, 0x60]);
+        emulator
+            .load_rom(&Cartridge::from_bytes(&rom).unwrap())
+            .unwrap();
+
+        assert_eq!(emulator.cpu.pc, 0x8000);
+        assert_eq!(emulator.memory.borrow()[0xC000], 0xA9);
+
+        run_cycles(&mut emulator, 6);
+
+        assert_eq!(emulator.memory.borrow()[0x6001], 0x5A);
+        assert_eq!(emulator.memory.borrow().prg_ram[1], 0x5A);
+
+        // states with a different amount of PRG-RAM don't load
+        let other = Emulator::new();
+        other.memory.borrow_mut().prg_ram = vec![0; 0x1000];
+        assert!(emulator.load_state(&other.save_state()).is_err());
+        assert_eq!(emulator.memory.borrow().prg_ram.len(), 0x2000);
+    }
+
+    #[test]
+    fn battery_save_round_trip() {
+        let rom_path =
+            std::env::temp_dir().join(format!("battery_test_{}.nes", std::process::id()));
+        let mut save = BatterySave::new(&rom_path);
+
+        let mut prg_ram = vec![0; 0x2000];
+        save.load(&mut prg_ram).unwrap();
+
+        // nothing changed, nothing written
+        assert!(!save.flush(&prg_ram).unwrap());
+        assert!(!save.path().exists());
+
+        prg_ram[0x10] = 0x42;
+        assert!(save.flush(&prg_ram).unwrap());
+
+        let mut loaded = vec![0; 0x2000];
+        BatterySave::new(&rom_path).load(&mut loaded).unwrap();
+        assert_eq!(loaded, prg_ram);
+
+        std::fs::remove_file(save.path()).unwrap();
+    }
+
+    #[test]
+    fn load_version_1_ram_chunk() {
+        // RAM chunks before version 2 held 0xFFFF bytes and no PRG-RAM
+        let mut array = vec![0; 0xFFFF];
+        array[0x10] = 0x2A;
+
+        let mut state = b"NESS".to_vec();
+        state.extend_from_slice(&1u16.to_le_bytes());
+        state.extend_from_slice(b"RAM ");
+        state.extend_from_slice(&1u16.to_le_bytes());
+        state.extend_from_slice(&(array.len() as u32 + 4).to_le_bytes());
+        state.extend_from_slice(&(array.len() as u32).to_le_bytes());
+        state.extend_from_slice(&array);
+
+        let mut emulator = Emulator::new();
+        emulator.load_state(&state).unwrap();
+
+        assert_eq!(emulator.memory.borrow()[0x0010], 0x2A);
+    }
+
+    #[test]
+    fn ppu_vblank_nmi() {
+        let mut ppu = PPU::new();
+        ppu.write_register(0x2000, ppu::Ctrl::Nmi);
+
+        // vblank starts on the second dot of scanline 241
+        for _ in 0..241 * ppu::DOTS as usize + 2 {
+            ppu.tick();
+        }
+
+        assert!(ppu.take_nmi());
+        assert!(!ppu.take_nmi());
+
+        // reading $2002 reports and clears vblank
+        assert_eq!(
+            ppu.read_register(0x2002) & ppu::Status::VBlank,
+            ppu::Status::VBlank
+        );
+        assert_eq!(ppu.read_register(0x2002) & ppu::Status::VBlank, 0);
+    }
+
+    #[test]
+    fn ppu_data_read_buffer() {
+        let mut ppu = PPU::new();
+        ppu.write_vram(0x2000, 0x11);
+        ppu.write_vram(0x2001, 0x22);
+
+        ppu.write_register(0x2006, 0x20);
+        ppu.write_register(0x2006, 0x00);
+
+        // the first read returns the stale buffer
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.read_register(0x2007), 0x11);
+        assert_eq!(ppu.read_register(0x2007), 0x22);
+    }
+
+    #[test]
+    fn bus_mirrors_ram_and_ignores_expansion_area() {
+        let emulator = Emulator::new();
+        let mut memory = emulator.memory.borrow_mut();
+
+        memory.write(0x0801, 0x42);
+        assert_eq!(memory.read(0x0001), 0x42);
+        assert_eq!(memory.read(0x1801), 0x42);
+
+        memory.write(0x5000, 0x42);
+        assert_eq!(memory.read(0x4020), 0xFF);
+        assert_eq!(memory.read(0x5000), 0xFF);
+    }
+
+    #[test]
+    fn controller_shift_register() {
+        let mut controller = Controller::new();
+        controller.buttons = Button::A | Button::Start;
+
+        controller.write(1);
+        controller.write(0);
+
+        let bits: Vec<u8> = (0..8).map(|_| controller.read() & 1).collect();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 0]);
+
+        // official controllers return 1 after the eighth read
+        assert_eq!(controller.read() & 1, 1);
+    }
+
+    #[test]
+    fn headless_input_script() {
+        use crate::headless::{self, InputScript, StopCondition};
+
+        let script = InputScript::parse("# comment\n30 T\n10 RA A\n40 .\n").unwrap();
+
+        assert_eq!(script.buttons_at(0), [0, 0]);
+        assert_eq!(
+            script.buttons_at(10),
+            [Button::Right | Button::A, Button::A]
+        );
+        assert_eq!(script.buttons_at(35), [Button::Start, 0]);
+        assert_eq!(script.buttons_at(100), [0, 0]);
+        assert!(InputScript::parse("10 X").is_err());
+
+        // STA $6000 is the last instruction before the CPU runs off the program
+        let cartridge =
+            Cartridge::from_bytes(&ines_rom(0, &[0xA9, 0x42, 0x8D, 0x00, 0x60])).unwrap();
+        let mut emulator = Emulator::new();
+        emulator.load_rom(&cartridge).unwrap();
+
+        let until = StopCondition::parse("$6000=42").unwrap();
+        assert_eq!(
+            headless::run(&mut emulator, 1, &script, Some(&until)),
+            (1, true)
+        );
+
+        assert_eq!(headless::crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn nintendulator_trace_format() {
+        let mut emulator = Emulator::new();
+        emulator.load(vec![0x4C, 0xF5, 0xC5]);
+
+        assert_eq!(
+            nintendulator_line(&emulator),
+            "8000  4C F5 C5  JMP $C5F5                       \
+             A:00 X:00 Y:00 P:00 SP:00 PPU:  0,  0 CYC:0"
+        );
+
+        emulator.memory.borrow_mut()[0x0089] = 0x00;
+        emulator.memory.borrow_mut()[0x008A] = 0x03;
+        emulator.memory.borrow_mut()[0x0300] = 0x5B;
+        emulator.load(vec![0xB1, 0x89]);
+        assert!(
+            nintendulator_line(&emulator).contains("  B1 89     LDA ($89),Y = 0300 @ 0300 = 5B ")
+        );
+
+        emulator.load(vec![0x04, 0x89]);
+        assert!(nintendulator_line(&emulator).contains("  04 89    *NOP $89 = 00 "));
+    }
+
+    #[test]
+    fn nestest_reports_first_divergence() {
+        use crate::headless;
+
+        // the program is mirrored to $C000, where automation mode starts
+        let cartridge = Cartridge::from_bytes(&ines_rom(0, &[0xA9, 0x42, 0xAA])).unwrap();
+
+        let reference = "\
+C000  A9 42     LDA #$42                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+C002  AA        TAX                             A:42 X:00 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9
+C003  00        BRK                             A:42 X:00 Y:00 P:24 SP:FD PPU:  0, 33 CYC:11
+";
+        let matching: Vec<&str> = reference.lines().take(2).collect();
+        assert_eq!(headless::nestest(&cartridge, &matching.join("\n")), Ok(2));
+
+        let report = headless::nestest(&cartridge, reference).unwrap_err();
+        assert!(report.starts_with("nestest diverged at line 3:"));
+        assert!(
+            report.contains("actual    C003  00        BRK                             A:42 X:42")
+        );
+    }
+
+    /// Needs test_roms/nestest.nes and test_roms/nestest.log, run with
+    /// `cargo test -- --ignored`
+    #[test]
+    #[ignore = "the CPU doesn't implement the full instruction set yet"]
+    fn nestest() {
+        use crate::headless;
+
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_roms");
+        let cartridge = Cartridge::load(&dir.join("nestest.nes")).unwrap();
+        let reference = std::fs::read_to_string(dir.join("nestest.log")).unwrap();
+
+        if let Err(report) = headless::nestest(&cartridge, &reference) {
+            panic!("{}", report);
+        }
+    }
+
+    /// Runs one case of the 6502 SingleStepTests: sets up the initial
+    /// registers and RAM, executes a single instruction and compares the
+    /// final registers, RAM and bus activity
+    fn single_step_case(case: &serde_json::Value) -> Result<(), String> {
+        let initial = &case["initial"];
+        let expected = &case["final"];
+        let number = |value: &serde_json::Value| value.as_u64().unwrap_or(0);
+
+        let mut emulator = Emulator::new();
+        {
+            let mut memory = emulator.memory.borrow_mut();
+            memory.flat = true;
+            for entry in initial["ram"].as_array().ok_or("Case has no RAM")? {
+                memory[number(&entry[0]) as u16] = number(&entry[1]) as u8;
+            }
+            memory.bus_log = Some(Vec::new());
+        }
+
+        let cpu = &mut emulator.cpu;
+        cpu.pc = number(&initial["pc"]) as u16;
+        cpu.sp = number(&initial["s"]) as u8;
+        cpu.acc = number(&initial["a"]) as u8;
+        cpu.idx_x = number(&initial["x"]) as u8;
+        cpu.idx_y = number(&initial["y"]) as u8;
+        cpu.status = number(&initial["p"]) as u8;
+
+        let step = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| emulator.step()));
+        if step.is_err() {
+            return Err(format!("{}: CPU panicked", case["name"]));
+        }
+
+        let mut errors = Vec::new();
+        let cpu = &emulator.cpu;
+        let registers = [
+            ("pc", cpu.pc as u64),
+            ("s", cpu.sp as u64),
+            ("a", cpu.acc as u64),
+            ("x", cpu.idx_x as u64),
+            ("y", cpu.idx_y as u64),
+            ("p", cpu.status as u64),
+        ];
+        for (name, actual) in registers {
+            if number(&expected[name]) != actual {
+                errors.push(format!(
+                    "{} is {:02X}, expected {:02X}",
+                    name,
+                    actual,
+                    number(&expected[name])
+                ));
+            }
+        }
+
+        let mut memory = emulator.memory.borrow_mut();
+        for entry in expected["ram"].as_array().ok_or("Case has no RAM")? {
+            let address = number(&entry[0]) as u16;
+            if memory[address] as u64 != number(&entry[1]) {
+                errors.push(format!(
+                    "${:04X} is {:02X}, expected {:02X}",
+                    address,
+                    memory[address],
+                    number(&entry[1])
+                ));
+            }
+        }
+
+        let bus: Vec<BusAccess> = case["cycles"]
+            .as_array()
+            .ok_or("Case has no cycles")?
+            .iter()
+            .map(|cycle| BusAccess {
+                address: number(&cycle[0]) as u16,
+                value: number(&cycle[1]) as u8,
+                write: cycle[2] == "write",
+            })
+            .collect();
+        let log = memory.bus_log.take().unwrap_or_default();
+        if log != bus {
+            errors.push(format!("bus activity {:?}, expected {:?}", log, bus));
+        }
+        if cpu.cycles != bus.len() as u64 {
+            errors.push(format!(
+                "took {} cycles, expected {}",
+                cpu.cycles,
+                bus.len()
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("{}: {}", case["name"], errors.join(", ")))
+        }
+    }
+
+    #[test]
+    fn single_step_case_lda_imm() {
+        let case = serde_json::json!({
+            "name": "a9 f1 00",
+            "initial": { "pc": 0x1234, "s": 0xFD, "a": 0, "x": 0, "y": 0, "p": 0x24,
+                         "ram": [[0x1234, 0xA9], [0x1235, 0xF1]] },
+            "final": { "pc": 0x1236, "s": 0xFD, "a": 0xF1, "x": 0, "y": 0, "p": 0xA4,
+                       "ram": [[0x1234, 0xA9], [0x1235, 0xF1]] },
+            "cycles": [[0x1234, 0xA9, "read"], [0x1235, 0xF1, "read"]]
+        });
+        assert_eq!(single_step_case(&case), Ok(()));
+
+        let mut case = case;
+        case["final"]["a"] = serde_json::json!(0x00);
+        let error = single_step_case(&case).unwrap_err();
+        assert!(error.contains("a is F1, expected 00"));
+    }
+
+    /// Needs the 6502 SingleStepTests JSON files (one per opcode, like
+    /// a9.json) in test_roms/6502, or in the directory named by
+    /// SINGLE_STEP_TESTS. Run with `cargo test -- --ignored`
+    #[test]
+    #[ignore = "the CPU doesn't implement the full instruction set yet"]
+    fn single_step_tests() {
+        let dir = std::env::var_os("SINGLE_STEP_TESTS")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|| {
+                std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_roms/6502")
+            });
+
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap_or_else(|e| panic!("{}: {}", dir.display(), e))
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        files.sort();
+
+        // panics are reported as failed cases, keep them from flooding the output
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|_| {}));
+
+        let mut failures = Vec::new();
+        for path in &files {
+            let text = std::fs::read_to_string(path).unwrap();
+            let cases: serde_json::Value = serde_json::from_str(&text).unwrap();
+            let cases = cases.as_array().unwrap();
+
+            let errors: Vec<String> = cases
+                .iter()
+                .filter_map(|case| single_step_case(case).err())
+                .collect();
+            if let Some(first) = errors.first() {
+                failures.push(format!(
+                    "{}: {}/{} cases failed, first: {}",
+                    path.file_name().unwrap().to_string_lossy(),
+                    errors.len(),
+                    cases.len(),
+                    first
+                ));
+            }
+        }
+
+        std::panic::set_hook(hook);
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    /// A ROM that reports `status` and the text "ok" through the blargg
+    /// protocol, then loops forever
+    fn blargg_rom(status: u8) -> Vec<u8> {
+        let mut program = Vec::new();
+        let writes = [
+            (0x6000, 0x80),
+            (0x6001, 0xDE),
+            (0x6002, 0xB0),
+            (0x6003, 0x61),
+            (0x6004, b'o'),
+            (0x6005, b'k'),
+            (0x6006, 0x00),
+            (0x6000, status),
+        ];
+        for (address, value) in writes {
+            // LDA #value, STA address
+            program.extend_from_slice(&[0xA9, value, 0x8D, address as u8, (address >> 8) as u8]);
+        }
+
+        // store the address of the JMP that follows at $0000, JMP $0000
+        // jumps through it like the jmp_abs test expects
+        let jmp = 0x8000 + program.len() as u16 + 8;
+        program.extend_from_slice(&[0xA9, jmp as u8, 0x85, 0x00, 0xA9, (jmp >> 8) as u8]);
+        program.extend_from_slice(&[0x85, 0x01, 0x4C, 0x00, 0x00]);
+
+        ines_rom(0, &program)
+    }
+
+    #[test]
+    fn blargg_status_protocol() {
+        use crate::headless;
+
+        let passed = Cartridge::from_bytes(&blargg_rom(0x00)).unwrap();
+        assert_eq!(headless::blargg(&passed, 10), Ok("ok".to_string()));
+
+        let failed = Cartridge::from_bytes(&blargg_rom(0x03)).unwrap();
+        assert_eq!(
+            headless::blargg(&failed, 10),
+            Err("Failed with code 3: ok".to_string())
+        );
+
+        let running = Cartridge::from_bytes(&blargg_rom(0x80)).unwrap();
+        assert!(headless::blargg(&running, 10).is_err());
+    }
+
+    /// Runs every .nes file under test_roms/blargg, or the directory named by
+    /// BLARGG_TESTS. Run with `cargo test -- --ignored --nocapture`
+    #[test]
+    #[ignore = "the CPU doesn't implement the full instruction set yet"]
+    fn blargg_test_roms() {
+        use crate::headless;
+
+        fn find_roms(dir: &std::path::Path, roms: &mut Vec<std::path::PathBuf>) {
+            let entries =
+                std::fs::read_dir(dir).unwrap_or_else(|e| panic!("{}: {}", dir.display(), e));
+            for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+                if path.is_dir() {
+                    find_roms(&path, roms);
+                } else if path.extension().is_some_and(|ext| ext == "nes") {
+                    roms.push(path);
+                }
+            }
+        }
+
+        let dir = std::env::var_os("BLARGG_TESTS")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|| {
+                std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_roms/blargg")
+            });
+
+        let mut roms = Vec::new();
+        find_roms(&dir, &mut roms);
+        roms.sort();
+
+        let mut failed = 0;
+        for rom in &roms {
+            // the longer blargg ROMs take around 30 seconds
+            let result =
+                Cartridge::load(rom).and_then(|cartridge| headless::blargg(&cartridge, 60 * 60));
+            match result {
+                Ok(_) => println!("pass  {}", rom.display()),
+                Err(e) => {
+                    println!("FAIL  {}: {}", rom.display(), e);
+                    failed += 1;
+                }
+            }
+        }
+
+        assert_eq!(failed, 0, "{} of {} ROMs failed", failed, roms.len());
+    }
+
+    #[test]
+    fn golden_frame_mismatch_writes_images() {
+        use crate::headless::{self, Golden, GoldenFrames, InputScript};
+
+        let cartridge = Cartridge::from_bytes(&blargg_rom(0x80)).unwrap();
+        let input = InputScript::default();
+        let dir = std::env::temp_dir().join(format!("golden_test_{}", std::process::id()));
+
+        // the frame a passing golden image is recorded from
+        let mut emulator = Emulator::new();
+        emulator.load_rom(&cartridge).unwrap();
+        emulator.run_frame();
+        emulator.run_frame();
+        let frame = emulator.ppu.borrow().framebuffer.clone();
+
+        std::fs::create_dir_all(&dir).unwrap();
+        headless::write_png(&dir.join("good.png"), 256, 240, &frame).unwrap();
+        headless::write_png(&dir.join("bad.png"), 256, 240, &vec![0xFF; frame.len()]).unwrap();
+
+        let wrong_hash = headless::crc32(&frame) ^ 1;
+        let text = format!("2 good.png\n# comment\n1 {:08x}\n", wrong_hash);
+        let golden = GoldenFrames::parse(&text, &dir).unwrap();
+        assert_eq!(golden.frames[0].0, 1);
+        assert_eq!(golden.frames[1].1, Golden::Image(dir.join("good.png")));
+
+        let error = headless::check_golden(&cartridge, &input, &golden, "rom", &dir).unwrap_err();
+        assert!(error.starts_with("frame 1: crc32"));
+        assert!(dir.join("rom.1.actual.png").exists());
+
+        let golden = GoldenFrames::parse("2 good.png", &dir).unwrap();
+        assert_eq!(
+            headless::check_golden(&cartridge, &input, &golden, "rom", &dir),
+            Ok(())
+        );
+
+        let golden = GoldenFrames::parse("2 bad.png", &dir).unwrap();
+        assert!(headless::check_golden(&cartridge, &input, &golden, "rom", &dir).is_err());
+        let (_, _, diff) = headless::read_png(&dir.join("rom.2.diff.png")).unwrap();
+        assert_eq!(&diff[..3], &[0xFF, 0x00, 0x00]);
+        assert!(dir.join("rom.2.expected.png").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Checks every <name>.golden under test_roms/regression against
+    /// <name>.nes, playing <name>.input if there is one. Mismatching frames
+    /// are written to target/regression. Run with `cargo test -- --ignored`
+    #[test]
+    #[ignore = "needs ROMs and golden files in test_roms/regression"]
+    fn frame_regressions() {
+        use crate::headless::{self, GoldenFrames, InputScript};
+
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let dir = root.join("test_roms/regression");
+        let entries =
+            std::fs::read_dir(&dir).unwrap_or_else(|e| panic!("{}: {}", dir.display(), e));
+
+        let mut failures = Vec::new();
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if path.extension().is_none_or(|ext| ext != "golden") {
+                continue;
+            }
+
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            let input_path = path.with_extension("input");
+            let result = Cartridge::load(&path.with_extension("nes")).and_then(|cartridge| {
+                let input = if input_path.exists() {
+                    InputScript::load(&input_path)?
+                } else {
+                    InputScript::default()
+                };
+                let golden = GoldenFrames::load(&path)?;
+                headless::check_golden(
+                    &cartridge,
+                    &input,
+                    &golden,
+                    &name,
+                    &root.join("target/regression"),
+                )
+            });
+
+            if let Err(e) = result {
+                failures.push(format!("{}:\n{}", name, e));
+            }
+        }
+
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn disassemble_addressing_modes() {
+        use crate::emulator::disasm::{disassemble, disassemble_range};
+
+        let program = [
+            (vec![0xEA], "NOP"),
+            (vec![0x0A], "ASL A"),
+            (vec![0xA9, 0x20], "LDA #$20"),
+            (vec![0xA5, 0x20], "LDA $20"),
+            (vec![0xB5, 0x20], "LDA $20,X"),
+            (vec![0xB6, 0x20], "LDX $20,Y"),
+            (vec![0xAD, 0x34, 0x12], "LDA $1234"),
+            (vec![0xBD, 0x34, 0x12], "LDA $1234,X"),
+            (vec![0xB9, 0x34, 0x12], "LDA $1234,Y"),
+            (vec![0x6C, 0x34, 0x12], "JMP ($1234)"),
+            (vec![0xA1, 0x20], "LDA ($20,X)"),
+            (vec![0xB1, 0x20], "LDA ($20),Y"),
+            // branches show their target, $801A + 2 - 4
+            (vec![0xD0, 0xFC], "BNE $8018"),
+        ];
+
+        let mut emulator = Emulator::new();
+        let bytes: Vec<u8> = program
+            .iter()
+            .flat_map(|(bytes, _)| bytes.clone())
+            .collect();
+        emulator.load(bytes.clone());
+
+        let memory = emulator.memory.borrow();
+        let mut address = 0x8000;
+        for (bytes, text) in &program {
+            let (instruction, size) = disassemble(&memory, address);
+            assert_eq!(instruction.to_string(), *text);
+            assert_eq!(instruction.bytes(), *bytes);
+            assert_eq!(size as usize, bytes.len());
+            address += size;
+        }
+
+        let range = disassemble_range(&memory, 0x8000, 0x8000 + bytes.len() as u16 - 1);
+        assert_eq!(range.len(), program.len());
+        assert_eq!(range[12].address, 0x801A);
+    }
+
+    #[test]
+    fn disassemble_resolves_effective_address() {
+        use crate::emulator::disasm::disassemble;
+
+        let mut emulator = Emulator::new();
+        emulator.load(vec![0xB1, 0x20, 0xB5, 0xFF]);
+        emulator.cpu.idx_x = 0x03;
+        emulator.cpu.idx_y = 0x05;
+        {
+            let mut memory = emulator.memory.borrow_mut();
+            memory[0x0020] = 0x00;
+            memory[0x0021] = 0x03;
+            memory[0x0305] = 0x5B;
+        }
+
+        let memory = emulator.memory.borrow();
+        let (indirect_y, _) = disassemble(&memory, 0x8000);
+        assert_eq!(
+            indirect_y.format_resolved(&emulator.cpu, &memory),
+            "LDA ($20),Y @ $0305 = $5B"
+        );
+
+        // zero page indexing wraps within the zero page
+        let (zero_page_x, _) = disassemble(&memory, 0x8002);
+        assert_eq!(
+            zero_page_x.effective_address(&emulator.cpu, &memory),
+            Some(0x0002)
+        );
+    }
+
+    #[test]
+    fn assemble_addressing_modes() {
+        use crate::emulator::asm::assemble;
+
+        let image = assemble(
+            "
+            ; every addressing mode
+                    .org $C000
+            start:  NOP
+                    ASL A
+                    LDA #$20
+                    LDA $20
+                    LDA $20,X
+                    LDX $20,Y
+                    LDA $1234
+                    LDA $1234,X
+                    LDA $1234,Y
+                    JMP ($1234)
+                    LDA ($20,X)
+                    LDA ($20),y
+            loop:   BNE loop
+                    LDA data        ; forward labels are assumed to be absolute
+                    JSR start
+            data:   .byte 1, $FF, %10, ';'
+                    .word data, >data, <data + 1
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(image.origin, 0xC000);
+        assert_eq!(
+            image.bytes,
+            [
+                0xEA, 0x0A, 0xA9, 0x20, 0xA5, 0x20, 0xB5, 0x20, 0xB6, 0x20, 0xAD, 0x34, 0x12, 0xBD,
+                0x34, 0x12, 0xB9, 0x34, 0x12, 0x6C, 0x34, 0x12, 0xA1, 0x20, 0xB1, 0x20, 0xD0, 0xFE,
+                0xAD, 0x22, 0xC0, 0x20, 0x00, 0xC0, 0x01, 0xFF, 0x02, 0x3B, 0x22, 0xC0, 0xC0, 0x00,
+                0x23, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_errors() {
+        use crate::emulator::asm::assemble;
+
+        assert!(assemble("LDA").unwrap_err().contains("Line 1"));
+        assert!(assemble("NOP\nFOO #1")
+            .unwrap_err()
+            .starts_with("Line 2: unknown instruction"));
+        assert!(assemble("STA #1").is_err());
+        assert!(assemble("LDA missing")
+            .unwrap_err()
+            .contains("undefined label"));
+        assert!(assemble("a: NOP\na: NOP").is_err());
+        assert!(assemble(".org $8000\nBNE far\n.org $9000\nfar: NOP").is_err());
+        assert!(assemble(".org $8000\nNOP\nNOP\n.org $8001\nNOP")
+            .unwrap_err()
+            .contains("overlap"));
+        assert!(assemble("LDA #-129").is_err());
+        assert!(assemble(".org $5000\nNOP")
+            .unwrap_err()
+            .contains("IO registers"));
+        assert!(assemble(".org $1FFF\nNOP\nNOP").is_err());
+        assert!(assemble(".org $FFFF\nLDA $1234")
+            .unwrap_err()
+            .contains("past $FFFF"));
+        assert!(assemble(".byte 256").is_err());
+    }
+
+    #[test]
+    fn assemble_negative_values() {
+        use crate::emulator::asm::assemble;
+
+        let image = assemble("LDA #-1\nLDX #-$80\n.byte -2, 3 - 4\n.word -1").unwrap();
+        assert_eq!(
+            image.bytes,
+            [0xA9, 0xFF, 0xA2, 0x80, 0xFE, 0xFF, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn assemble_and_run() {
+        use crate::emulator::asm::assemble;
+
+        let image = assemble(
+            "
+            LDA #$21
+            STA $10
+            LDA $10,X
+            TAX
+            ",
+        )
+        .unwrap();
+
+        let mut emulator = Emulator::new();
+        emulator.cpu.idx_x = 0x00;
+        run(&mut emulator, image.bytes, 10);
+
+        assert_eq!(emulator.memory.borrow()[0x0010], 0x21);
+        assert_eq!(emulator.cpu.idx_x, 0x21);
+
+        // patching replaces code in place
+        let patch = assemble(".org $8000\nLDA #$42").unwrap();
+        patch.patch(&mut emulator.memory.borrow_mut());
+        assert_eq!(emulator.memory.borrow()[0x8001], 0x42);
+    }
+
+    #[test]
+    fn trace_logger_filters() {
+        use crate::emulator::{asm::assemble, trace::TraceLogger};
+
+        let program = assemble(
+            "
+            LDA #$01
+            STA $10
+            LDA #$02
+            TAX
+            LDA #$03
+            ",
+        )
+        .unwrap();
+
+        let mut emulator = Emulator::new();
+        emulator.load(program.bytes);
+
+        let mut trace = TraceLogger::ring(2);
+        // from the second LDA, skipping TAX, while A is still below 3
+        trace.trigger = Some(0x8004);
+        trace.ranges = vec![0x8000..=0x8005, 0x8007..=0x8008];
+        trace.condition = Some(Box::new(|emulator: &Emulator| emulator.cpu.acc < 3));
+        emulator.trace = Some(trace);
+
+        for _ in 0..5 {
+            emulator.step();
+        }
+
+        let trace = emulator.trace.take().unwrap();
+        let lines: Vec<&String> = trace.recent().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("8004  A9 02     LDA #$02"));
+        assert!(lines[0].contains("A:01 X:00 Y:00 P:00 nvubdizc SP:00 CYC:5"));
+        assert!(lines[1].starts_with("8007  A9 03     LDA #$03"));
+        assert_eq!(trace.dump().lines().count(), 2);
+    }
+
+    #[test]
+    fn trace_skips_instruction_replaced_by_nmi() {
+        use crate::emulator::trace::TraceLogger;
+
+        let mut emulator = Emulator::new();
+        // LDA #$01, LDA #$02
+        emulator.load(vec![0xA9, 0x01, 0xA9, 0x02]);
+        emulator.memory.borrow_mut().write_u16(0xFFFA, 0x8002);
+        emulator.trace = Some(TraceLogger::ring(4));
+
+        emulator.cpu.trigger_nmi();
+        emulator.step();
+        emulator.step();
+
+        let trace = emulator.trace.take().unwrap();
+        let lines: Vec<&String> = trace.recent().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("8002  A9 02     LDA #$02"));
+    }
+
+    #[test]
+    fn debugger_breakpoints() {
+        use crate::emulator::{
+            asm::assemble,
+            debugger::{Access, Breakpoint, Space, Stop, WatchHit},
+        };
+
+        let program = assemble(
+            "
+            LDA #$01
+            STA $10
+            LDA #$02
+            STA $11
+            ",
+        )
+        .unwrap();
+
+        let mut emulator = Emulator::new();
+        emulator.load(program.bytes);
+        assert!(!emulator.debugger.is_active());
+
+        let exec = emulator.add_breakpoint(Breakpoint::Exec(0x8004));
+        emulator.run_frame();
+        assert_eq!(emulator.debugger.stopped, Some(Stop::Breakpoint(exec)));
+        assert_eq!(emulator.cpu.pc, 0x8004);
+        assert_eq!(emulator.memory.borrow()[0x0010], 0x01);
+
+        // nothing runs while stopped
+        let cycles = emulator.cpu.cycles;
+        emulator.cycle();
+        assert_eq!(emulator.cpu.cycles, cycles);
+
+        let watch = emulator.add_breakpoint(Breakpoint::Watch {
+            space: Space::Cpu,
+            start: 0x0011,
+            end: 0x0011,
+            access: Access::Write,
+        });
+        emulator.resume();
+        emulator.run_frame();
+        let hit = WatchHit {
+            address: 0x0011,
+            value: 0x02,
+            write: true,
+        };
+        assert_eq!(
+            emulator.debugger.stopped,
+            Some(Stop::Watchpoint(watch, hit))
+        );
+        assert_eq!(emulator.debugger.breakpoints()[0].hits, 1);
+        assert_eq!(emulator.debugger.breakpoints()[1].hits, 1);
+
+        emulator.remove_breakpoint(exec);
+        emulator.set_breakpoint_enabled(watch, false);
+        emulator.resume();
+        assert!(!emulator.debugger.is_active());
+    }
+
+    fn run_until_stopped(emulator: &mut Emulator) {
+        while !emulator.is_stopped() {
+            emulator.cycle();
+        }
+    }
+
+    #[test]
+    fn debugger_stepping() {
+        use crate::emulator::{
+            asm::assemble,
+            debugger::{Breakpoint, Stop},
+        };
+
+        let program = assemble(
+            "
+            LDA #$01
+            STA $10
+            LDA #$02
+            STA $11
+            TAX
+            loop:
+            JMP $0000
+            ",
+        )
+        .unwrap();
+
+        let mut emulator = Emulator::new();
+        emulator.load(program.bytes);
+        // JMP abs goes through the pointer at its operand on this CPU
+        emulator.memory.borrow_mut()[0x0000] = 0x09;
+        emulator.memory.borrow_mut()[0x0001] = 0x80;
+
+        emulator.step_into();
+        run_until_stopped(&mut emulator);
+        assert_eq!(emulator.debugger.stopped, Some(Stop::Step));
+        assert_eq!(emulator.cpu.pc, 0x8002);
+
+        emulator.run_to(0x8006);
+        run_until_stopped(&mut emulator);
+        assert_eq!(emulator.cpu.pc, 0x8006);
+        assert_eq!(emulator.memory.borrow()[0x0011], 0x00);
+
+        // not a JSR, so the same as step into
+        emulator.step_over();
+        run_until_stopped(&mut emulator);
+        assert_eq!(emulator.cpu.pc, 0x8008);
+
+        let frame = emulator.ppu.borrow().frame;
+        emulator.frame_advance();
+        run_until_stopped(&mut emulator);
+        assert_eq!(emulator.debugger.stopped, Some(Stop::Step));
+        assert_eq!(emulator.ppu.borrow().frame, frame + 1);
+
+        let scanline = emulator.add_breakpoint(Breakpoint::Scanline(100));
+        emulator.resume();
+        run_until_stopped(&mut emulator);
+        assert_eq!(emulator.debugger.stopped, Some(Stop::Breakpoint(scanline)));
+        assert_eq!(emulator.ppu.borrow().scanline, 100);
+    }
+
+    #[test]
+    fn expression_evaluation() {
+        use crate::emulator::expr::Expression;
+
+        let mut emulator = Emulator::new();
+        emulator.cpu.acc = 0x40;
+        emulator.cpu.idx_x = 5;
+        emulator.cpu.status = 0x81;
+        emulator.memory.borrow_mut()[0x0300] = 0x80;
+        // frames are counted like movies count them, not by the PPU
+        emulator.frames.0 = 12;
+        emulator.ppu.borrow_mut().frame = 2;
+
+        let holds = |text: &str, hits| Expression::compile(text).unwrap().holds(&emulator, hits);
+        assert!(holds("A == $40 && X > 3", 0));
+        assert!(!holds("a == $40 && x > 5", 0));
+        assert!(holds("[$0300] & $80", 0));
+        assert!(holds("[$02FF + 1] == %10000000", 0));
+        assert!(holds("n && c && !z", 0));
+        assert!(holds("hits % 3 == 0", 6));
+        assert!(holds("-1 < 0 && (1 + 2) * 3 == 9 && 1 << 4 == 16", 0));
+        assert!(holds("scanline == 0", 0));
+        assert!(holds("frame == 12", 0));
+
+        // constants are folded when compiling
+        let folded = Expression::compile("($10 + 2) * 4").unwrap();
+        assert_eq!(folded.constant(), Some(0x48));
+        assert_eq!(Expression::compile("A + 1").unwrap().constant(), None);
+
+        for bad in ["A ==", "(A", "[$10", "foo > 1", "A # 2", "$zz", "A B"] {
+            assert!(Expression::compile(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn conditional_breakpoints() {
+        use crate::emulator::{
+            asm::assemble,
+            debugger::{Breakpoint, Stop},
+        };
+
+        let program = assemble(
+            "
+            LDA #$01
+            LDX $10
+            JMP $0000
+            ",
+        )
+        .unwrap();
+
+        let mut emulator = Emulator::new();
+        emulator.load(program.bytes);
+        // JMP abs goes through the pointer at its operand on this CPU
+        emulator.memory.borrow_mut()[0x0000] = 0x00;
+        emulator.memory.borrow_mut()[0x0001] = 0x80;
+        emulator.memory.borrow_mut()[0x0010] = 0x07;
+
+        let id = emulator.add_breakpoint(Breakpoint::Exec(0x8002));
+        assert!(emulator.set_breakpoint_condition(id, "A ==").is_err());
+        assert!(emulator.set_breakpoint_condition(id + 1, "A").is_err());
+        emulator
+            .set_breakpoint_condition(id, "A == 1 && [$10] == 7 && hits == 3")
+            .unwrap();
+
+        run_until_stopped(&mut emulator);
+        assert_eq!(emulator.debugger.stopped, Some(Stop::Breakpoint(id)));
+        assert_eq!(emulator.cpu.pc, 0x8002);
+        assert_eq!(emulator.debugger.breakpoints()[0].hits, 3);
+
+        // an empty condition removes it
+        emulator.set_breakpoint_condition(id, " ").unwrap();
+        emulator.resume();
+        run_until_stopped(&mut emulator);
+        assert_eq!(emulator.debugger.breakpoints()[0].hits, 4);
+    }
+
+    #[test]
+    fn debugger_call_stack() {
+        use crate::emulator::debugger::CallFrame;
+
+        let mut emulator = Emulator::new();
+        // LDA #$01, then a JMP $0000 loop back to it
+        emulator.load(vec![0xA9, 0x01, 0x4C, 0x00, 0x00]);
+        emulator.memory.borrow_mut().write_u16(0x0000, 0x8000);
+        emulator.memory.borrow_mut().write_u16(0xFFFA, 0x9000);
+        emulator.debugger.set_call_tracking(true);
+        assert!(emulator.debugger.is_active());
+
+        emulator.step();
+        emulator.cpu.trigger_nmi();
+        emulator.step();
+        emulator.step();
+
+        let frame = CallFrame {
+            caller: 0x8002,
+            target: 0x9000,
+            interrupt: true,
+        };
+        assert_eq!(emulator.debugger.call_stack(), &[frame]);
+
+        emulator.debugger.set_call_tracking(false);
+        assert!(emulator.debugger.call_stack().is_empty());
+        assert!(!emulator.debugger.is_active());
+    }
+
+    #[test]
+    fn memory_regions() {
+        use crate::emulator::{ppu::Status, region::Region};
+
+        let mut emulator = Emulator::new();
+        emulator.poke_region(Region::Cpu, 0x0010, 0x42);
+        emulator.poke_region(Region::PrgRom, 0x0001, 0xA9);
+        emulator.poke_region(Region::Palette, 0x00, 0x0F);
+        emulator.poke_region(Region::Oam, 0xFF, 0x33);
+        emulator.poke_region(Region::Ppu, 0x2005, 0x77);
+        // IO registers and offsets past the end are left alone
+        emulator.poke_region(Region::Cpu, 0x2000, 0x80);
+        emulator.poke_region(Region::Palette, 0x20, 0x01);
+
+        assert_eq!(emulator.peek_region(Region::Cpu, 0x0010), 0x42);
+        assert_eq!(emulator.peek_region(Region::Cpu, 0x8001), 0xA9);
+        assert_eq!(emulator.peek_region(Region::Ppu, 0x3F00), 0x0F);
+        assert_eq!(emulator.peek_region(Region::Oam, 0xFF), 0x33);
+        assert_eq!(emulator.ppu.borrow().read_vram(0x2005), 0x77);
+        assert_eq!(emulator.ppu.borrow().ctrl, 0x00);
+        assert_eq!(emulator.peek_region(Region::Palette, 0x20), 0x00);
+        assert_eq!(emulator.region_len(Region::Chr), 0x2000);
+
+        // looking at $2002 doesn't clear vblank
+        emulator.ppu.borrow_mut().status |= Status::VBlank;
+        emulator.peek_region_range(Region::Cpu, 0x2000, 8);
+        assert_ne!(emulator.ppu.borrow().status & Status::VBlank, 0);
+
+        emulator.poke_region(Region::PrgRam, 0x0100, b'H');
+        emulator.poke_region(Region::PrgRam, 0x0101, b'I');
+        assert_eq!(
+            emulator.search_region(Region::PrgRam, b"HI", 0),
+            Some(0x0100)
+        );
+        assert_eq!(
+            emulator.search_region(Region::PrgRam, b"HI", 0x0101),
+            Some(0x0100)
+        );
+        assert_eq!(emulator.search_region(Region::PrgRam, b"HO", 0), None);
+        // internal RAM is mirrored up to $1FFF
+        assert_eq!(
+            emulator.search_region(Region::Cpu, &[0x42], 0x0011),
+            Some(0x0810)
+        );
+    }
+
+    #[test]
+    fn ppu_viewers() {
+        use crate::emulator::{ppu::SYSTEM_PALETTE, ppu_viewer::*};
+
+        let mut ppu = PPU::new();
+        // tile 1: top row colors 1, 2, 3, 0, 0, 0, 0, 0
+        ppu.write_vram(0x0010, 0b1010_0000);
+        ppu.write_vram(0x0018, 0b0110_0000);
+        // tile 1 at the top left of nametable 1, with palette 2 from the
+        // attribute table
+        ppu.write_vram(0x2400, 0x01);
+        ppu.write_vram(0x27C0, 0b0000_0010);
+        ppu.write_vram(0x3F0B, 0x16);
+        ppu.oam[4..8].copy_from_slice(&[0x20, 0x01, 0x41, 0x30]);
+
+        let table = pattern_table(&ppu, 0, 1);
+        assert_eq!(&table[8..12], &[5, 6, 7, 4]);
+        assert_eq!(table[8 + PATTERN_TABLE_SIZE], 4);
+
+        let screens = nametables(&ppu);
+        assert_eq!(&screens[256..260], &[9, 10, 11, 0]);
+        assert_eq!(color(&ppu.palette, 11), SYSTEM_PALETTE[0x16]);
+
+        // fine Y 5, nametable 2, coarse Y 3, coarse X 2
+        ppu.t = (5 << 12) | (2 << 10) | (3 << 5) | 2;
+        ppu.x = 3;
+        assert_eq!(scroll(&ppu), (2 * 8 + 3, 240 + 3 * 8 + 5));
+
+        let sprite = sprites(&ppu)[1];
+        assert_eq!((sprite.x, sprite.y, sprite.tile), (0x30, 0x20, 0x01));
+        assert_eq!(sprite.palette(), 1);
+        assert!(sprite.flip_x() && !sprite.flip_y() && !sprite.behind_background());
+        let pixels = sprite_pixels(&ppu, &sprite);
+        assert_eq!(pixels.len(), 64);
+        assert_eq!(&pixels[..4], &[0x15, 0x16, 0x17, 0]);
+    }
+
+    #[test]
+    fn peek_has_no_side_effects() {
+        use crate::emulator::{controller::Button, ppu::Status};
+
+        let emulator = Emulator::new();
+        let mut memory = emulator.memory.borrow_mut();
+
+        memory.ppu.borrow_mut().status |= Status::VBlank;
+        assert_eq!(memory.peek(0x2002) & Status::VBlank, Status::VBlank);
+        assert_eq!(memory.peek(0x2002) & Status::VBlank, Status::VBlank);
+        assert_eq!(memory.read(0x2002) & Status::VBlank, Status::VBlank);
+        assert_eq!(memory.peek(0x2002) & Status::VBlank, 0);
+
+        // PPUDATA peeks show the read buffer and leave the address alone
+        memory.ppu.borrow_mut().write_vram(0x2000, 0x11);
+        memory.write(0x2006, 0x20);
+        memory.write(0x2006, 0x00);
+        memory.read(0x2007);
+        assert_eq!(memory.peek(0x2007), 0x11);
+        assert_eq!(memory.ppu.borrow().v, 0x2001);
+        assert_eq!(memory.read(0x2007), 0x11);
+
+        memory.controllers[0].buttons = Button::B;
+        memory.write(0x4016, 1);
+        memory.write(0x4016, 0);
+        assert_eq!(memory.peek(0x4016), 0);
+        assert_eq!(memory.peek(0x4016), 0);
+        assert_eq!(memory.read(0x4016), 0);
+        assert_eq!(memory.peek(0x4016), 1);
+        assert_eq!(memory.read(0x4016), 1);
+
+        assert_eq!(memory.peek(0x4000), 0);
+        assert_eq!(memory.peek(0x5000), 0xFF);
+    }
+
+    #[test]
+    fn game_genie_codes() {
+        use crate::emulator::cheats::{decode_game_genie, Code, RomPatch};
+
+        assert_eq!(
+            decode_game_genie("SXIOPO"),
+            Ok(RomPatch {
+                address: 0x91D9,
+                value: 0xAD,
+                compare: None,
+            })
+        );
+        assert_eq!(
+            decode_game_genie("zexpygla"),
+            Ok(RomPatch {
+                address: 0x94A7,
+                value: 0x02,
+                compare: Some(0x03),
+            })
+        );
+        assert_eq!(
+            decode_game_genie("YEUZUGAA").map(|patch| patch.compare),
+            Ok(Some(0x00))
+        );
+        assert!(decode_game_genie("SXIOP").is_err());
+        assert!(decode_game_genie("SXIOPB").is_err());
+
+        assert_eq!(
+            Code::parse("0075:09"),
+            Ok(Code::Ram {
+                address: 0x0075,
+                value: 0x09,
+            })
+        );
+        assert_eq!(
+            Code::parse("C123?45:EA"),
+            Ok(Code::Rom(RomPatch {
+                address: 0xC123,
+                value: 0xEA,
+                compare: Some(0x45),
+            }))
+        );
+        assert!(Code::parse("2000:00").is_err());
+        assert!(Code::parse("0075?01:09").is_err());
+    }
+
+    #[test]
+    fn cheats() {
+        let mut emulator = Emulator::new();
+        // JMP $0000, looping through the pointer there
+        emulator.load(vec![0x4C, 0x00, 0x00]);
+        emulator.memory.borrow_mut()[0x0001] = 0x80;
+        emulator.memory.borrow_mut().array[0x91D9] = 0x45;
+        emulator.memory.borrow_mut().array[0x91DA] = 0x46;
+
+        let rom = emulator.add_cheat("SXIOPO", "Infinite lives").unwrap();
+        emulator.add_cheat("91DA?99:EA", "Wrong bank").unwrap();
+        let ram = emulator.add_cheat("0075:09", "").unwrap();
+
+        {
+            let mut memory = emulator.memory.borrow_mut();
+            assert_eq!(memory.read(0x91D9), 0xAD);
+            assert_eq!(memory.peek(0x91D9), 0xAD);
+            assert_eq!(memory.read(0x91DA), 0x46);
+            // the ROM itself is untouched
+            assert_eq!(memory.array[0x91D9], 0x45);
+        }
+
+        emulator.set_cheat_enabled(rom, false);
+        assert_eq!(emulator.memory.borrow_mut().read(0x91D9), 0x45);
+
+        emulator.memory.borrow_mut().array[0x0075] = 1;
+        emulator.run_frame();
+        assert_eq!(emulator.memory.borrow().array[0x0075], 9);
+
+        emulator.remove_cheat(ram);
+        emulator.memory.borrow_mut().array[0x0075] = 1;
+        emulator.run_frame();
+        assert_eq!(emulator.memory.borrow().array[0x0075], 1);
+    }
+
+    #[test]
+    fn ram_search() {
+        use crate::emulator::ram_search::{Comparison, Operand, RamSearch, Size};
+
+        let emulator = Emulator::new();
+        let poke = |address: u16, value: u8| emulator.memory.borrow_mut()[address] = value;
+
+        poke(0x0042, 3);
+        poke(0x6010, 3);
+        let mut search = RamSearch::new(&emulator, Size::Byte, false);
+        assert_eq!(search.len(), 0x800 + 0x2000);
+
+        search.filter(&emulator, Comparison::Equal, Operand::Value(3));
+        assert_eq!(search.len(), 2);
+
+        poke(0x0042, 2);
+        search.filter(&emulator, Comparison::Less, Operand::Previous);
+        let candidates = search.candidates(&emulator, 10);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].address, 0x0042);
+        assert_eq!(candidates[0].current, 2);
+
+        // signed words, $FFFE is -2
+        poke(0x0100, 0xFE);
+        poke(0x0101, 0xFF);
+        let mut search = RamSearch::new(&emulator, Size::Word, true);
+        assert_eq!(search.len(), 0x800 + 0x2000 - 2);
+        search.filter(&emulator, Comparison::Less, Operand::Value(0));
+        // the word starting a byte earlier is $FE00
+        let candidates = search.candidates(&emulator, 10);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].current, -512);
+        assert_eq!(candidates[1].address, 0x0100);
+        assert_eq!(candidates[1].current, -2);
+    }
+
+    #[test]
+    fn watch_list() {
+        use crate::emulator::watch::{Format, Watch, WatchList};
+
+        let mut emulator = Emulator::new();
+        emulator.memory.borrow_mut()[0x0010] = 0x42;
+        emulator.memory.borrow_mut()[0x0011] = 0x81;
+
+        let byte = |format: Format| format.format([0x42, 0]);
+        assert_eq!(byte(Format::Hex), "$42");
+        assert_eq!(byte(Format::Decimal), "66");
+        assert_eq!(byte(Format::Bcd), "42");
+        assert_eq!(Format::Bcd.value([0x42, 0]), 42);
+        assert_eq!(Format::Signed.format([0x81, 0]), "-127");
+        assert_eq!(Format::Word.format([0x42, 0x81]), "33090");
+        assert_eq!(Format::SignedWord.format([0x42, 0x81]), "-32446");
+
+        let mut watches = WatchList::default();
+        watches
+            .watches
+            .push(Watch::new(0x0010, Format::Word, "Score"));
+        watches.watches.push(Watch::new(0x0011, Format::Signed, ""));
+        watches.set_history(0, true);
+        watches.on_frame(&mut emulator);
+        watches.set_frozen(1, true);
+
+        emulator.memory.borrow_mut()[0x0010] = 0x43;
+        emulator.memory.borrow_mut()[0x0011] = 0x00;
+        watches.on_frame(&mut emulator);
+        assert_eq!(watches.watches[1].text(), "-127");
+        assert_eq!(emulator.memory.borrow()[0x0011], 0x81);
+        let history: Vec<i64> = watches.watches[0].history.clone().unwrap().into();
+        assert_eq!(history, vec![0x8142, 0x8143]);
+
+        let path = std::env::temp_dir().join(format!("watch_test_{}.wch", std::process::id()));
+        watches.save(&path).unwrap();
+        let loaded = WatchList::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.watches.len(), 2);
+        assert_eq!(loaded.watches[0].label, "Score");
+        assert_eq!(loaded.watches[0].format, Format::Word);
+        assert!(loaded.watches[0].history.is_some());
+        assert_eq!(loaded.watches[1].format, Format::Signed);
+        // freezes only last for the session
+        assert!(loaded.watches[1].frozen.is_none());
+
+        std::fs::write(&path, "0075   dec\tLives  left\n0090  word  +history\n").unwrap();
+        let loaded = WatchList::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.watches[0].address, 0x0075);
+        assert_eq!(loaded.watches[0].label, "Lives  left");
+        assert_eq!(loaded.watches[1].format, Format::Word);
+        assert!(loaded.watches[1].history.is_some());
+    }
+
+    #[test]
+    fn fm2_round_trip() {
+        use crate::emulator::movie::Movie;
+
+        let text = "version 3\nemuVersion 22020\nrerecordCount 7\npalFlag 0\n\
+                    romFilename smb\nromChecksum base64:kRs6FZpHnWNfy7Qjk+uJzA==\n\
+                    guid 5CA3D3B4-9B4A-5E4A-4F44-7E5F3F9B7A66\nfourscore 0\nport0 1\n\
+                    port1 0\nport2 0\ncomment author someone\n\
+                    |0|........|||\n|0|....T..A|||\n|1|R......A|||\n";
+        let movie = Movie::parse_fm2(text).unwrap();
+        assert_eq!(movie.rerecords, 7);
+        assert_eq!(movie.rom_filename, "smb");
+        assert_eq!(movie.comments, vec!["author someone".to_string()]);
+        assert_eq!(movie.frames.len(), 3);
+        assert_eq!(movie.frames[1].buttons, [Button::Start | Button::A, 0]);
+        assert_eq!(movie.frames[2].commands, 1);
+        assert_eq!(movie.frames[2].buttons[0], Button::Right | Button::A);
+
+        let mut with_state = movie.clone();
+        with_state.savestate = Some(vec![0, 1, 2, 0xFE, 0xFF]);
+        let exported = with_state.to_fm2();
+        assert!(exported.contains("romChecksum base64:kRs6FZpHnWNfy7Qjk+uJzA==\n"));
+        assert!(exported.contains("|0|....T..A|........||\n"));
+        assert_eq!(Movie::parse_fm2(&exported).unwrap(), with_state);
+
+        assert!(Movie::parse_fm2("version 2\n").is_err());
+        assert!(Movie::parse_fm2("port0 2\n").is_err());
+        assert!(Movie::parse_fm2("|0|..X|||\n").is_err());
+    }
+
+    #[test]
+    fn movie_record_and_playback() {
+        // copies the A button into $10 over and over, looping through the
+        // reset vector since the power cycle clears RAM
+        use crate::emulator::asm::assemble;
+
+        let program = assemble(
+            "
+            LDA #$01
+            STA $4016
+            LDA #$00
+            STA $4016
+            LDA $4016
+            STA $10
+            JMP $FFFC
+            ",
+        )
+        .unwrap();
+        let power_on = || {
+            let mut emulator = Emulator::new();
+            emulator.load(program.bytes.clone());
+            emulator
+        };
+        let a_pressed = |emulator: &Emulator| emulator.memory.borrow()[0x0010] & 1 == 1;
+
+        let mut emulator = power_on();
+        emulator.record_movie(false, "test");
+        emulator.run_frame();
+        emulator.set_buttons(0, Button::A);
+        emulator.run_frame();
+        emulator.run_frame();
+        assert!(a_pressed(&emulator));
+        let state = emulator.save_state();
+        emulator.set_buttons(0, 0);
+        emulator.run_frame();
+        emulator.run_frame();
+        assert!(!a_pressed(&emulator));
+
+        // loading a state while recording rerecords from there
+        emulator.load_state(&state).unwrap();
+        emulator.set_buttons(0, Button::A);
+        emulator.run_frame();
+        let recorded = emulator.stop_movie().unwrap();
+        assert_eq!(recorded.rerecords, 1);
+        let buttons: Vec<u8> = recorded
+            .frames
+            .iter()
+            .map(|frame| frame.buttons[0])
+            .collect();
+        assert_eq!(buttons, vec![0, Button::A, Button::A, Button::A]);
+
+        // playback starts with a power cycle, whatever ran before
+        let mut playback = power_on();
+        playback.set_buttons(0, Button::A);
+        playback.run_frame();
+        playback.play_movie(recorded.clone(), true).unwrap();
+        // live input is ignored during playback
+        playback.set_buttons(0, 0);
+        for _ in 0..4 {
+            playback.run_frame();
+        }
+        assert!(a_pressed(&playback));
+        assert!(playback.movie().unwrap().is_finished(&playback));
+        assert_eq!(
+            playback.memory.borrow().array[..0x800],
+            emulator.memory.borrow().array[..0x800]
+        );
+
+        // read only loads keep playing instead of recording
+        playback.load_state(&state).unwrap();
+        assert!(!playback.movie().unwrap().is_recording());
+        assert_eq!(playback.movie().unwrap().movie.rerecords, 1);
+    }
+
+    #[test]
+    fn seeded_runs_hash_the_same() {
+        // sums uninitialized RAM into $20 over and over
+        use crate::emulator::{asm::assemble, desync::FrameHashes, ram::RamInit};
+
+        let program = assemble(
+            "
+            LDA $20
+            CLC
+            ADC $21
+            STA $20
+            JMP $0000
+            ",
+        )
+        .unwrap();
+        let run = |seed: u64| {
+            let mut emulator = Emulator::with_ram_init(RamInit::Random(seed));
+            emulator.load(program.bytes.clone());
+            emulator.memory.borrow_mut().write_u16(0x0000, 0x8000);
+            emulator.hashes = Some(FrameHashes::new());
+            for _ in 0..3 {
+                emulator.run_frame();
+            }
+            emulator.hashes.take().unwrap()
+        };
+
+        let hashes = run(1);
+        assert_eq!(hashes.hashes.len(), 3);
+        assert_eq!(hashes.first_divergence(&run(1)), None);
+        assert_eq!(hashes.first_divergence(&run(2)), Some(1));
+    }
+
+    #[test]
+    fn first_divergence() {
+        use crate::emulator::desync::FrameHashes;
+
+        let run = |hashes: &[(u64, u64)]| FrameHashes {
+            hashes: hashes.to_vec(),
+        };
+        let a = run(&[(1, 10), (2, 20), (3, 30)]);
+        assert_eq!(
+            a.first_divergence(&run(&[(1, 10), (2, 21), (3, 30)])),
+            Some(2)
+        );
+        // a run that stops early diverges where it stops
+        assert_eq!(a.first_divergence(&run(&[(1, 10)])), Some(2));
+        assert_eq!(run(&[(1, 10)]).first_divergence(&a), Some(2));
+    }
+
+    #[test]
+    fn ram_init_patterns() {
+        use crate::emulator::ram::RamInit;
+
+        let internal_ram = |init: RamInit| {
+            let emulator = Emulator::with_ram_init(init);
+            let bytes = emulator.memory.borrow().array[..0x800].to_vec();
+            assert_eq!(emulator.memory.borrow().array[0x800], 0);
+            bytes
+        };
+
+        assert!(internal_ram(RamInit::Zeros).iter().all(|&byte| byte == 0));
+        assert!(internal_ram(RamInit::Ones).iter().all(|&byte| byte == 0xFF));
+        assert_eq!(
+            internal_ram(RamInit::Alternating)[..12],
+            [0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]
+        );
+        let random = internal_ram(RamInit::Random(5));
+        assert_eq!(random, internal_ram(RamInit::Random(5)));
+        assert_ne!(random, internal_ram(RamInit::Random(6)));
+
+        assert_eq!(RamInit::parse("random:5"), Ok(RamInit::Random(5)));
+        assert_eq!(RamInit::parse("ff"), Ok(RamInit::Ones));
+        assert!(RamInit::parse("random").is_err());
+    }
+
+    #[test]
+    fn cpu_power_on_and_soft_reset() {
+        let mut emulator = Emulator::new();
+        emulator.load(vec![0xEA]);
+        emulator.cpu.acc = 0x12;
+        emulator.cpu.sp = 0x00;
+
+        emulator.cpu.power_on();
+        assert_eq!(emulator.cpu.acc, 0);
+        assert_eq!(emulator.cpu.status, 0x34);
+        assert_eq!(emulator.cpu.sp, 0xFD);
+        assert_eq!(emulator.cpu.pc, 0x8000);
+
+        emulator.cpu.acc = 0x12;
+        emulator.cpu.status = 0x01;
+        emulator.cpu.pc = 0x8001;
+        emulator.cpu.soft_reset();
+        assert_eq!(emulator.cpu.acc, 0x12);
+        assert_eq!(emulator.cpu.status, 0x05);
+        assert_eq!(emulator.cpu.sp, 0xFA);
+        assert_eq!(emulator.cpu.pc, 0x8000);
+    }
+
+    #[test]
+    fn soft_reset_and_power_cycle() {
+        use crate::emulator::ram::RamInit;
+
+        let mut emulator = Emulator::with_ram_init(RamInit::Ones);
+        emulator.load(vec![0xEA]);
+        emulator.cpu.power_on();
+        emulator.memory.borrow_mut()[0x0010] = 0x42;
+        emulator.cpu.sp = 0xF0;
+        emulator.cpu.pc = 0x8001;
+        emulator.ppu.borrow_mut().ctrl = 0x80;
+        emulator.ppu.borrow_mut().vram[0] = 0x05;
+        emulator.memory.borrow_mut()[0x6000] = 0x12;
+        {
+            let mut ppu = emulator.ppu.borrow_mut();
+            ppu.frame = 7;
+            ppu.scanline = 100;
+            ppu.framebuffer[0] = 0xFF;
+        }
+        emulator.frames.0 = 7;
+
+        emulator.soft_reset();
+        assert_eq!(emulator.memory.borrow()[0x0010], 0x42);
+        assert_eq!(emulator.cpu.sp, 0xED);
+        assert_eq!(emulator.cpu.status & 0x04, 0x04);
+        assert_eq!(emulator.cpu.pc, 0x8000);
+        assert_eq!(emulator.ppu.borrow().ctrl, 0);
+        assert_eq!(emulator.ppu.borrow().vram[0], 0x05);
+
+        emulator.power_cycle();
+        assert_eq!(emulator.memory.borrow()[0x0010], 0xFF);
+        assert_eq!(emulator.cpu.sp, 0xFD);
+        assert_eq!(emulator.cpu.status, 0x34);
+        assert_eq!(emulator.ppu.borrow().vram[0], 0);
+        assert_eq!(emulator.ppu.borrow().frame, 0);
+        assert_eq!(emulator.ppu.borrow().scanline, 0);
+        assert_eq!(emulator.ppu.borrow().framebuffer[0], 0);
+        assert_eq!(emulator.memory.borrow()[0x6000], 0xFF);
+        // ROM and the frame count survive
+        assert_eq!(emulator.memory.borrow()[0x8000], 0xEA);
+        assert_eq!(emulator.frame_count(), 7);
+
+        // battery backed PRG-RAM is kept
+        emulator.memory.borrow_mut().battery = true;
+        emulator.memory.borrow_mut()[0x6000] = 0x34;
+        emulator.power_cycle();
+        assert_eq!(emulator.memory.borrow()[0x6000], 0x34);
+    }
+
+    #[test]
+    fn movie_records_resets() {
+        use crate::emulator::movie::Command;
+
+        let mut emulator = Emulator::new();
+        // JMP through the reset vector, RAM is cleared by the power cycle
+        emulator.load(vec![0x4C, 0xFC, 0xFF]);
+        emulator.record_movie(false, "test");
+        emulator.run_frame();
+
+        // waits for the next frame while recording
+        emulator.soft_reset();
+        assert_eq!(emulator.cpu.sp, 0xFD);
+        emulator.run_frame();
+        assert_eq!(emulator.cpu.sp, 0xFA);
+
+        let movie = emulator.stop_movie().unwrap();
+        assert_eq!(movie.frames[0].commands, 0);
+        assert_eq!(movie.frames[1].commands, Command::SoftReset);
+    }
+}
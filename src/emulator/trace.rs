@@ -0,0 +1,331 @@
+//! Per instruction CPU traces.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use super::{
+    cpu::AddressingMode::*,
+    disasm,
+    opcodes::{self, Opcode},
+    ram::RAM,
+    Emulator,
+};
+
+/// Reads memory for display. IO registers read as $FF instead of being
+/// touched, like in Nintendulator logs.
+fn peek(memory: &RAM, address: u16) -> u8 {
+    match address {
+        0x2000..=0x5FFF => 0xFF,
+        _ => memory.peek(address),
+    }
+}
+
+fn peek_u16(memory: &RAM, address: u16) -> u16 {
+    u16::from_le_bytes([peek(memory, address), peek(memory, address.wrapping_add(1))])
+}
+
+/// Reads a pointer from the zero page, wrapping within it
+fn peek_zp_u16(memory: &RAM, address: u8) -> u16 {
+    u16::from_le_bytes([
+        peek(memory, address as u16),
+        peek(memory, address.wrapping_add(1) as u16),
+    ])
+}
+
+/// Formats the operand of the instruction at `pc` like Nintendulator does,
+/// including the effective address and the value stored there
+fn nintendulator_operand(emulator: &Emulator, pc: u16, opcode: &Opcode) -> String {
+    let memory = emulator.memory.borrow();
+    let cpu = &emulator.cpu;
+
+    let byte = peek(&memory, pc.wrapping_add(1));
+    let word = peek_u16(&memory, pc.wrapping_add(1));
+
+    match opcode.mode {
+        NoneAddressing => String::new(),
+        Accumulator => "A".to_string(),
+        Immediate => format!("#${:02X}", byte),
+        ZeroPage => format!("${:02X} = {:02X}", byte, peek(&memory, byte as u16)),
+        ZeroPageX | ZeroPageY => {
+            let (index, name) = if opcode.mode == ZeroPageX {
+                (cpu.idx_x, 'X')
+            } else {
+                (cpu.idx_y, 'Y')
+            };
+            let address = byte.wrapping_add(index);
+            format!(
+                "${:02X},{} @ {:02X} = {:02X}",
+                byte,
+                name,
+                address,
+                peek(&memory, address as u16)
+            )
+        }
+        Absolute => {
+            if matches!(opcode.mnemonic, "JMP" | "JSR") {
+                format!("${:04X}", word)
+            } else {
+                format!("${:04X} = {:02X}", word, peek(&memory, word))
+            }
+        }
+        AbsoluteX | AbsoluteY => {
+            let (index, name) = if opcode.mode == AbsoluteX {
+                (cpu.idx_x, 'X')
+            } else {
+                (cpu.idx_y, 'Y')
+            };
+            let address = word.wrapping_add(index as u16);
+            format!(
+                "${:04X},{} @ {:04X} = {:02X}",
+                word,
+                name,
+                address,
+                peek(&memory, address)
+            )
+        }
+        Indirect => {
+            // the high byte is fetched without carrying into the page
+            let high = (word & 0xFF00) | (word.wrapping_add(1) & 0x00FF);
+            let target = u16::from_le_bytes([peek(&memory, word), peek(&memory, high)]);
+            format!("(${:04X}) = {:04X}", word, target)
+        }
+        IndirectX => {
+            let pointer = byte.wrapping_add(cpu.idx_x);
+            let address = peek_zp_u16(&memory, pointer);
+            format!(
+                "(${:02X},X) @ {:02X} = {:04X} = {:02X}",
+                byte,
+                pointer,
+                address,
+                peek(&memory, address)
+            )
+        }
+        IndirectY => {
+            let base = peek_zp_u16(&memory, byte);
+            let address = base.wrapping_add(cpu.idx_y as u16);
+            format!(
+                "(${:02X}),Y = {:04X} @ {:04X} = {:02X}",
+                byte,
+                base,
+                address,
+                peek(&memory, address)
+            )
+        }
+        Relative => {
+            let target = pc.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            format!("${:04X}", target)
+        }
+    }
+}
+
+/// Formats the instruction the CPU is about to execute as a line of a
+/// Nintendulator log, the format of the reference nestest.log:
+///
+/// ```text
+/// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+/// ```
+pub fn nintendulator_line(emulator: &Emulator) -> String {
+    let cpu = &emulator.cpu;
+    let pc = cpu.pc;
+
+    let (opcode, bytes) = {
+        let memory = emulator.memory.borrow();
+        let opcode = opcodes::lookup(peek(&memory, pc));
+        let bytes: Vec<String> = (0..opcode.size())
+            .map(|i| format!("{:02X}", peek(&memory, pc.wrapping_add(i))))
+            .collect();
+        (opcode, bytes.join(" "))
+    };
+
+    let mnemonic = if opcode.unofficial {
+        format!("*{}", opcode.mnemonic)
+    } else {
+        opcode.mnemonic.to_string()
+    };
+    let operand = nintendulator_operand(emulator, pc, opcode);
+
+    let ppu = emulator.ppu.borrow();
+    format!(
+        "{:04X}  {:<8} {:>4} {:<27} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        pc,
+        bytes,
+        mnemonic,
+        operand,
+        cpu.acc,
+        cpu.idx_x,
+        cpu.idx_y,
+        cpu.status,
+        cpu.sp,
+        ppu.scanline,
+        ppu.dot,
+        cpu.cycles
+    )
+}
+
+/// Status flags as letters, uppercase when set
+pub fn format_flags(status: u8) -> String {
+    "NVUBDIZC"
+        .chars()
+        .enumerate()
+        .map(|(i, flag)| {
+            if status & (0x80 >> i) != 0 {
+                flag
+            } else {
+                flag.to_ascii_lowercase()
+            }
+        })
+        .collect()
+}
+
+/// Formats the instruction the CPU is about to execute:
+///
+/// ```text
+/// C000  4C F5 C5  JMP $C5F5                  A:00 X:00 Y:00 P:24 nvUbdIzc SP:FD CYC:7 SL:0 DOT:21
+/// ```
+pub fn trace_line(emulator: &Emulator) -> String {
+    let cpu = &emulator.cpu;
+    let memory = emulator.memory.borrow();
+    let ppu = emulator.ppu.borrow();
+
+    let (instruction, _) = disasm::disassemble(&memory, cpu.pc);
+    let bytes: Vec<String> = instruction
+        .bytes()
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect();
+
+    format!(
+        "{:04X}  {:<8}  {:<26} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} {} SP:{:02X} CYC:{} SL:{} DOT:{}",
+        cpu.pc,
+        bytes.join(" "),
+        instruction.format_resolved(cpu, &memory),
+        cpu.acc,
+        cpu.idx_x,
+        cpu.idx_y,
+        cpu.status,
+        format_flags(cpu.status),
+        cpu.sp,
+        cpu.cycles,
+        ppu.scanline,
+        ppu.dot
+    )
+}
+
+pub type TraceCondition = Box<dyn Fn(&Emulator) -> bool>;
+
+/// Logs every instruction the CPU executes while set as
+/// [`Emulator::trace`]. The last lines are always kept in a ring buffer so
+/// they can be dumped after a crash or breakpoint, and can also be written to
+/// a file.
+pub struct TraceLogger {
+    file: Option<BufWriter<File>>,
+    /// Why writing to the file stopped
+    pub error: Option<String>,
+    recent: VecDeque<String>,
+    capacity: usize,
+    /// Only log instructions in these ranges, or everywhere when empty
+    pub ranges: Vec<RangeInclusive<u16>>,
+    /// Don't log anything until the CPU reaches this address
+    pub trigger: Option<u16>,
+    triggered: bool,
+    /// Only log while this holds
+    pub condition: Option<TraceCondition>,
+}
+
+impl TraceLogger {
+    /// Keeps the last `capacity` lines in memory only
+    pub fn ring(capacity: usize) -> Self {
+        TraceLogger {
+            file: None,
+            error: None,
+            recent: VecDeque::with_capacity(capacity),
+            capacity,
+            ranges: Vec::new(),
+            trigger: None,
+            triggered: false,
+            condition: None,
+        }
+    }
+
+    /// Writes every line to `path`, and keeps the last `capacity` in memory
+    pub fn to_file(path: &Path, capacity: usize) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let mut logger = TraceLogger::ring(capacity);
+        logger.file = Some(BufWriter::new(file));
+        Ok(logger)
+    }
+
+    /// Called before each instruction executes
+    pub fn log(&mut self, emulator: &Emulator) {
+        // the CPU takes the NMI instead of the instruction at PC, the
+        // handler's first instruction is the next line
+        if emulator.cpu.nmi_pending() {
+            return;
+        }
+
+        let pc = emulator.cpu.pc;
+
+        if !self.triggered {
+            if self.trigger.is_some_and(|trigger| trigger != pc) {
+                return;
+            }
+            self.triggered = true;
+        }
+
+        if !self.ranges.is_empty() && !self.ranges.iter().any(|range| range.contains(&pc)) {
+            return;
+        }
+        if self
+            .condition
+            .as_ref()
+            .is_some_and(|condition| !condition(emulator))
+        {
+            return;
+        }
+
+        let line = trace_line(emulator);
+
+        if let Some(file) = &mut self.file {
+            if let Err(e) = writeln!(file, "{}", line) {
+                self.error = Some(format!("Trace file write failed: {}", e));
+                self.file = None;
+            }
+        }
+
+        if self.capacity > 0 {
+            if self.recent.len() == self.capacity {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(line);
+        }
+    }
+
+    /// The last lines logged, oldest first
+    pub fn recent(&self) -> impl Iterator<Item = &String> {
+        self.recent.iter()
+    }
+
+    /// The last lines logged as one string, for crash and breakpoint reports
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        for line in &self.recent {
+            dump += line;
+            dump.push('\n');
+        }
+        dump
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(file) = &mut self.file {
+            if let Err(e) = file.flush() {
+                self.error = Some(format!("Trace file write failed: {}", e));
+            }
+        }
+    }
+}
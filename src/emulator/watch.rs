@@ -0,0 +1,263 @@
+//! RAM watch list. Watches show an address in a chosen format every frame,
+//! can keep a history of recent values and can freeze the value they show.
+//!
+//! Watches are kept in a `.wch` file next to the ROM, one per line:
+//!
+//! ```text
+//! 0075 dec Lives
+//! 0090 word +history Score
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{battery::write_atomic, region::Region, Emulator};
+
+/// Frames of history kept for watches that have it
+pub const HISTORY_FRAMES: usize = 120;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Hex,
+    Decimal,
+    Signed,
+    /// Two decimal digits, one per nibble
+    Bcd,
+    /// Two bytes, little endian
+    Word,
+    /// Two bytes, little endian, two's complement
+    SignedWord,
+}
+
+impl Format {
+    pub const ALL: [Format; 6] = [
+        Format::Hex,
+        Format::Decimal,
+        Format::Signed,
+        Format::Bcd,
+        Format::Word,
+        Format::SignedWord,
+    ];
+
+    /// Name used in watch files
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Hex => "hex",
+            Format::Decimal => "dec",
+            Format::Signed => "signed",
+            Format::Bcd => "bcd",
+            Format::Word => "word",
+            Format::SignedWord => "sword",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        Format::ALL.into_iter().find(|format| format.name() == name)
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            Format::Word | Format::SignedWord => 2,
+            _ => 1,
+        }
+    }
+
+    /// Numeric value of the first `size` bytes, for the history. BCD
+    /// digits over 9 count as their nibble values.
+    pub fn value(self, bytes: [u8; 2]) -> i64 {
+        match self {
+            Format::Hex | Format::Decimal => bytes[0] as i64,
+            Format::Signed => bytes[0] as i8 as i64,
+            Format::Bcd => (bytes[0] >> 4) as i64 * 10 + (bytes[0] & 0x0F) as i64,
+            Format::Word => u16::from_le_bytes(bytes) as i64,
+            Format::SignedWord => i16::from_le_bytes(bytes) as i64,
+        }
+    }
+
+    pub fn format(self, bytes: [u8; 2]) -> String {
+        match self {
+            Format::Hex => format!("${:02X}", bytes[0]),
+            // BCD shown as is, so invalid digits stand out as A-F
+            Format::Bcd => format!("{:02X}", bytes[0]),
+            _ => self.value(bytes).to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Watch {
+    pub address: u16,
+    pub label: String,
+    pub format: Format,
+    /// Keep the last `HISTORY_FRAMES` values
+    pub history: Option<VecDeque<i64>>,
+    /// Bytes written back every frame while frozen
+    pub frozen: Option<[u8; 2]>,
+    /// Bytes as of the last frame
+    pub bytes: [u8; 2],
+}
+
+impl Watch {
+    pub fn new(address: u16, format: Format, label: &str) -> Self {
+        Watch {
+            address,
+            label: label.trim().to_string(),
+            format,
+            history: None,
+            frozen: None,
+            bytes: [0; 2],
+        }
+    }
+
+    /// Updates `bytes` without touching the history
+    pub fn read(&mut self, emulator: &Emulator) {
+        self.bytes = [0; 2];
+        for (i, byte) in self.bytes[..self.format.size()].iter_mut().enumerate() {
+            let address = self.address.wrapping_add(i as u16);
+            *byte = emulator.peek_region(Region::Cpu, address as usize);
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.format.format(self.bytes)
+    }
+}
+
+/// Splits the first whitespace separated field off `text`, the label is
+/// whatever is left so it keeps its spaces
+fn next_field(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    text.split_once(char::is_whitespace).unwrap_or((text, ""))
+}
+
+/// Watches are stored as `<rom>.wch`
+pub fn watch_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("wch")
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WatchList {
+    pub watches: Vec<Watch>,
+}
+
+impl WatchList {
+    /// Reads a watch file, a missing file has no watches
+    pub fn load(path: &Path) -> Result<WatchList, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(WatchList::default()),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+
+        let mut watches = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: &str| format!("{}:{}: {}", path.display(), number + 1, message);
+            let (address, rest) = next_field(line);
+            let address =
+                u16::from_str_radix(address, 16).map_err(|_| error("Expected a hex address"))?;
+            let (format, rest) = next_field(rest);
+            let format = Format::from_name(format)
+                .ok_or_else(|| error("Expected hex, dec, signed, bcd, word or sword"))?;
+
+            let rest = rest.trim_start();
+            let (history, label) = match rest.strip_prefix("+history") {
+                Some(label) => (true, label),
+                None => (false, rest),
+            };
+
+            let mut watch = Watch::new(address, format, label);
+            if history {
+                watch.history = Some(VecDeque::with_capacity(HISTORY_FRAMES));
+            }
+            watches.push(watch);
+        }
+
+        Ok(WatchList { watches })
+    }
+
+    /// Writes the watches, frozen values aren't kept
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut text = String::new();
+        for watch in &self.watches {
+            text.push_str(&format!("{:04X} {}", watch.address, watch.format.name()));
+            if watch.history.is_some() {
+                text.push_str(" +history");
+            }
+            if !watch.label.is_empty() {
+                text.push(' ');
+                text.push_str(&watch.label);
+            }
+            text.push('\n');
+        }
+
+        write_atomic(path, text.as_bytes()).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Reads every watch, e.g. after a change while the emulator is paused
+    pub fn refresh(&mut self, emulator: &Emulator) {
+        for watch in &mut self.watches {
+            watch.read(emulator);
+        }
+    }
+
+    /// Freezes a watch at the value it has now, or unfreezes it
+    pub fn set_frozen(&mut self, index: usize, frozen: bool) {
+        if let Some(watch) = self.watches.get_mut(index) {
+            watch.frozen = if frozen { Some(watch.bytes) } else { None };
+        }
+    }
+
+    /// Changes how a watch is shown. Frozen watches are frozen again at
+    /// their current value, which may now cover another byte.
+    pub fn set_format(&mut self, index: usize, format: Format, emulator: &Emulator) {
+        if let Some(watch) = self.watches.get_mut(index) {
+            watch.format = format;
+            watch.read(emulator);
+            if watch.frozen.is_some() {
+                watch.frozen = Some(watch.bytes);
+            }
+        }
+    }
+
+    pub fn set_history(&mut self, index: usize, history: bool) {
+        if let Some(watch) = self.watches.get_mut(index) {
+            watch.history = if history {
+                Some(VecDeque::with_capacity(HISTORY_FRAMES))
+            } else {
+                None
+            };
+        }
+    }
+
+    /// Writes frozen values back, then reads every watch and records its
+    /// history. Called once per frame.
+    pub fn on_frame(&mut self, emulator: &mut Emulator) {
+        // all freezes go first, watches may overlap
+        for watch in &self.watches {
+            if let Some(frozen) = watch.frozen {
+                for (i, &byte) in frozen[..watch.format.size()].iter().enumerate() {
+                    let address = watch.address.wrapping_add(i as u16);
+                    emulator.poke_region(Region::Cpu, address as usize, byte);
+                }
+            }
+        }
+
+        for watch in &mut self.watches {
+            watch.read(emulator);
+            if let Some(history) = &mut watch.history {
+                if history.len() == HISTORY_FRAMES {
+                    history.pop_front();
+                }
+                history.push_back(watch.format.value(watch.bytes));
+            }
+        }
+    }
+}
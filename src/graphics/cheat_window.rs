@@ -0,0 +1,128 @@
+use egui_sdl2_gl::egui;
+
+use crate::emulator::cheats::{Cheat, Code};
+
+/// Sent from the cheat window to the emulator thread
+pub enum CheatAction {
+    Add { code: String, description: String },
+    Remove(usize),
+    SetEnabled(usize, bool),
+}
+
+/// What a decoded code does, e.g. `ROM $91D9 = $AD if $45`
+fn describe(code: &Code) -> String {
+    match code {
+        Code::Rom(patch) => match patch.compare {
+            Some(compare) => format!(
+                "ROM ${:04X} = ${:02X} if ${:02X}",
+                patch.address, patch.value, compare
+            ),
+            None => format!("ROM ${:04X} = ${:02X}", patch.address, patch.value),
+        },
+        Code::Ram { address, value } => format!("RAM ${:04X} = ${:02X}", address, value),
+    }
+}
+
+/// Lists the cheats of the running ROM, with checkboxes to toggle them and
+/// fields to add Game Genie or raw codes
+pub struct CheatWindow {
+    pub open: bool,
+    cheats: Vec<Cheat>,
+    code: String,
+    description: String,
+    actions: Vec<CheatAction>,
+}
+
+impl CheatWindow {
+    pub fn new() -> Self {
+        CheatWindow {
+            open: false,
+            cheats: Vec::new(),
+            code: String::new(),
+            description: String::new(),
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, cheats: Vec<Cheat>) {
+        self.cheats = cheats;
+    }
+
+    pub fn show(&mut self, ctx: &egui::CtxRef) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        egui::Window::new("Cheats").open(&mut open).show(ctx, |ui| {
+            if self.cheats.is_empty() {
+                ui.label("No cheats for this ROM");
+            }
+
+            egui::Grid::new("cheats").striped(true).show(ui, |ui| {
+                for (i, cheat) in self.cheats.iter().enumerate() {
+                    let mut enabled = cheat.enabled;
+                    if ui.checkbox(&mut enabled, "").changed() {
+                        self.actions.push(CheatAction::SetEnabled(i, enabled));
+                    }
+                    ui.monospace(&cheat.code)
+                        .on_hover_text(describe(&cheat.decoded));
+                    ui.label(&cheat.description);
+                    if ui.small_button("x").clicked() {
+                        self.actions.push(CheatAction::Remove(i));
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.label("Code");
+                ui.add(egui::TextEdit::singleline(&mut self.code).desired_width(80.0));
+                ui.label("Description");
+                ui.add(egui::TextEdit::singleline(&mut self.description).desired_width(120.0));
+            });
+
+            let parsed = if self.code.trim().is_empty() {
+                None
+            } else {
+                Some(Code::parse(&self.code))
+            };
+            ui.horizontal(|ui| {
+                match &parsed {
+                    Some(Ok(code)) => {
+                        ui.monospace(describe(code));
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                    None => {
+                        ui.label("Game Genie, AAAA:VV or AAAA?CC:VV");
+                    }
+                }
+
+                if ui
+                    .add_enabled(matches!(parsed, Some(Ok(_))), egui::Button::new("Add"))
+                    .clicked()
+                {
+                    self.actions.push(CheatAction::Add {
+                        code: std::mem::take(&mut self.code),
+                        description: std::mem::take(&mut self.description),
+                    });
+                }
+            });
+        });
+        self.open = open;
+    }
+
+    /// Returns the actions queued since this was last called
+    pub fn take_actions(&mut self) -> Vec<CheatAction> {
+        std::mem::take(&mut self.actions)
+    }
+}
+
+impl Default for CheatWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
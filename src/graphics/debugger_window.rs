@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+
+use egui_sdl2_gl::egui::{self, Color32, RichText};
+
+use crate::emulator::{
+    debugger::{Breakpoint, CallFrame, Stop},
+    disasm,
+    expr::Expression,
+    trace::format_flags,
+    Emulator,
+};
+
+/// Instructions shown from the PC on
+const DISASSEMBLY_LINES: usize = 24;
+
+const FLAGS: [(&str, u8); 8] = [
+    ("N", 0x80),
+    ("V", 0x40),
+    ("U", 0x20),
+    ("B", 0x10),
+    ("D", 0x08),
+    ("I", 0x04),
+    ("Z", 0x02),
+    ("C", 0x01),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    P,
+    Sp,
+    Pc,
+}
+
+const REGISTERS: [(Register, &str); 6] = [
+    (Register::A, "A"),
+    (Register::X, "X"),
+    (Register::Y, "Y"),
+    (Register::P, "P"),
+    (Register::Sp, "SP"),
+    (Register::Pc, "PC"),
+];
+
+/// Sent from the debugger window to the emulator thread, which owns the
+/// emulator and applies them between cycles
+pub enum DebugAction {
+    /// The window was opened or closed, calls are only tracked while open
+    Attach(bool),
+    Pause,
+    Resume,
+    StepInto,
+    StepOver,
+    StepOut,
+    FrameAdvance,
+    RunTo(u16),
+    /// Adds an exec breakpoint at the address, or removes the ones there
+    ToggleBreakpoint(u16),
+    SetBreakpointEnabled(usize, bool),
+    RemoveBreakpoint(usize),
+    SetCondition(usize, String),
+    SetRegister(Register, u16),
+    /// Sets or clears status flags, see [`FLAGS`]
+    SetFlag(u8, bool),
+}
+
+impl DebugAction {
+    pub fn apply(self, emulator: &mut Emulator) {
+        match self {
+            DebugAction::Attach(attached) => emulator.debugger.set_call_tracking(attached),
+            DebugAction::Pause => emulator.pause(),
+            DebugAction::Resume => emulator.resume(),
+            DebugAction::StepInto => emulator.step_into(),
+            DebugAction::StepOver => emulator.step_over(),
+            DebugAction::StepOut => emulator.step_out(),
+            DebugAction::FrameAdvance => emulator.frame_advance(),
+            DebugAction::RunTo(address) => emulator.run_to(address),
+            DebugAction::ToggleBreakpoint(address) => {
+                let existing: Vec<usize> = emulator
+                    .debugger
+                    .breakpoints()
+                    .iter()
+                    .filter(|entry| entry.breakpoint == Breakpoint::Exec(address))
+                    .map(|entry| entry.id)
+                    .collect();
+
+                if existing.is_empty() {
+                    emulator.add_breakpoint(Breakpoint::Exec(address));
+                }
+                for id in existing {
+                    emulator.remove_breakpoint(id);
+                }
+            }
+            DebugAction::SetBreakpointEnabled(id, enabled) => {
+                emulator.set_breakpoint_enabled(id, enabled)
+            }
+            DebugAction::RemoveBreakpoint(id) => emulator.remove_breakpoint(id),
+            DebugAction::SetCondition(id, condition) => {
+                if let Err(e) = emulator.set_breakpoint_condition(id, &condition) {
+                    println!("Failed to set breakpoint condition: {}", e);
+                }
+            }
+            DebugAction::SetRegister(register, value) => {
+                let cpu = &mut emulator.cpu;
+                match register {
+                    Register::A => cpu.acc = value as u8,
+                    Register::X => cpu.idx_x = value as u8,
+                    Register::Y => cpu.idx_y = value as u8,
+                    Register::P => cpu.status = value as u8,
+                    Register::Sp => cpu.sp = value as u8,
+                    Register::Pc => cpu.pc = value,
+                }
+            }
+            DebugAction::SetFlag(mask, set) => {
+                let cpu = &mut emulator.cpu;
+                if set {
+                    cpu.status |= mask;
+                } else {
+                    cpu.status &= !mask;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BreakpointInfo {
+    pub id: usize,
+    pub breakpoint: Breakpoint,
+    pub enabled: bool,
+    pub hits: u64,
+    pub condition: String,
+}
+
+#[derive(Clone)]
+pub struct DisassemblyLine {
+    pub address: u16,
+    pub bytes: String,
+    pub text: String,
+}
+
+/// Everything the debugger window shows, captured on the emulator thread
+#[derive(Clone, Default)]
+pub struct DebugView {
+    pub acc: u8,
+    pub idx_x: u8,
+    pub idx_y: u8,
+    pub status: u8,
+    pub sp: u8,
+    pub pc: u16,
+    pub cycles: u64,
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame: u64,
+    pub stopped: Option<Stop>,
+    pub disassembly: Vec<DisassemblyLine>,
+    pub breakpoints: Vec<BreakpointInfo>,
+    pub call_stack: Vec<CallFrame>,
+}
+
+impl DebugView {
+    pub fn capture(emulator: &Emulator) -> Self {
+        let cpu = &emulator.cpu;
+        let memory = emulator.memory.borrow();
+        let ppu = emulator.ppu.borrow();
+
+        let mut disassembly = Vec::with_capacity(DISASSEMBLY_LINES);
+        let mut address = cpu.pc;
+        for _ in 0..DISASSEMBLY_LINES {
+            let (instruction, size) = disasm::disassemble(&memory, address);
+            let bytes: Vec<String> = instruction
+                .bytes()
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+
+            disassembly.push(DisassemblyLine {
+                address,
+                bytes: bytes.join(" "),
+                text: instruction.to_string(),
+            });
+            address = address.wrapping_add(size);
+        }
+
+        let breakpoints = emulator
+            .debugger
+            .breakpoints()
+            .iter()
+            .map(|entry| BreakpointInfo {
+                id: entry.id,
+                breakpoint: entry.breakpoint,
+                enabled: entry.enabled,
+                hits: entry.hits,
+                condition: entry
+                    .condition
+                    .as_ref()
+                    .map(|condition| condition.text.clone())
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        DebugView {
+            acc: cpu.acc,
+            idx_x: cpu.idx_x,
+            idx_y: cpu.idx_y,
+            status: cpu.status,
+            sp: cpu.sp,
+            pc: cpu.pc,
+            cycles: cpu.cycles,
+            scanline: ppu.scanline,
+            dot: ppu.dot,
+            frame: emulator.frame_count(),
+            stopped: emulator.debugger.stopped,
+            disassembly,
+            breakpoints,
+            call_stack: emulator.debugger.call_stack().to_vec(),
+        }
+    }
+
+    fn register(&self, register: Register) -> u16 {
+        match register {
+            Register::A => self.acc as u16,
+            Register::X => self.idx_x as u16,
+            Register::Y => self.idx_y as u16,
+            Register::P => self.status as u16,
+            Register::Sp => self.sp as u16,
+            Register::Pc => self.pc,
+        }
+    }
+}
+
+fn describe_stop(stop: Option<Stop>) -> String {
+    match stop {
+        None => "Running".to_string(),
+        Some(Stop::Pause) => "Paused".to_string(),
+        Some(Stop::Step) => "Stepped".to_string(),
+        Some(Stop::Breakpoint(id)) => format!("Breakpoint {}", id),
+        Some(Stop::Watchpoint(id, hit)) => format!(
+            "Watchpoint {}: {} ${:02X} {} ${:04X}",
+            id,
+            if hit.write { "wrote" } else { "read" },
+            hit.value,
+            if hit.write { "to" } else { "from" },
+            hit.address
+        ),
+    }
+}
+
+/// Registers, disassembly, breakpoints and call stack. Shows the last
+/// [`DebugView`] received, and queues actions for the emulator thread.
+pub struct DebuggerWindow {
+    pub open: bool,
+    view: DebugView,
+    /// Register text being edited, in the order of `REGISTERS`
+    registers: [String; 6],
+    /// Which register fields were typed in since they got focus
+    edited: [bool; 6],
+    /// Condition text being edited, by breakpoint id
+    conditions: HashMap<usize, String>,
+    /// Why the last condition typed in didn't compile, by breakpoint id
+    condition_errors: HashMap<usize, String>,
+    /// Why the last register value typed in wasn't applied
+    register_error: Option<String>,
+    actions: Vec<DebugAction>,
+    attached: bool,
+}
+
+impl DebuggerWindow {
+    pub fn new() -> Self {
+        DebuggerWindow {
+            open: false,
+            view: DebugView::default(),
+            registers: Default::default(),
+            edited: [false; 6],
+            conditions: HashMap::new(),
+            condition_errors: HashMap::new(),
+            register_error: None,
+            actions: Vec::new(),
+            attached: false,
+        }
+    }
+
+    pub fn update(&mut self, view: DebugView) {
+        self.conditions
+            .retain(|id, _| view.breakpoints.iter().any(|info| info.id == *id));
+        self.condition_errors
+            .retain(|id, _| view.breakpoints.iter().any(|info| info.id == *id));
+        self.view = view;
+    }
+
+    pub fn show(&mut self, ctx: &egui::CtxRef) {
+        if self.open != self.attached {
+            self.attached = self.open;
+            self.actions.push(DebugAction::Attach(self.open));
+        }
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        egui::Window::new("Debugger")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                self.show_controls(ui);
+                ui.separator();
+                self.show_registers(ui);
+                ui.separator();
+
+                ui.columns(2, |columns| {
+                    self.show_disassembly(&mut columns[0]);
+                    self.show_breakpoints(&mut columns[1]);
+                    columns[1].separator();
+                    self.show_call_stack(&mut columns[1]);
+                });
+            });
+        self.open = open;
+    }
+
+    fn show_controls(&mut self, ui: &mut egui::Ui) {
+        let stopped = self.view.stopped.is_some();
+
+        ui.horizontal(|ui| {
+            if stopped {
+                if ui.button("Run").clicked() {
+                    self.actions.push(DebugAction::Resume);
+                }
+            } else if ui.button("Pause").clicked() {
+                self.actions.push(DebugAction::Pause);
+            }
+
+            let steps = [
+                ("Step into", DebugAction::StepInto),
+                ("Step over", DebugAction::StepOver),
+                ("Step out", DebugAction::StepOut),
+                ("Frame", DebugAction::FrameAdvance),
+            ];
+            for (label, action) in steps {
+                if ui.add_enabled(stopped, egui::Button::new(label)).clicked() {
+                    self.actions.push(action);
+                }
+            }
+        });
+
+        ui.label(describe_stop(self.view.stopped));
+        ui.label(format!(
+            "Frame {}  Scanline {}  Dot {}  Cycle {}",
+            self.view.frame, self.view.scanline, self.view.dot, self.view.cycles
+        ));
+    }
+
+    fn show_registers(&mut self, ui: &mut egui::Ui) {
+        // registers can only be edited while they hold still
+        let stopped = self.view.stopped.is_some();
+
+        ui.horizontal(|ui| {
+            for (i, &(register, name)) in REGISTERS.iter().enumerate() {
+                let width = if register == Register::Pc { 4 } else { 2 };
+
+                ui.label(name);
+                let edit = egui::TextEdit::singleline(&mut self.registers[i])
+                    .desired_width(width as f32 * 9.0)
+                    .text_style(egui::TextStyle::Monospace);
+                let response = ui.add_enabled(stopped, edit);
+                if response.changed() {
+                    self.edited[i] = true;
+                }
+
+                // edits apply when the field loses focus, if there were any
+                if response.lost_focus() && std::mem::take(&mut self.edited[i]) {
+                    let text = self.registers[i].trim();
+                    let value = if register == Register::Pc {
+                        u16::from_str_radix(text, 16).ok()
+                    } else {
+                        u8::from_str_radix(text, 16).ok().map(u16::from)
+                    };
+                    match value {
+                        Some(value) => {
+                            self.register_error = None;
+                            self.actions.push(DebugAction::SetRegister(register, value));
+                        }
+                        None => {
+                            self.register_error =
+                                Some(format!("Invalid {} value: {}", name, self.registers[i]))
+                        }
+                    }
+                } else if !response.has_focus() {
+                    self.registers[i] = format!("{:01$X}", self.view.register(register), width);
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            for (name, mask) in FLAGS {
+                let mut set = self.view.status & mask != 0;
+                if ui.checkbox(&mut set, name).changed() {
+                    self.actions.push(DebugAction::SetFlag(mask, set));
+                }
+            }
+            ui.monospace(format_flags(self.view.status));
+        });
+
+        if let Some(error) = &self.register_error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+
+    fn show_disassembly(&mut self, ui: &mut egui::Ui) {
+        ui.label("Disassembly");
+
+        for line in &self.view.disassembly {
+            let breakpoint = self
+                .view
+                .breakpoints
+                .iter()
+                .find(|info| info.breakpoint == Breakpoint::Exec(line.address));
+
+            ui.horizontal(|ui| {
+                let marker = match breakpoint {
+                    Some(info) if info.enabled => RichText::new("●").color(Color32::RED),
+                    Some(_) => RichText::new("○").color(Color32::RED),
+                    None => RichText::new("○").color(Color32::DARK_GRAY),
+                };
+                if ui
+                    .small_button(marker)
+                    .on_hover_text("Toggle breakpoint")
+                    .clicked()
+                {
+                    self.actions
+                        .push(DebugAction::ToggleBreakpoint(line.address));
+                }
+
+                let mut text = RichText::new(format!(
+                    "{:04X}  {:<8}  {}",
+                    line.address, line.bytes, line.text
+                ))
+                .monospace();
+                if line.address == self.view.pc {
+                    text = text.color(Color32::YELLOW);
+                }
+
+                if ui
+                    .add(egui::Label::new(text).sense(egui::Sense::click()))
+                    .on_hover_text("Double click to run to here")
+                    .double_clicked()
+                {
+                    self.actions.push(DebugAction::RunTo(line.address));
+                }
+            });
+        }
+    }
+
+    fn show_breakpoints(&mut self, ui: &mut egui::Ui) {
+        ui.label("Breakpoints");
+
+        for info in &self.view.breakpoints {
+            ui.horizontal(|ui| {
+                let mut enabled = info.enabled;
+                if ui
+                    .checkbox(&mut enabled, info.breakpoint.to_string())
+                    .changed()
+                {
+                    self.actions
+                        .push(DebugAction::SetBreakpointEnabled(info.id, enabled));
+                }
+                ui.label(format!("{} hits", info.hits));
+
+                if ui.small_button("x").on_hover_text("Remove").clicked() {
+                    self.actions.push(DebugAction::RemoveBreakpoint(info.id));
+                }
+            });
+
+            let condition = self
+                .conditions
+                .entry(info.id)
+                .or_insert_with(|| info.condition.clone());
+            let edit = egui::TextEdit::singleline(condition)
+                .hint_text("Condition")
+                .text_style(egui::TextStyle::Monospace);
+            let response = ui.add(edit);
+
+            if response.lost_focus() {
+                // checked here so the error can be shown next to the field
+                let compiled = match condition.trim() {
+                    "" => Ok(()),
+                    text => Expression::compile(text).map(|_| ()),
+                };
+                match compiled {
+                    Ok(()) => {
+                        self.condition_errors.remove(&info.id);
+                        self.actions
+                            .push(DebugAction::SetCondition(info.id, condition.clone()));
+                    }
+                    Err(e) => {
+                        self.condition_errors
+                            .insert(info.id, format!("Invalid condition {}: {}", condition, e));
+                    }
+                }
+            } else if !response.has_focus() {
+                *condition = info.condition.clone();
+            }
+            if let Some(error) = self.condition_errors.get(&info.id) {
+                ui.colored_label(Color32::RED, error);
+            }
+        }
+
+        if self.view.breakpoints.is_empty() {
+            ui.label("Click ○ next to an instruction to add one");
+        }
+    }
+
+    fn show_call_stack(&self, ui: &mut egui::Ui) {
+        ui.label("Call stack");
+
+        for frame in self.view.call_stack.iter().rev() {
+            let kind = if frame.interrupt { "NMI" } else { "JSR" };
+            ui.monospace(format!(
+                "{:04X}  {} from {:04X}",
+                frame.target, kind, frame.caller
+            ));
+        }
+
+        if self.view.call_stack.is_empty() {
+            ui.label("Empty");
+        }
+    }
+
+    /// Returns the actions queued since this was last called
+    pub fn take_actions(&mut self) -> Vec<DebugAction> {
+        std::mem::take(&mut self.actions)
+    }
+}
+
+impl Default for DebuggerWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
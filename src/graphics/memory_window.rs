@@ -0,0 +1,354 @@
+use egui_sdl2_gl::egui::{self, Color32, RichText};
+
+use crate::emulator::{region::Region, Emulator};
+
+const ROW_BYTES: usize = 16;
+const PAGE_ROWS: usize = 16;
+pub const PAGE_SIZE: usize = ROW_BYTES * PAGE_ROWS;
+
+/// Frames a changed byte stays highlighted for, fading out
+const HIGHLIGHT_FRAMES: u8 = 30;
+
+/// Sent from the memory window to the emulator thread
+pub enum MemoryAction {
+    /// Send this page of a region every frame, or nothing
+    View(Option<(Region, usize)>),
+    Poke {
+        region: Region,
+        offset: usize,
+        value: u8,
+    },
+    Search {
+        region: Region,
+        pattern: Vec<u8>,
+        from: usize,
+    },
+}
+
+/// A page of a region, captured on the emulator thread
+#[derive(Clone)]
+pub struct MemoryPage {
+    pub region: Region,
+    pub start: usize,
+    pub bytes: Vec<u8>,
+    /// Size of the whole region
+    pub len: usize,
+}
+
+impl MemoryPage {
+    pub fn capture(emulator: &Emulator, region: Region, start: usize) -> Self {
+        MemoryPage {
+            region,
+            start,
+            bytes: emulator.peek_region_range(region, start, PAGE_SIZE),
+            len: emulator.region_len(region),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SearchKind {
+    Bytes,
+    Text,
+}
+
+/// Parses hex bytes like `A9 00 8D` or `A9008D`
+fn parse_bytes(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err(format!("Expected pairs of hex digits: {}", text));
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("Invalid hex byte: {}", &digits[i..i + 2]))
+        })
+        .collect()
+}
+
+/// Hex editor for the regions in [`Region`]. Shows a page at a time, with
+/// bytes that changed in the last frames highlighted.
+pub struct MemoryWindow {
+    pub open: bool,
+    region: Region,
+    start: usize,
+    page: Option<MemoryPage>,
+    /// Frames left to highlight each byte of the page
+    highlight: Vec<u8>,
+    selected: Option<usize>,
+    value: String,
+    /// `value` was typed in since the field got focus
+    value_edited: bool,
+    goto: String,
+    search: String,
+    search_kind: SearchKind,
+    /// Errors shown next to the go to, search and edit fields
+    goto_error: Option<String>,
+    search_error: Option<String>,
+    value_error: Option<String>,
+    /// Where the last search matched, the next one starts after it
+    found: Option<usize>,
+    /// The page the emulator thread was last asked for
+    viewing: Option<(Region, usize)>,
+    actions: Vec<MemoryAction>,
+}
+
+impl MemoryWindow {
+    pub fn new() -> Self {
+        MemoryWindow {
+            open: false,
+            region: Region::Cpu,
+            start: 0,
+            page: None,
+            highlight: vec![0; PAGE_SIZE],
+            selected: None,
+            value: String::new(),
+            value_edited: false,
+            goto: String::new(),
+            search: String::new(),
+            search_kind: SearchKind::Bytes,
+            goto_error: None,
+            search_error: None,
+            value_error: None,
+            found: None,
+            viewing: None,
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, page: MemoryPage) {
+        if page.region != self.region || page.start != self.start {
+            return;
+        }
+
+        match &self.page {
+            Some(previous) if previous.region == page.region && previous.start == page.start => {
+                for (i, highlight) in self.highlight.iter_mut().enumerate() {
+                    if previous.bytes.get(i) != page.bytes.get(i) {
+                        *highlight = HIGHLIGHT_FRAMES;
+                    } else {
+                        *highlight = highlight.saturating_sub(1);
+                    }
+                }
+            }
+            _ => self.highlight.fill(0),
+        }
+
+        self.page = Some(page);
+    }
+
+    /// Called with the result of a [`MemoryAction::Search`]
+    pub fn found(&mut self, offset: Option<usize>) {
+        self.found = offset;
+        match offset {
+            Some(offset) => {
+                self.go_to(offset);
+                self.selected = Some(offset);
+                self.search_error = None;
+            }
+            None => self.search_error = Some(format!("Not found: {}", self.search)),
+        }
+    }
+
+    fn go_to(&mut self, offset: usize) {
+        self.start = offset - offset % PAGE_SIZE;
+    }
+
+    pub fn show(&mut self, ctx: &egui::CtxRef) {
+        let viewing = if self.open {
+            Some((self.region, self.start))
+        } else {
+            None
+        };
+        if viewing != self.viewing {
+            self.viewing = viewing;
+            self.actions.push(MemoryAction::View(viewing));
+        }
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        egui::Window::new("Memory").open(&mut open).show(ctx, |ui| {
+            self.show_navigation(ui);
+            ui.separator();
+            self.show_page(ui);
+            ui.separator();
+            self.show_edit(ui);
+        });
+        self.open = open;
+    }
+
+    fn show_navigation(&mut self, ui: &mut egui::Ui) {
+        let len = self.page.as_ref().map_or(PAGE_SIZE, |page| page.len);
+
+        ui.horizontal(|ui| {
+            for region in Region::ALL {
+                if ui
+                    .selectable_label(self.region == region, region.name())
+                    .clicked()
+                    && self.region != region
+                {
+                    self.region = region;
+                    self.start = 0;
+                    self.selected = None;
+                    self.found = None;
+                    self.search_error = None;
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("<").clicked() {
+                self.start = self.start.saturating_sub(PAGE_SIZE);
+            }
+            if ui.button(">").clicked() && self.start + PAGE_SIZE < len {
+                self.start += PAGE_SIZE;
+            }
+
+            ui.label("Go to");
+            let response = ui.add(egui::TextEdit::singleline(&mut self.goto).desired_width(50.0));
+            if response.lost_focus() {
+                match usize::from_str_radix(self.goto.trim().trim_start_matches('$'), 16) {
+                    Ok(offset) if offset < len => {
+                        self.go_to(offset);
+                        self.selected = Some(offset);
+                        self.goto_error = None;
+                    }
+                    _ => self.goto_error = Some(format!("Invalid address: {}", self.goto)),
+                }
+            }
+            if let Some(error) = &self.goto_error {
+                ui.colored_label(Color32::RED, error);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.search_kind, SearchKind::Bytes, "Hex");
+            ui.selectable_value(&mut self.search_kind, SearchKind::Text, "Text");
+            ui.add(egui::TextEdit::singleline(&mut self.search).desired_width(120.0));
+
+            if ui.button("Find next").clicked() {
+                let pattern = match self.search_kind {
+                    SearchKind::Bytes => parse_bytes(&self.search),
+                    SearchKind::Text => Ok(self.search.as_bytes().to_vec()),
+                };
+
+                match pattern {
+                    Ok(pattern) => {
+                        self.search_error = None;
+                        self.actions.push(MemoryAction::Search {
+                            region: self.region,
+                            pattern,
+                            from: self.found.map_or(self.start, |found| found + 1),
+                        });
+                    }
+                    Err(e) => self.search_error = Some(e),
+                }
+            }
+            if let Some(error) = &self.search_error {
+                ui.colored_label(Color32::RED, error);
+            }
+        });
+    }
+
+    fn show_page(&mut self, ui: &mut egui::Ui) {
+        let page = match &self.page {
+            Some(page) if page.region == self.region && page.start == self.start => page,
+            _ => {
+                ui.label("Loading...");
+                return;
+            }
+        };
+
+        for (row, bytes) in page.bytes.chunks(ROW_BYTES).enumerate() {
+            let row_start = page.start + row * ROW_BYTES;
+
+            ui.horizontal(|ui| {
+                ui.monospace(format!("{:04X}:", row_start));
+
+                for (i, &byte) in bytes.iter().enumerate() {
+                    let offset = row_start + i;
+                    let highlight = self.highlight[row * ROW_BYTES + i];
+
+                    let mut text = RichText::new(format!("{:02X}", byte)).monospace();
+                    if highlight > 0 {
+                        let strength = (highlight as u32 * 255 / HIGHLIGHT_FRAMES as u32) as u8;
+                        text =
+                            text.color(Color32::from_rgb(255, 255 - strength / 2, 255 - strength));
+                    }
+
+                    if ui
+                        .selectable_label(self.selected == Some(offset), text)
+                        .clicked()
+                    {
+                        self.selected = Some(offset);
+                        self.value = format!("{:02X}", byte);
+                        self.value_edited = false;
+                    }
+                }
+
+                let text: String = bytes
+                    .iter()
+                    .map(|&byte| {
+                        if byte.is_ascii_graphic() || byte == b' ' {
+                            byte as char
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect();
+                ui.monospace(text);
+            });
+        }
+    }
+
+    fn show_edit(&mut self, ui: &mut egui::Ui) {
+        let offset = match self.selected {
+            Some(offset) => offset,
+            None => {
+                ui.label("Click a byte to edit it");
+                return;
+            }
+        };
+
+        ui.horizontal(|ui| {
+            ui.label(format!("{} ${:04X} =", self.region.name(), offset));
+
+            let response = ui.add(egui::TextEdit::singleline(&mut self.value).desired_width(30.0));
+            if response.changed() {
+                self.value_edited = true;
+            }
+            // leaving the field untouched mustn't write back a stale value
+            if response.lost_focus() && std::mem::take(&mut self.value_edited) {
+                match u8::from_str_radix(self.value.trim(), 16) {
+                    Ok(value) => {
+                        self.value_error = None;
+                        self.actions.push(MemoryAction::Poke {
+                            region: self.region,
+                            offset,
+                            value,
+                        });
+                    }
+                    Err(_) => self.value_error = Some(format!("Invalid byte: {}", self.value)),
+                }
+            }
+            if let Some(error) = &self.value_error {
+                ui.colored_label(Color32::RED, error);
+            }
+        });
+    }
+
+    /// Returns the actions queued since this was last called
+    pub fn take_actions(&mut self) -> Vec<MemoryAction> {
+        std::mem::take(&mut self.actions)
+    }
+}
+
+impl Default for MemoryWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,4 +1,12 @@
+pub mod cheat_window;
+pub mod debugger_window;
 pub mod gui;
+pub mod memory_window;
+pub mod movie_window;
+pub mod ppu_window;
+pub mod ram_search_window;
+pub mod slot_window;
+pub mod watch_window;
 
 pub struct Graphics {
     pub sdl_context: sdl2::Sdl,
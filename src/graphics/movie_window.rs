@@ -0,0 +1,167 @@
+use egui_sdl2_gl::egui;
+
+use crate::emulator::Emulator;
+
+/// Sent from the movie window to the emulator thread
+pub enum MovieAction {
+    /// Records from power on, or from a save state of the current machine
+    Record {
+        from_savestate: bool,
+    },
+    Play {
+        path: String,
+        read_only: bool,
+    },
+    SetReadOnly(bool),
+    /// Stops the movie, saving it to `path` unless it was read only
+    Stop {
+        path: String,
+    },
+    /// Send the status every frame while true
+    View(bool),
+}
+
+/// The active movie, captured on the emulator thread
+#[derive(Clone, Default)]
+pub struct MovieStatus {
+    pub active: bool,
+    pub recording: bool,
+    pub read_only: bool,
+    pub finished: bool,
+    /// Movie frame the emulator is in, `None` before the first
+    pub frame: Option<usize>,
+    pub length: usize,
+    pub rerecords: u32,
+}
+
+impl MovieStatus {
+    pub fn capture(emulator: &Emulator) -> Self {
+        match emulator.movie() {
+            Some(session) => MovieStatus {
+                active: true,
+                recording: session.is_recording(),
+                read_only: session.read_only,
+                finished: session.is_finished(emulator),
+                frame: session.frame(emulator),
+                length: session.movie.frames.len(),
+                rerecords: session.movie.rerecords,
+            },
+            None => MovieStatus::default(),
+        }
+    }
+}
+
+/// Records and plays `.fm2` input movies
+pub struct MovieWindow {
+    pub open: bool,
+    path: String,
+    read_only: bool,
+    status: MovieStatus,
+    viewing: bool,
+    actions: Vec<MovieAction>,
+}
+
+impl MovieWindow {
+    /// `path` is where movies are saved and played from unless changed
+    pub fn new(path: String) -> Self {
+        MovieWindow {
+            open: false,
+            path,
+            read_only: true,
+            status: MovieStatus::default(),
+            viewing: false,
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, status: MovieStatus) {
+        self.status = status;
+    }
+
+    pub fn show(&mut self, ctx: &egui::CtxRef) {
+        if self.open != self.viewing {
+            self.viewing = self.open;
+            self.actions.push(MovieAction::View(self.open));
+        }
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        egui::Window::new("Movie").open(&mut open).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("File");
+                ui.add(egui::TextEdit::singleline(&mut self.path).desired_width(200.0));
+            });
+
+            if ui.checkbox(&mut self.read_only, "Read only").changed() {
+                self.actions.push(MovieAction::SetReadOnly(self.read_only));
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Record from power on").clicked() {
+                    self.actions.push(MovieAction::Record {
+                        from_savestate: false,
+                    });
+                }
+                if ui.button("Record from here").clicked() {
+                    self.actions.push(MovieAction::Record {
+                        from_savestate: true,
+                    });
+                }
+                if ui.button("Play").clicked() {
+                    self.actions.push(MovieAction::Play {
+                        path: self.path.clone(),
+                        read_only: self.read_only,
+                    });
+                }
+                if ui
+                    .add_enabled(self.status.active, egui::Button::new("Stop"))
+                    .clicked()
+                {
+                    self.actions.push(MovieAction::Stop {
+                        path: self.path.clone(),
+                    });
+                }
+            });
+            ui.separator();
+
+            let status = &self.status;
+            if !status.active {
+                ui.label("No movie");
+                return;
+            }
+
+            let state = if status.recording {
+                "Recording"
+            } else if status.finished {
+                "Finished"
+            } else {
+                "Playing"
+            };
+            let frame = status
+                .frame
+                .map_or("-".to_string(), |frame| frame.to_string());
+            ui.label(format!(
+                "{}{}, frame {} of {}",
+                state,
+                if status.read_only { " (read only)" } else { "" },
+                frame,
+                status.length
+            ));
+            ui.label(format!("Rerecords: {}", status.rerecords));
+        });
+        self.open = open;
+    }
+
+    /// Returns the actions queued since this was last called
+    pub fn take_actions(&mut self) -> Vec<MovieAction> {
+        std::mem::take(&mut self.actions)
+    }
+}
+
+impl Default for MovieWindow {
+    fn default() -> Self {
+        Self::new(String::new())
+    }
+}
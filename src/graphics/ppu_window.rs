@@ -0,0 +1,322 @@
+use egui_sdl2_gl::{
+    egui::{self, pos2, vec2, Color32, Rect, Stroke},
+    painter::Painter,
+};
+
+use crate::emulator::{
+    ppu_viewer::{self, Sprite, NAMETABLES_HEIGHT, NAMETABLES_WIDTH, PATTERN_TABLE_SIZE},
+    Emulator,
+};
+
+/// Sprites are drawn into one texture, 8 across of up to 8x16 each
+const SPRITE_ATLAS_WIDTH: usize = 64;
+const SPRITE_ATLAS_HEIGHT: usize = 128;
+
+/// PPU state for the viewers, captured on the emulator thread
+#[derive(Clone)]
+pub struct PpuView {
+    /// Both pattern tables, colored with palette 0
+    pub patterns: [Vec<u8>; 2],
+    pub nametables: Vec<u8>,
+    pub scroll: (usize, usize),
+    pub palette: [u8; 32],
+    pub sprites: Vec<Sprite>,
+    pub sprite_pixels: Vec<Vec<u8>>,
+    pub sprite_height: usize,
+}
+
+impl PpuView {
+    pub fn capture(emulator: &Emulator) -> Self {
+        let ppu = emulator.ppu.borrow();
+        let sprites = ppu_viewer::sprites(&ppu);
+
+        PpuView {
+            patterns: [
+                ppu_viewer::pattern_table(&ppu, 0, 0),
+                ppu_viewer::pattern_table(&ppu, 1, 0),
+            ],
+            nametables: ppu_viewer::nametables(&ppu),
+            scroll: ppu_viewer::scroll(&ppu),
+            palette: ppu.palette,
+            sprite_pixels: sprites
+                .iter()
+                .map(|sprite| ppu_viewer::sprite_pixels(&ppu, sprite))
+                .collect(),
+            sprites,
+            sprite_height: ppu_viewer::sprite_height(&ppu),
+        }
+    }
+}
+
+/// Palette RAM indices to RGBA, index 0 is transparent when `transparent`
+fn rgba(indices: &[u8], palette: &[u8; 32], transparent: bool) -> Vec<u8> {
+    indices
+        .iter()
+        .flat_map(|&index| {
+            let [r, g, b] = ppu_viewer::color(palette, index);
+            let alpha = if transparent && index == 0 { 0 } else { 0xFF };
+            [r, g, b, alpha]
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Tab {
+    Patterns,
+    Nametables,
+    Palettes,
+    Sprites,
+}
+
+struct Textures {
+    patterns: [egui::TextureId; 2],
+    nametables: egui::TextureId,
+    sprites: egui::TextureId,
+}
+
+/// Pattern table, nametable, palette and sprite viewers
+pub struct PpuWindow {
+    pub open: bool,
+    tab: Tab,
+    /// Palette the pattern tables are shown with, 0-3 background, 4-7 sprites
+    palette: u8,
+    view: Option<PpuView>,
+    textures: Option<Textures>,
+    /// Palette the pattern table textures were last drawn with
+    uploaded_palette: u8,
+    viewing: bool,
+}
+
+impl PpuWindow {
+    pub fn new() -> Self {
+        PpuWindow {
+            open: false,
+            tab: Tab::Patterns,
+            palette: 0,
+            view: None,
+            textures: None,
+            uploaded_palette: 0,
+            viewing: false,
+        }
+    }
+
+    pub fn update(&mut self, view: PpuView, painter: &mut Painter) {
+        self.view = Some(view);
+        self.upload(painter);
+    }
+
+    /// Redraws the pattern tables if another palette was picked since the
+    /// last view, which matters while the emulator is paused
+    pub fn refresh(&mut self, painter: &mut Painter) {
+        if self.palette != self.uploaded_palette {
+            self.upload(painter);
+        }
+    }
+
+    fn upload(&mut self, painter: &mut Painter) {
+        let view = match &self.view {
+            Some(view) => view,
+            None => return,
+        };
+        self.uploaded_palette = self.palette;
+
+        let patterns = view.patterns.clone().map(|pattern| {
+            let indices: Vec<u8> = pattern
+                .iter()
+                .map(|&index| self.palette * 4 + index)
+                .collect();
+            rgba(&indices, &view.palette, false)
+        });
+        let nametables = rgba(&view.nametables, &view.palette, false);
+
+        let mut sprites = vec![0; SPRITE_ATLAS_WIDTH * SPRITE_ATLAS_HEIGHT];
+        for (i, pixels) in view.sprite_pixels.iter().enumerate() {
+            let (left, top) = (i % 8 * 8, i / 8 * 16);
+            for (row, line) in pixels.chunks_exact(8).enumerate() {
+                let start = (top + row) * SPRITE_ATLAS_WIDTH + left;
+                sprites[start..start + 8].copy_from_slice(line);
+            }
+        }
+        let sprites = rgba(&sprites, &view.palette, true);
+
+        match &self.textures {
+            Some(textures) => {
+                let [low, high] = patterns;
+                painter.update_user_texture_rgba8_data(textures.patterns[0], low);
+                painter.update_user_texture_rgba8_data(textures.patterns[1], high);
+                painter.update_user_texture_rgba8_data(textures.nametables, nametables);
+                painter.update_user_texture_rgba8_data(textures.sprites, sprites);
+            }
+            None => {
+                let size = (PATTERN_TABLE_SIZE, PATTERN_TABLE_SIZE);
+                let [low, high] = patterns;
+                self.textures = Some(Textures {
+                    patterns: [
+                        painter.new_user_texture_rgba8(size, low, false),
+                        painter.new_user_texture_rgba8(size, high, false),
+                    ],
+                    nametables: painter.new_user_texture_rgba8(
+                        (NAMETABLES_WIDTH, NAMETABLES_HEIGHT),
+                        nametables,
+                        false,
+                    ),
+                    sprites: painter.new_user_texture_rgba8(
+                        (SPRITE_ATLAS_WIDTH, SPRITE_ATLAS_HEIGHT),
+                        sprites,
+                        false,
+                    ),
+                });
+            }
+        }
+    }
+
+    /// Returns whether views should be sent, when that changed since this
+    /// was last called
+    pub fn take_viewing(&mut self) -> Option<bool> {
+        if self.open != self.viewing {
+            self.viewing = self.open;
+            Some(self.open)
+        } else {
+            None
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::CtxRef) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        egui::Window::new("PPU").open(&mut open).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.tab, Tab::Patterns, "Pattern tables");
+                ui.selectable_value(&mut self.tab, Tab::Nametables, "Nametables");
+                ui.selectable_value(&mut self.tab, Tab::Palettes, "Palettes");
+                ui.selectable_value(&mut self.tab, Tab::Sprites, "Sprites");
+            });
+            ui.separator();
+
+            let (view, textures) = match (&self.view, &self.textures) {
+                (Some(view), Some(textures)) => (view, textures),
+                _ => {
+                    ui.label("Waiting for the next frame...");
+                    return;
+                }
+            };
+
+            match self.tab {
+                Tab::Patterns => {
+                    ui.horizontal(|ui| {
+                        ui.label("Palette");
+                        for palette in 0..8 {
+                            ui.selectable_value(&mut self.palette, palette, palette.to_string());
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let size = PATTERN_TABLE_SIZE as f32 * 2.0;
+                        ui.image(textures.patterns[0], [size, size]);
+                        ui.image(textures.patterns[1], [size, size]);
+                    });
+                }
+                Tab::Nametables => show_nametables(ui, view, textures),
+                Tab::Palettes => show_palettes(ui, view),
+                Tab::Sprites => show_sprites(ui, view, textures),
+            }
+        });
+        self.open = open;
+    }
+}
+
+impl Default for PpuWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn show_nametables(ui: &mut egui::Ui, view: &PpuView, textures: &Textures) {
+    let size = vec2(NAMETABLES_WIDTH as f32, NAMETABLES_HEIGHT as f32);
+    let response = ui.image(textures.nametables, size);
+
+    // the screen wraps around the edges of the nametables
+    let painter = ui.painter_at(response.rect);
+    let (x, y) = view.scroll;
+    let stroke = Stroke::new(2.0, Color32::RED);
+    for dx in [0.0, -size.x] {
+        for dy in [0.0, -size.y] {
+            let min = response.rect.min + vec2(x as f32 + dx, y as f32 + dy);
+            let screen = Rect::from_min_size(min, vec2(256.0, 240.0));
+            painter.rect_stroke(screen, 0.0, stroke);
+        }
+    }
+
+    ui.label(format!("Scroll: {}, {}", x, y));
+}
+
+fn show_palettes(ui: &mut egui::Ui, view: &PpuView) {
+    const SWATCH: f32 = 24.0;
+
+    for (half, name) in ["Background", "Sprites"].iter().enumerate() {
+        ui.label(*name);
+        for palette in 0..4 {
+            ui.horizontal(|ui| {
+                ui.monospace(format!("{}", half * 4 + palette));
+                for color in 0..4 {
+                    let index = (half * 16 + palette * 4 + color) as u8;
+                    let [r, g, b] = ppu_viewer::color(&view.palette, index);
+
+                    let (rect, response) =
+                        ui.allocate_exact_size(vec2(SWATCH, SWATCH), egui::Sense::hover());
+                    ui.painter()
+                        .rect_filled(rect, 0.0, Color32::from_rgb(r, g, b));
+                    response.on_hover_text(format!(
+                        "$3F{:02X} = ${:02X}",
+                        index, view.palette[index as usize]
+                    ));
+                }
+            });
+        }
+    }
+}
+
+fn show_sprites(ui: &mut egui::Ui, view: &PpuView, textures: &Textures) {
+    let height = view.sprite_height;
+
+    egui::ScrollArea::vertical()
+        .max_height(400.0)
+        .show(ui, |ui| {
+            for (i, sprite) in view.sprites.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let (left, top) = (i % 8 * 8, i / 8 * 16);
+                    let uv = Rect::from_min_max(
+                        pos2(
+                            left as f32 / SPRITE_ATLAS_WIDTH as f32,
+                            top as f32 / SPRITE_ATLAS_HEIGHT as f32,
+                        ),
+                        pos2(
+                            (left + 8) as f32 / SPRITE_ATLAS_WIDTH as f32,
+                            (top + height) as f32 / SPRITE_ATLAS_HEIGHT as f32,
+                        ),
+                    );
+                    let image =
+                        egui::Image::new(textures.sprites, [16.0, height as f32 * 2.0]).uv(uv);
+                    ui.add(image);
+
+                    ui.monospace(format!(
+                        "{:2}  X:{:3} Y:{:3} Tile:${:02X} Palette:{} {}{}{}",
+                        i,
+                        sprite.x,
+                        sprite.y,
+                        sprite.tile,
+                        sprite.palette() + 4,
+                        if sprite.behind_background() {
+                            "Behind"
+                        } else {
+                            "Front "
+                        },
+                        if sprite.flip_x() { " H" } else { "" },
+                        if sprite.flip_y() { " V" } else { "" },
+                    ));
+                });
+            }
+        });
+}
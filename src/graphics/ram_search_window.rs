@@ -0,0 +1,275 @@
+use egui_sdl2_gl::egui;
+
+use crate::{
+    emulator::{
+        ram_search::{Candidate, Comparison, Operand, RamSearch, Size},
+        watch::Format,
+        Emulator,
+    },
+    graphics::{cheat_window::CheatAction, watch_window::WatchAction},
+};
+
+/// Candidates sent to the window, the count covers all of them
+const RESULT_LIMIT: usize = 256;
+
+/// Sent from the RAM search window to the emulator thread, except for
+/// `Cheat` and `Watch` which the GUI thread hands to their windows
+pub enum RamSearchAction {
+    /// Starts over with every address as a candidate
+    Start {
+        size: Size,
+        signed: bool,
+    },
+    Filter(Comparison, Operand),
+    /// Send results every frame while true
+    View(bool),
+    Cheat(CheatAction),
+    Watch(WatchAction),
+}
+
+/// The first candidates of a search, captured on the emulator thread
+#[derive(Clone)]
+pub struct RamSearchView {
+    pub size: Size,
+    pub signed: bool,
+    pub count: usize,
+    pub candidates: Vec<Candidate>,
+}
+
+impl RamSearchView {
+    pub fn capture(search: &RamSearch, emulator: &Emulator) -> Self {
+        RamSearchView {
+            size: search.size,
+            signed: search.signed,
+            count: search.len(),
+            candidates: search.candidates(emulator, RESULT_LIMIT),
+        }
+    }
+}
+
+/// Parses decimal or `$` prefixed hex, either may be negative
+fn parse_value(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let value = match text.strip_prefix('$') {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => text.parse().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Raw codes forcing a candidate's current value, low byte first
+fn cheat_codes(size: Size, candidate: &Candidate) -> Vec<String> {
+    let bytes = (candidate.current as u16).to_le_bytes();
+    let len = if size == Size::Word { 2 } else { 1 };
+
+    (0..len)
+        .map(|i| format!("{:04X}:{:02X}", candidate.address + i as u16, bytes[i]))
+        .collect()
+}
+
+/// Narrows down where a value lives by filtering candidates frame after
+/// frame, then turns one into a watch or a cheat
+pub struct RamSearchWindow {
+    pub open: bool,
+    size: Size,
+    signed: bool,
+    comparison: Comparison,
+    /// Compare to `value` rather than the previous snapshot
+    specific: bool,
+    value: String,
+    /// Why the last filter wasn't applied
+    error: Option<String>,
+    view: Option<RamSearchView>,
+    viewing: bool,
+    actions: Vec<RamSearchAction>,
+}
+
+impl RamSearchWindow {
+    pub fn new() -> Self {
+        RamSearchWindow {
+            open: false,
+            size: Size::Byte,
+            signed: false,
+            comparison: Comparison::Equal,
+            specific: false,
+            value: String::new(),
+            error: None,
+            view: None,
+            viewing: false,
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, view: RamSearchView) {
+        self.view = Some(view);
+    }
+
+    pub fn show(&mut self, ctx: &egui::CtxRef) {
+        if self.open != self.viewing {
+            self.viewing = self.open;
+            self.actions.push(RamSearchAction::View(self.open));
+        }
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        egui::Window::new("RAM search")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.show_filters(ui);
+                ui.separator();
+                self.show_candidates(ui);
+            });
+        self.open = open;
+    }
+
+    fn show_filters(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.size, Size::Byte, "8-bit");
+            ui.selectable_value(&mut self.size, Size::Word, "16-bit");
+            ui.checkbox(&mut self.signed, "Signed");
+
+            if ui.button("Reset").clicked() {
+                self.actions.push(RamSearchAction::Start {
+                    size: self.size,
+                    signed: self.signed,
+                });
+            }
+        });
+
+        ui.horizontal(|ui| {
+            for (name, comparison) in [
+                ("Changed", Comparison::NotEqual),
+                ("Unchanged", Comparison::Equal),
+                ("Increased", Comparison::Greater),
+                ("Decreased", Comparison::Less),
+            ] {
+                if ui.button(name).clicked() {
+                    self.actions
+                        .push(RamSearchAction::Filter(comparison, Operand::Previous));
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Value");
+            for comparison in Comparison::ALL {
+                ui.selectable_value(&mut self.comparison, comparison, comparison.symbol());
+            }
+            ui.checkbox(&mut self.specific, "");
+            ui.add_enabled(
+                self.specific,
+                egui::TextEdit::singleline(&mut self.value).desired_width(60.0),
+            );
+            if !self.specific {
+                ui.label("previous");
+            }
+
+            if ui.button("Filter").clicked() {
+                // values are compared as the running search reads them
+                let (size, signed) = match &self.view {
+                    Some(view) => (view.size, view.signed),
+                    None => (self.size, self.signed),
+                };
+                let range = size.range(signed);
+
+                let operand = if self.specific {
+                    match parse_value(&self.value) {
+                        Some(value) if range.contains(&value) => Ok(Operand::Value(value)),
+                        Some(value) => Err(format!(
+                            "{} is outside {} to {}",
+                            value,
+                            range.start(),
+                            range.end()
+                        )),
+                        None => Err(format!("Invalid value: {}", self.value)),
+                    }
+                } else {
+                    Ok(Operand::Previous)
+                };
+                match operand {
+                    Ok(operand) => {
+                        self.error = None;
+                        self.actions
+                            .push(RamSearchAction::Filter(self.comparison, operand));
+                    }
+                    Err(e) => self.error = Some(e),
+                }
+            }
+            if let Some(error) = &self.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+    }
+
+    fn show_candidates(&mut self, ui: &mut egui::Ui) {
+        let view = match &self.view {
+            Some(view) => view,
+            None => {
+                ui.label("Loading...");
+                return;
+            }
+        };
+
+        ui.label(format!("{} candidates", view.count));
+        if view.count > view.candidates.len() {
+            ui.label(format!("Showing the first {}", view.candidates.len()));
+        }
+
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("candidates").striped(true).show(ui, |ui| {
+                    ui.label("Address");
+                    ui.label("Previous");
+                    ui.label("Current");
+                    ui.end_row();
+
+                    for candidate in &view.candidates {
+                        ui.monospace(format!("${:04X}", candidate.address));
+                        ui.monospace(candidate.previous.to_string());
+                        ui.monospace(candidate.current.to_string());
+
+                        if ui.small_button("Watch").clicked() {
+                            let format = match (view.size, view.signed) {
+                                (Size::Word, true) => Format::SignedWord,
+                                (Size::Word, false) => Format::Word,
+                                (Size::Byte, true) => Format::Signed,
+                                (Size::Byte, false) => Format::Decimal,
+                            };
+                            self.actions.push(RamSearchAction::Watch(WatchAction::Add {
+                                address: candidate.address,
+                                format,
+                                label: String::new(),
+                            }));
+                        }
+                        if ui.small_button("Cheat").clicked() {
+                            for code in cheat_codes(view.size, candidate) {
+                                self.actions.push(RamSearchAction::Cheat(CheatAction::Add {
+                                    code,
+                                    description: String::from("RAM search"),
+                                }));
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    /// Returns the actions queued since this was last called
+    pub fn take_actions(&mut self) -> Vec<RamSearchAction> {
+        std::mem::take(&mut self.actions)
+    }
+}
+
+impl Default for RamSearchWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,104 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use egui_sdl2_gl::{egui, painter::Painter};
+
+use crate::emulator::slots::{SaveSlots, SLOT_COUNT};
+
+pub enum SlotAction {
+    Save(usize),
+    Load(usize),
+}
+
+/// Grid of save state slots, clicking a thumbnail loads the slot
+pub struct SlotWindow {
+    /// Thumbnail texture and timestamp of each slot, `None` if the slot is empty
+    slots: Vec<Option<(egui::TextureId, u64)>>,
+    action: Option<SlotAction>,
+}
+
+impl SlotWindow {
+    pub fn new() -> Self {
+        SlotWindow {
+            slots: vec![None; SLOT_COUNT],
+            action: None,
+        }
+    }
+
+    /// Re-reads a slot from disk and uploads its thumbnail
+    pub fn refresh(&mut self, slot: usize, save_slots: &SaveSlots, painter: &mut Painter) {
+        if let Some((texture, _)) = self.slots[slot - 1].take() {
+            painter.free_user_texture(texture);
+        }
+
+        if let Some(info) = save_slots.info(slot) {
+            let thumbnail = info.thumbnail;
+            let rgba = thumbnail
+                .pixels
+                .chunks_exact(3)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 0xFF])
+                .collect();
+
+            let texture =
+                painter.new_user_texture_rgba8((thumbnail.width, thumbnail.height), rgba, false);
+            self.slots[slot - 1] = Some((texture, info.timestamp));
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::CtxRef) {
+        egui::Window::new("Save states").show(ctx, |ui| {
+            egui::Grid::new("save_slots").show(ui, |ui| {
+                for slot in 1..=SLOT_COUNT {
+                    ui.vertical(|ui| {
+                        ui.label(format!("Slot {}", slot));
+
+                        match self.slots[slot - 1] {
+                            Some((texture, timestamp)) => {
+                                let thumbnail = egui::ImageButton::new(texture, [64.0, 60.0]);
+                                if ui.add(thumbnail).on_hover_text("Load").clicked() {
+                                    self.action = Some(SlotAction::Load(slot));
+                                }
+                                ui.label(format_age(timestamp));
+                            }
+                            None => {
+                                ui.label("Empty");
+                            }
+                        }
+
+                        if ui.button("Save").clicked() {
+                            self.action = Some(SlotAction::Save(slot));
+                        }
+                    });
+
+                    if slot % 5 == 0 {
+                        ui.end_row();
+                    }
+                }
+            });
+        });
+    }
+
+    /// Returns the last slot clicked since this was last called
+    pub fn take_action(&mut self) -> Option<SlotAction> {
+        self.action.take()
+    }
+}
+
+impl Default for SlotWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn format_age(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+
+    match now.saturating_sub(timestamp) {
+        age @ 0..=59 => format!("{}s ago", age),
+        age @ 60..=3599 => format!("{}m ago", age / 60),
+        age @ 3600..=86399 => format!("{}h ago", age / 3600),
+        age => format!("{}d ago", age / 86400),
+    }
+}
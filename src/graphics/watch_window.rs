@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+
+use egui_sdl2_gl::egui::{self, pos2, vec2, Color32, Stroke};
+
+use crate::emulator::watch::{Format, WatchList, HISTORY_FRAMES};
+
+/// Sent from the watch window to the emulator thread
+pub enum WatchAction {
+    Add {
+        address: u16,
+        format: Format,
+        label: String,
+    },
+    Remove(usize),
+    SetLabel(usize, String),
+    SetFormat(usize, Format),
+    SetHistory(usize, bool),
+    /// Freezes a watch at its current value, or unfreezes it
+    Freeze(usize, bool),
+    /// Send the watches every frame while true
+    View(bool),
+}
+
+fn format_picker(ui: &mut egui::Ui, id: impl std::hash::Hash, format: &mut Format) -> bool {
+    let mut changed = false;
+    egui::ComboBox::from_id_source(id)
+        .selected_text(format.name())
+        .width(70.0)
+        .show_ui(ui, |ui| {
+            for option in Format::ALL {
+                changed |= ui.selectable_value(format, option, option.name()).changed();
+            }
+        });
+    changed
+}
+
+/// Draws recent values scaled to fit between their minimum and maximum
+fn sparkline(ui: &mut egui::Ui, history: &VecDeque<i64>) {
+    let size = vec2(HISTORY_FRAMES as f32, 16.0);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    if history.len() < 2 {
+        return;
+    }
+
+    let min = *history.iter().min().unwrap();
+    let max = *history.iter().max().unwrap();
+    let range = (max - min).max(1) as f32;
+
+    let points = history
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| {
+            let x = rect.left() + i as f32 * rect.width() / (HISTORY_FRAMES - 1) as f32;
+            let y = rect.bottom() - (value - min) as f32 / range * rect.height();
+            pos2(x, y)
+        })
+        .collect();
+    ui.painter().add(egui::Shape::line(
+        points,
+        Stroke::new(1.0, Color32::LIGHT_GREEN),
+    ));
+}
+
+/// Addresses with labels, shown in a chosen format and updated every frame
+pub struct WatchWindow {
+    pub open: bool,
+    watches: WatchList,
+    /// Index and text of the label being edited
+    editing: Option<(usize, String)>,
+    address: String,
+    format: Format,
+    label: String,
+    /// Why the last watch wasn't added
+    error: Option<String>,
+    viewing: bool,
+    actions: Vec<WatchAction>,
+}
+
+impl WatchWindow {
+    pub fn new() -> Self {
+        WatchWindow {
+            open: false,
+            watches: WatchList::default(),
+            editing: None,
+            address: String::new(),
+            format: Format::Hex,
+            label: String::new(),
+            error: None,
+            viewing: false,
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, watches: WatchList) {
+        self.watches = watches;
+    }
+
+    pub fn show(&mut self, ctx: &egui::CtxRef) {
+        if self.open != self.viewing {
+            self.viewing = self.open;
+            self.actions.push(WatchAction::View(self.open));
+        }
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        egui::Window::new("Watch").open(&mut open).show(ctx, |ui| {
+            self.show_watches(ui);
+            ui.separator();
+            self.show_add(ui);
+        });
+        self.open = open;
+    }
+
+    fn show_watches(&mut self, ui: &mut egui::Ui) {
+        if self.watches.watches.is_empty() {
+            ui.label("No watches for this ROM");
+            return;
+        }
+
+        egui::Grid::new("watches").striped(true).show(ui, |ui| {
+            for (i, watch) in self.watches.watches.iter().enumerate() {
+                ui.monospace(format!("${:04X}", watch.address));
+
+                match &mut self.editing {
+                    Some((index, text)) if *index == i => {
+                        let response = ui.text_edit_singleline(text);
+                        if response.lost_focus() {
+                            self.actions.push(WatchAction::SetLabel(i, text.clone()));
+                            self.editing = None;
+                        } else {
+                            response.request_focus();
+                        }
+                    }
+                    _ => {
+                        let label = if watch.label.is_empty() {
+                            "(label)"
+                        } else {
+                            &watch.label
+                        };
+                        if ui.selectable_label(false, label).clicked() {
+                            self.editing = Some((i, watch.label.clone()));
+                        }
+                    }
+                }
+
+                ui.monospace(watch.text());
+
+                let mut format = watch.format;
+                if format_picker(ui, ("watch format", i), &mut format) {
+                    self.actions.push(WatchAction::SetFormat(i, format));
+                }
+
+                let mut history = watch.history.is_some();
+                if ui.checkbox(&mut history, "History").changed() {
+                    self.actions.push(WatchAction::SetHistory(i, history));
+                }
+                match &watch.history {
+                    Some(history) => sparkline(ui, history),
+                    None => {
+                        ui.label("");
+                    }
+                }
+
+                let mut frozen = watch.frozen.is_some();
+                if ui.checkbox(&mut frozen, "Freeze").changed() {
+                    self.actions.push(WatchAction::Freeze(i, frozen));
+                }
+
+                if ui.small_button("x").clicked() {
+                    self.actions.push(WatchAction::Remove(i));
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    fn show_add(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Address");
+            ui.add(egui::TextEdit::singleline(&mut self.address).desired_width(40.0));
+            format_picker(ui, "new watch format", &mut self.format);
+            ui.label("Label");
+            ui.add(egui::TextEdit::singleline(&mut self.label).desired_width(100.0));
+
+            if ui.button("Add").clicked() {
+                match u16::from_str_radix(self.address.trim().trim_start_matches('$'), 16) {
+                    Ok(address) => {
+                        self.actions.push(WatchAction::Add {
+                            address,
+                            format: self.format,
+                            label: std::mem::take(&mut self.label),
+                        });
+                        self.address.clear();
+                        self.error = None;
+                    }
+                    Err(_) => self.error = Some(format!("Invalid address: {}", self.address)),
+                }
+            }
+            if let Some(error) = &self.error {
+                ui.colored_label(Color32::RED, error);
+            }
+        });
+    }
+
+    /// Returns the actions queued since this was last called
+    pub fn take_actions(&mut self) -> Vec<WatchAction> {
+        std::mem::take(&mut self.actions)
+    }
+}
+
+impl Default for WatchWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
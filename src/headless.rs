@@ -0,0 +1,568 @@
+//! Running the emulator without a window, for CI and scripted runs.
+
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::emulator::{
+    cartridge::Cartridge,
+    controller::Button,
+    movie::{Frame, Movie},
+    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    trace, Emulator,
+};
+
+/// Sample rate of the audio written by [`write_wav`]
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Controller input over time, read from a text script:
+///
+/// ```text
+/// # frame  port 1  [port 2]
+/// 0        .
+/// 30       T        # start
+/// 32       .
+/// 100      RA       A
+/// ```
+///
+/// Buttons are `R`ight, `L`eft, `D`own, `U`p, s`T`art, `S`elect, `B` and `A`,
+/// `.` releases everything. Buttons stay held until the next line.
+#[derive(Default)]
+pub struct InputScript {
+    /// (frame, buttons of both ports), sorted by frame
+    entries: Vec<(u64, [u8; 2])>,
+}
+
+impl InputScript {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        InputScript::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |message: &str| format!("Input script line {}: {}", number + 1, message);
+
+            let mut fields = line.split_whitespace();
+            let frame = fields
+                .next()
+                .and_then(|frame| frame.parse().ok())
+                .ok_or_else(|| error("expected a frame number"))?;
+
+            let mut buttons = [0; 2];
+            for (port, field) in fields.enumerate() {
+                if port >= 2 {
+                    return Err(error("more than two controllers"));
+                }
+                buttons[port] = parse_buttons(field).ok_or_else(|| error("unknown button"))?;
+            }
+
+            entries.push((frame, buttons));
+        }
+
+        entries.sort_by_key(|(frame, _)| *frame);
+        Ok(InputScript { entries })
+    }
+
+    /// Buttons held on both ports during `frame`
+    pub fn buttons_at(&self, frame: u64) -> [u8; 2] {
+        self.entries
+            .iter()
+            .take_while(|(start, _)| *start <= frame)
+            .last()
+            .map(|(_, buttons)| *buttons)
+            .unwrap_or([0; 2])
+    }
+
+    /// The first `frames` frames as a movie starting at power on
+    pub fn to_movie(&self, frames: u64, rom_filename: &str) -> Movie {
+        Movie {
+            frames: (0..frames)
+                .map(|frame| Frame {
+                    commands: 0,
+                    buttons: self.buttons_at(frame),
+                })
+                .collect(),
+            rom_filename: rom_filename.to_string(),
+            ..Movie::default()
+        }
+    }
+}
+
+fn parse_buttons(field: &str) -> Option<u8> {
+    if field == "." {
+        return Some(0);
+    }
+
+    field.chars().try_fold(0, |buttons, c| {
+        let button = match c {
+            'R' => Button::Right,
+            'L' => Button::Left,
+            'D' => Button::Down,
+            'U' => Button::Up,
+            'T' => Button::Start,
+            'S' => Button::Select,
+            'B' => Button::B,
+            'A' => Button::A,
+            _ => return None,
+        };
+        Some(buttons | button)
+    })
+}
+
+/// Stops a run early
+pub enum StopCondition {
+    /// The CPU is about to execute the instruction at this address
+    Pc(u16),
+    /// A CPU address holds this value
+    Memory(u16, u8),
+}
+
+impl StopCondition {
+    /// Parses `pc=ADDR` or `ADDR=VALUE`, numbers in hex with an optional `$`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (left, right) = text
+            .split_once('=')
+            .ok_or_else(|| format!("Expected pc=ADDR or ADDR=VALUE, got {}", text))?;
+
+        let hex = |text: &str| {
+            u16::from_str_radix(text.trim().trim_start_matches('$'), 16)
+                .map_err(|_| format!("Invalid hex number: {}", text))
+        };
+
+        if left.trim().eq_ignore_ascii_case("pc") {
+            Ok(StopCondition::Pc(hex(right)?))
+        } else {
+            let value = hex(right)?;
+            if value > 0xFF {
+                return Err(format!("Value doesn't fit in a byte: {}", right));
+            }
+            Ok(StopCondition::Memory(hex(left)?, value as u8))
+        }
+    }
+
+    pub fn check(&self, emulator: &Emulator) -> bool {
+        match *self {
+            StopCondition::Pc(address) => emulator.cpu.pc == address,
+            StopCondition::Memory(address, value) => {
+                // IO registers can't be read without side effects
+                matches!(address, 0x0000..=0x1FFF | 0x6000..=0xFFFF)
+                    && emulator.memory.borrow()[address] == value
+            }
+        }
+    }
+}
+
+/// Runs up to `max_frames` frames, feeding `input` to the controllers at the
+/// start of each frame. Stops early when the debugger breaks. Returns the
+/// number of frames run and whether `until` was met.
+pub fn run(
+    emulator: &mut Emulator,
+    max_frames: u64,
+    input: &InputScript,
+    until: Option<&StopCondition>,
+) -> (u64, bool) {
+    for frame in 0..max_frames {
+        let [port1, port2] = input.buttons_at(frame);
+        emulator.set_buttons(0, port1);
+        emulator.set_buttons(1, port2);
+
+        let start = emulator.frame_count();
+        while emulator.frame_count() == start {
+            emulator.cycle();
+
+            if until.is_some_and(|until| until.check(emulator)) {
+                return (frame + 1, true);
+            }
+            if emulator.is_stopped() {
+                return (frame + 1, false);
+            }
+        }
+    }
+
+    (max_frames, false)
+}
+
+/// What a frame of a regression run should look like
+#[derive(Debug, PartialEq, Eq)]
+pub enum Golden {
+    /// CRC32 of the RGB8 framebuffer
+    Hash(u32),
+    /// A PNG screenshot of the frame
+    Image(PathBuf),
+}
+
+/// Expected frames of a regression run, read from a text file:
+///
+/// ```text
+/// # frame  expected
+/// 60       1a2b3c4d      # framebuffer crc32, as printed by nes-headless
+/// 300      title.png     # relative to this file
+/// ```
+///
+/// Frame N is the picture after N frames have run, so the hash of frame N is
+/// what `nes-headless --frames N` prints.
+pub struct GoldenFrames {
+    /// Sorted by frame
+    pub frames: Vec<(u64, Golden)>,
+}
+
+impl GoldenFrames {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        GoldenFrames::parse(&text, path.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Parses a golden file, image paths are relative to `dir`
+    pub fn parse(text: &str, dir: &Path) -> Result<Self, String> {
+        let mut frames = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |message: &str| format!("Golden file line {}: {}", number + 1, message);
+
+            let (frame, expected) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| error("expected a frame and a hash or image"))?;
+            let frame = frame.parse().map_err(|_| error("invalid frame number"))?;
+            let expected = expected.trim();
+
+            let golden = if expected.ends_with(".png") {
+                Golden::Image(dir.join(expected))
+            } else {
+                Golden::Hash(u32::from_str_radix(expected, 16).map_err(|_| error("invalid hash"))?)
+            };
+            frames.push((frame, golden));
+        }
+
+        frames.sort_by_key(|(frame, _)| *frame);
+        Ok(GoldenFrames { frames })
+    }
+}
+
+/// Highlights differing pixels in red over a dimmed copy of `actual`
+pub fn diff_image(actual: &[u8], expected: &[u8]) -> Vec<u8> {
+    actual
+        .chunks(3)
+        .zip(expected.chunks(3))
+        .flat_map(|(a, e)| {
+            if a == e {
+                let gray = ((a[0] as u16 + a[1] as u16 + a[2] as u16) / 12) as u8;
+                [gray, gray, gray]
+            } else {
+                [0xFF, 0x00, 0x00]
+            }
+        })
+        .collect()
+}
+
+/// Plays `input` from power on and compares the frames listed in `golden`.
+/// For every mismatching frame `<name>.<frame>.actual.png` is written to
+/// `out_dir`, along with `.expected.png` and `.diff.png` when the golden is
+/// an image. Mismatches are reported as one `Err`, a line per frame.
+pub fn check_golden(
+    cartridge: &Cartridge,
+    input: &InputScript,
+    golden: &GoldenFrames,
+    name: &str,
+    out_dir: &Path,
+) -> Result<(), String> {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(cartridge)?;
+
+    let mut frames_run = 0;
+    let mut mismatches = Vec::new();
+
+    for (frame, expected) in &golden.frames {
+        for frame in frames_run..*frame {
+            let [port1, port2] = input.buttons_at(frame);
+            emulator.set_buttons(0, port1);
+            emulator.set_buttons(1, port2);
+            emulator.run_frame();
+        }
+        frames_run = *frame;
+
+        let actual = emulator.ppu.borrow().framebuffer.clone();
+        let out = |kind: &str| out_dir.join(format!("{}.{}.{}.png", name, frame, kind));
+
+        let expected_image = match expected {
+            Golden::Hash(hash) => {
+                if crc32(&actual) == *hash {
+                    continue;
+                }
+                mismatches.push(format!(
+                    "frame {}: crc32 {:08x}, expected {:08x}",
+                    frame,
+                    crc32(&actual),
+                    hash
+                ));
+                None
+            }
+            Golden::Image(path) => {
+                let (width, height, pixels) = read_png(path)?;
+                if (width, height) != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+                    return Err(format!(
+                        "{}: {}x{} image, expected {}x{}",
+                        path.display(),
+                        width,
+                        height,
+                        SCREEN_WIDTH,
+                        SCREEN_HEIGHT
+                    ));
+                }
+                if pixels == actual {
+                    continue;
+                }
+
+                let differing = actual
+                    .chunks(3)
+                    .zip(pixels.chunks(3))
+                    .filter(|(a, e)| a != e)
+                    .count();
+                mismatches.push(format!(
+                    "frame {}: {} pixels differ from {}",
+                    frame,
+                    differing,
+                    path.display()
+                ));
+                Some(pixels)
+            }
+        };
+
+        fs::create_dir_all(out_dir).map_err(|e| format!("{}: {}", out_dir.display(), e))?;
+        write_png(&out("actual"), SCREEN_WIDTH, SCREEN_HEIGHT, &actual)?;
+        if let Some(expected) = expected_image {
+            write_png(&out("expected"), SCREEN_WIDTH, SCREEN_HEIGHT, &expected)?;
+            let diff = diff_image(&actual, &expected);
+            write_png(&out("diff"), SCREEN_WIDTH, SCREEN_HEIGHT, &diff)?;
+        }
+    }
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(mismatches.join("\n"))
+    }
+}
+
+/// Trace lines shown before the first divergence from a reference log
+pub const NESTEST_CONTEXT: usize = 8;
+
+/// Runs nestest in automation mode, which starts at $C000 instead of the
+/// reset vector, and compares its trace line by line with `reference`, a
+/// Nintendulator log. Returns the number of lines compared, or a report of
+/// the first divergence.
+pub fn nestest(cartridge: &Cartridge, reference: &str) -> Result<usize, String> {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(cartridge)?;
+
+    // the state the reference log starts in, 7 cycles into the reset sequence
+    emulator.cpu.pc = 0xC000;
+    emulator.cpu.status = 0x24;
+    emulator.cpu.sp = 0xFD;
+    emulator.cpu.cycles = 7;
+    for _ in 0..21 {
+        emulator.ppu.borrow_mut().tick();
+    }
+
+    let mut history = VecDeque::with_capacity(NESTEST_CONTEXT);
+    let mut lines = 0;
+
+    for (number, expected) in reference.lines().enumerate() {
+        let actual = trace::nintendulator_line(&emulator);
+
+        if actual.trim_end() != expected.trim_end() {
+            let mut report = format!("nestest diverged at line {}:\n", number + 1);
+            for (context_number, line) in &history {
+                report += &format!("{:>6}  {}\n", context_number, line);
+            }
+            report += &format!("expected  {}\n", expected.trim_end());
+            report += &format!("actual    {}\n", actual);
+
+            // nestest stores the number of the failed test at $02 and $03
+            let memory = emulator.memory.borrow();
+            report += &format!(
+                "result codes: $02={:02X} $03={:02X}",
+                memory[0x02], memory[0x03]
+            );
+            return Err(report);
+        }
+
+        if history.len() == NESTEST_CONTEXT {
+            history.pop_front();
+        }
+        history.push_back((number + 1, actual));
+        lines += 1;
+
+        emulator.step();
+    }
+
+    Ok(lines)
+}
+
+/// Frames to wait before answering a blargg ROM's reset request, it asks
+/// for at least 100 ms
+const BLARGG_RESET_DELAY: u64 = 6;
+
+/// Reads the text a blargg test ROM writes at $6004
+fn blargg_text(emulator: &Emulator) -> String {
+    let memory = emulator.memory.borrow();
+    let text: Vec<u8> = memory.prg_ram[4..]
+        .iter()
+        .take_while(|&&byte| byte != 0)
+        .copied()
+        .collect();
+    String::from_utf8_lossy(&text).trim().to_string()
+}
+
+/// Runs a blargg test ROM until it reports a result through $6000, or for at
+/// most `max_frames` frames. Returns the text the ROM printed if it passed,
+/// otherwise the result code and text, or why it didn't finish.
+///
+/// $6000 only holds a status once $6001-$6003 hold the signature $DE $B0 $61:
+/// $80 while running, $81 when the ROM wants to be reset and the result code
+/// once done, 0 meaning passed.
+pub fn blargg(cartridge: &Cartridge, max_frames: u64) -> Result<String, String> {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(cartridge)?;
+
+    let mut reset_at = None;
+
+    for frame in 0..max_frames {
+        emulator.run_frame();
+
+        let (signature, status) = {
+            let memory = emulator.memory.borrow();
+            (
+                memory.prg_ram[1..4] == [0xDE, 0xB0, 0x61],
+                memory.prg_ram[0],
+            )
+        };
+        if !signature {
+            continue;
+        }
+
+        match status {
+            0x80 => {}
+            0x81 => match reset_at {
+                None => reset_at = Some(frame + BLARGG_RESET_DELAY),
+                Some(at) if frame >= at => {
+                    emulator.soft_reset();
+                    reset_at = None;
+                }
+                Some(_) => {}
+            },
+            0x00 => return Ok(blargg_text(&emulator)),
+            code => {
+                return Err(format!(
+                    "Failed with code {}: {}",
+                    code,
+                    blargg_text(&emulator)
+                ))
+            }
+        }
+    }
+
+    Err(format!(
+        "No result after {} frames: {}",
+        max_frames,
+        blargg_text(&emulator)
+    ))
+}
+
+/// Number of audio samples produced by `frames` frames
+pub fn samples_for_frames(frames: u64) -> usize {
+    (frames * SAMPLE_RATE as u64 / crate::emulator::FRAMES_PER_SECOND as u64) as usize
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// Writes an RGB8 image
+pub fn write_png(path: &Path, width: usize, height: usize, rgb: &[u8]) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+
+    let file = fs::File::create(path).map_err(|e| error(&e))?;
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(|e| error(&e))?;
+    writer.write_image_data(rgb).map_err(|e| error(&e))
+}
+
+/// Reads a PNG as RGB8, returns (width, height, pixels)
+pub fn read_png(path: &Path) -> Result<(usize, usize, Vec<u8>), String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+
+    let file = fs::File::open(path).map_err(|e| error(&e))?;
+    let mut decoder = png::Decoder::new(file);
+    // palettes and low bit depths are expanded to 8 bits per channel
+    decoder.set_transformations(png::Transformations::EXPAND);
+
+    let mut reader = decoder.read_info().map_err(|e| error(&e))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| error(&e))?;
+    buffer.truncate(info.buffer_size());
+
+    if info.bit_depth != png::BitDepth::Eight {
+        return Err(error(&"expected 8 bits per channel"));
+    }
+
+    let rgb = match info.color_type {
+        png::ColorType::Rgb => buffer,
+        png::ColorType::Rgba => buffer
+            .chunks(4)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|&v| [v, v, v]).collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks(2)
+            .flat_map(|pixel| [pixel[0], pixel[0], pixel[0]])
+            .collect(),
+        png::ColorType::Indexed => return Err(error(&"unexpanded palette")),
+    };
+
+    Ok((info.width as usize, info.height as usize, rgb))
+}
+
+/// Writes mono 16 bit PCM at [`SAMPLE_RATE`]
+pub fn write_wav(path: &Path, samples: &[i16]) -> Result<(), String> {
+    let data_len = (samples.len() * 2) as u32;
+
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes()); // byte rate
+    wav.extend_from_slice(&2u16.to_le_bytes()); // block align
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    fs::write(path, wav).map_err(|e| format!("{}: {}", path.display(), e))
+}
@@ -0,0 +1,4 @@
+pub mod emulator;
+#[cfg(feature = "gui")]
+pub mod graphics;
+pub mod headless;
@@ -1,17 +1,86 @@
 extern crate sdl2;
 
-pub mod emulator;
-pub mod graphics;
-
 use std::{
-    cell::Cell,
+    cell::{Cell, RefCell},
+    path::PathBuf,
     rc::Rc,
-    sync::{Arc, Mutex},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
-use graphics::Graphics;
-use sdl2::event::Event;
+use nes_emulator::emulator::{
+    battery::BatterySave,
+    cartridge::Cartridge,
+    cheats::{self, Cheat},
+    movie::Movie,
+    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    ram_search::{RamSearch, Size},
+    rewind::{Rewind, RewindConfig},
+    slots::{SaveSlots, Thumbnail, SLOT_COUNT},
+    watch::{self, WatchList},
+    Emulator, CPU_CYCLES_PER_FRAME,
+};
+use nes_emulator::graphics::{
+    cheat_window::{CheatAction, CheatWindow},
+    debugger_window::{DebugAction, DebugView, DebuggerWindow},
+    memory_window::{MemoryAction, MemoryPage, MemoryWindow},
+    movie_window::{MovieAction, MovieStatus, MovieWindow},
+    ppu_window::{PpuView, PpuWindow},
+    ram_search_window::{RamSearchAction, RamSearchView, RamSearchWindow},
+    slot_window::{SlotAction, SlotWindow},
+    watch_window::{WatchAction, WatchWindow},
+    Graphics,
+};
+use sdl2::{
+    event::Event,
+    keyboard::{Keycode, Mod},
+};
+
+const BATTERY_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Sent from the GUI thread to the emulator thread
+pub enum EmulatorCommand {
+    SaveSlot(usize),
+    LoadSlot(usize),
+    /// Rewind while true, sent when the rewind key is pressed and released
+    Rewind(bool),
+    RewindSpeed(f32),
+    Debug(DebugAction),
+    Memory(MemoryAction),
+    /// Send PPU views every frame while true
+    ViewPpu(bool),
+    Cheat(CheatAction),
+    RamSearch(RamSearchAction),
+    Watch(WatchAction),
+    Movie(MovieAction),
+    SoftReset,
+    PowerCycle,
+}
+
+/// Sent from the emulator thread back to the GUI thread
+pub enum EmulatorEvent {
+    SlotSaved(usize),
+    /// Sent every frame while the debugger window is open, and whenever
+    /// the emulator stops
+    Debug(Box<DebugView>),
+    /// Sent every frame while the memory window is open, and after edits
+    Memory(MemoryPage),
+    /// Result of a memory search
+    MemoryFound(Option<usize>),
+    Ppu(Box<PpuView>),
+    /// Sent on startup and after every change
+    Cheats(Vec<Cheat>),
+    /// Sent every frame while the RAM search window is open, and after
+    /// each filter
+    RamSearch(Box<RamSearchView>),
+    /// Sent every frame while the watch window is open, and after changes
+    Watches(WatchList),
+    /// Sent every frame while the movie window is open, and after changes
+    Movie(MovieStatus),
+}
 
 pub fn main() -> Result<(), String> {
     let mut gfx = Graphics::new();
@@ -25,16 +94,90 @@ pub fn main() -> Result<(), String> {
 
     let fps_clone = fps.clone();
 
+    let rewind_speed = Rc::new(Cell::new(1.0));
+    let mut sent_rewind_speed = rewind_speed.get();
+
+    let rewind_speed_clone = rewind_speed.clone();
+
+    // without a ROM on the command line the built in test program is run and
+    // its save slots go in the working directory
+    let rom_path = std::env::args().nth(1).map(PathBuf::from);
+    let save_path = rom_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("test_program"));
+    let save_slots = SaveSlots::new(&save_path);
+
+    let slot_window = Rc::new(RefCell::new(SlotWindow::new()));
+    for slot in 1..=SLOT_COUNT {
+        slot_window
+            .borrow_mut()
+            .refresh(slot, &save_slots, &mut gfx.gui.painter);
+    }
+
+    let slot_window_clone = slot_window.clone();
+
+    let debugger_window = Rc::new(RefCell::new(DebuggerWindow::new()));
+    let debugger_window_clone = debugger_window.clone();
+
+    let memory_window = Rc::new(RefCell::new(MemoryWindow::new()));
+    let memory_window_clone = memory_window.clone();
+
+    let ppu_window = Rc::new(RefCell::new(PpuWindow::new()));
+    let ppu_window_clone = ppu_window.clone();
+
+    let cheat_window = Rc::new(RefCell::new(CheatWindow::new()));
+    let cheat_window_clone = cheat_window.clone();
+
+    let ram_search_window = Rc::new(RefCell::new(RamSearchWindow::new()));
+    let ram_search_window_clone = ram_search_window.clone();
+
+    let watch_window = Rc::new(RefCell::new(WatchWindow::new()));
+    let watch_window_clone = watch_window.clone();
+
+    let movie_path = save_path.with_extension("fm2");
+    let movie_window = Rc::new(RefCell::new(MovieWindow::new(
+        movie_path.to_string_lossy().into_owned(),
+    )));
+    let movie_window_clone = movie_window.clone();
+
     gfx.gui
         .set_ui(Box::new(move |ctx: &egui_sdl2_gl::egui::CtxRef| {
             egui_sdl2_gl::egui::Window::new("Test window").show(ctx, |ui| {
                 ui.label(format!("Time: {:.2}s", start.elapsed().as_secs_f64()));
                 ui.separator();
                 ui.label(format!("FPS: {:.2}", fps_clone.get()));
+                ui.separator();
+
+                let mut speed = rewind_speed_clone.get();
+                ui.add(
+                    egui_sdl2_gl::egui::Slider::new(&mut speed, 0.25..=4.0).text("Rewind speed"),
+                );
+                rewind_speed_clone.set(speed);
+                ui.separator();
+
+                ui.checkbox(&mut debugger_window_clone.borrow_mut().open, "Debugger");
+                ui.checkbox(&mut memory_window_clone.borrow_mut().open, "Memory");
+                ui.checkbox(&mut ppu_window_clone.borrow_mut().open, "PPU");
+                ui.checkbox(&mut cheat_window_clone.borrow_mut().open, "Cheats");
+                ui.checkbox(&mut ram_search_window_clone.borrow_mut().open, "RAM search");
+                ui.checkbox(&mut watch_window_clone.borrow_mut().open, "Watch");
+                ui.checkbox(&mut movie_window_clone.borrow_mut().open, "Movie");
             });
+
+            slot_window_clone.borrow_mut().show(ctx);
+            debugger_window_clone.borrow_mut().show(ctx);
+            memory_window_clone.borrow_mut().show(ctx);
+            ppu_window_clone.borrow_mut().show(ctx);
+            cheat_window_clone.borrow_mut().show(ctx);
+            ram_search_window_clone.borrow_mut().show(ctx);
+            watch_window_clone.borrow_mut().show(ctx);
+            movie_window_clone.borrow_mut().show(ctx);
         }));
 
-    start_emulator();
+    let (commands, command_receiver) = mpsc::channel();
+    let (event_sender, events) = mpsc::channel();
+
+    let emulator_thread = start_emulator(rom_path, save_path, command_receiver, event_sender);
 
     'running: loop {
         if Instant::now().duration_since(last_second) >= Duration::from_secs(1) {
@@ -51,29 +194,224 @@ pub fn main() -> Result<(), String> {
 
         gfx.window.gl_swap_window();
 
+        match slot_window.borrow_mut().take_action() {
+            Some(SlotAction::Save(slot)) => {
+                commands.send(EmulatorCommand::SaveSlot(slot)).ok();
+            }
+            Some(SlotAction::Load(slot)) => {
+                commands.send(EmulatorCommand::LoadSlot(slot)).ok();
+            }
+            None => {}
+        }
+
+        for action in debugger_window.borrow_mut().take_actions() {
+            commands.send(EmulatorCommand::Debug(action)).ok();
+        }
+        for action in memory_window.borrow_mut().take_actions() {
+            commands.send(EmulatorCommand::Memory(action)).ok();
+        }
+        if let Some(viewing) = ppu_window.borrow_mut().take_viewing() {
+            commands.send(EmulatorCommand::ViewPpu(viewing)).ok();
+        }
+        ppu_window.borrow_mut().refresh(&mut gfx.gui.painter);
+        for action in cheat_window.borrow_mut().take_actions() {
+            commands.send(EmulatorCommand::Cheat(action)).ok();
+        }
+        for action in ram_search_window.borrow_mut().take_actions() {
+            match action {
+                RamSearchAction::Cheat(action) => {
+                    commands.send(EmulatorCommand::Cheat(action)).ok();
+                }
+                RamSearchAction::Watch(action) => {
+                    watch_window.borrow_mut().open = true;
+                    commands.send(EmulatorCommand::Watch(action)).ok();
+                }
+                action => {
+                    commands.send(EmulatorCommand::RamSearch(action)).ok();
+                }
+            }
+        }
+        for action in watch_window.borrow_mut().take_actions() {
+            commands.send(EmulatorCommand::Watch(action)).ok();
+        }
+        for action in movie_window.borrow_mut().take_actions() {
+            commands.send(EmulatorCommand::Movie(action)).ok();
+        }
+
+        if rewind_speed.get() != sent_rewind_speed {
+            sent_rewind_speed = rewind_speed.get();
+            commands
+                .send(EmulatorCommand::RewindSpeed(sent_rewind_speed))
+                .ok();
+        }
+
+        for event in events.try_iter() {
+            match event {
+                EmulatorEvent::SlotSaved(slot) => {
+                    slot_window
+                        .borrow_mut()
+                        .refresh(slot, &save_slots, &mut gfx.gui.painter);
+                }
+                EmulatorEvent::Debug(view) => debugger_window.borrow_mut().update(*view),
+                EmulatorEvent::Memory(page) => memory_window.borrow_mut().update(page),
+                EmulatorEvent::MemoryFound(offset) => memory_window.borrow_mut().found(offset),
+                EmulatorEvent::Ppu(view) => {
+                    ppu_window.borrow_mut().update(*view, &mut gfx.gui.painter)
+                }
+                EmulatorEvent::Cheats(list) => cheat_window.borrow_mut().update(list),
+                EmulatorEvent::RamSearch(view) => ram_search_window.borrow_mut().update(*view),
+                EmulatorEvent::Watches(watches) => watch_window.borrow_mut().update(watches),
+                EmulatorEvent::Movie(status) => movie_window.borrow_mut().update(status),
+            }
+        }
+
         for event in gfx.event_pump.poll_iter() {
             gfx.gui.process_event(&gfx.window, event.clone());
             match event {
                 Event::Quit { .. } => break 'running,
+                // hold backspace to rewind
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    repeat: false,
+                    ..
+                } => {
+                    commands.send(EmulatorCommand::Rewind(true)).ok();
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => {
+                    commands.send(EmulatorCommand::Rewind(false)).ok();
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    // Ctrl+R resets, Ctrl+P power cycles
+                    if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+                        match keycode {
+                            Keycode::R => {
+                                commands.send(EmulatorCommand::SoftReset).ok();
+                            }
+                            Keycode::P => {
+                                commands.send(EmulatorCommand::PowerCycle).ok();
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    // F1-F10 save, Shift+F1-F10 load
+                    if let Some(slot) = slot_hotkey(keycode) {
+                        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            commands.send(EmulatorCommand::LoadSlot(slot)).ok();
+                        } else {
+                            commands.send(EmulatorCommand::SaveSlot(slot)).ok();
+                        }
+                    }
+                }
                 _ => {}
             }
         }
     }
 
+    // let the emulator thread flush battery saves before exiting
+    drop(commands);
+    emulator_thread.join().ok();
+
     Ok(())
 }
 
-fn start_emulator() -> std::thread::JoinHandle<()> {
+/// Maps F1-F10 to save slots 1-10
+fn slot_hotkey(keycode: Keycode) -> Option<usize> {
+    let keys = [
+        Keycode::F1,
+        Keycode::F2,
+        Keycode::F3,
+        Keycode::F4,
+        Keycode::F5,
+        Keycode::F6,
+        Keycode::F7,
+        Keycode::F8,
+        Keycode::F9,
+        Keycode::F10,
+    ];
+
+    keys.iter()
+        .position(|&key| key == keycode)
+        .map(|index| index + 1)
+}
+
+fn start_emulator(
+    rom_path: Option<PathBuf>,
+    save_path: PathBuf,
+    commands: Receiver<EmulatorCommand>,
+    events: Sender<EmulatorEvent>,
+) -> std::thread::JoinHandle<()> {
     std::thread::spawn(move || {
-        let mut emulator = emulator::Emulator::new();
+        let mut emulator = Emulator::new();
+        let save_slots = SaveSlots::new(&save_path);
+        let mut battery = None;
+
+        let mut rewind = Rewind::new(RewindConfig::default());
+        let mut rewinding = false;
+
+        let mut debugger_attached = false;
+        let mut memory_page = None;
+        let mut ppu_viewing = false;
+        let mut ram_search: Option<RamSearch> = None;
+        let mut ram_search_viewing = false;
+        let mut watches_viewing = false;
+        let mut movie_viewing = false;
+        let mut was_stopped = false;
+
+        match &rom_path {
+            Some(rom_path) => {
+                let result = Cartridge::load(rom_path).and_then(|cartridge| {
+                    emulator.load_rom(&cartridge)?;
+                    Ok(cartridge)
+                });
+
+                let cartridge = match result {
+                    Ok(cartridge) => cartridge,
+                    Err(e) => {
+                        println!("Failed to load ROM: {}", e);
+                        return;
+                    }
+                };
+
+                if cartridge.battery {
+                    let mut save = BatterySave::new(rom_path);
+                    if let Err(e) = save.load(&mut emulator.memory.borrow_mut().prg_ram) {
+                        println!("Failed to load battery save: {}", e);
+                    }
+                    battery = Some(save);
+                }
+            }
+            None => load_test_program(&mut emulator),
+        }
+
+        let rom_filename = save_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+
+        let cheat_path = cheats::cheat_path(&save_path);
+        match cheats::load_cheats(&cheat_path) {
+            Ok(list) => emulator.set_cheats(list),
+            Err(e) => println!("Failed to load cheats: {}", e),
+        }
+        events
+            .send(EmulatorEvent::Cheats(emulator.cheats().to_vec()))
+            .ok();
 
-        // test loop program, acc should be 0x12 at the end
-        emulator.memory.borrow_mut().write_u16(0x0000, 0x8000);
-        emulator.load(vec![
-            0xA9, 0x12, // 0x8000 LDA immediate, load value 0x12 into accumulator
-            0x4C, 0x00, 0x00, // 0x8002 JMP, jump to the address at 0x0000
-            0xA9, 0x34, // 0x8005 LDA immediate, load value 0x34 into accumulator (skipped)
-        ]);
+        let watch_path = watch::watch_path(&save_path);
+        let mut watches = WatchList::load(&watch_path).unwrap_or_else(|e| {
+            println!("Failed to load watches: {}", e);
+            WatchList::default()
+        });
 
         let target_cycle_time = Duration::from_secs_f64(1.0 / 1_789_773.0);
 
@@ -82,10 +420,12 @@ fn start_emulator() -> std::thread::JoinHandle<()> {
 
         println!("Running...");
 
-        let mut cycles: i64 = 0;
+        let mut cycles: u64 = 0;
         let run_duration = Duration::from_secs(1);
         let start = Instant::now();
         let mut last_loop = Instant::now();
+        let mut reported = false;
+        let mut last_flush = Instant::now();
 
         let mut cycles_buffer = 0.0;
 
@@ -94,39 +434,311 @@ fn start_emulator() -> std::thread::JoinHandle<()> {
                 continue;
             }
 
+            loop {
+                match commands.try_recv() {
+                    Ok(EmulatorCommand::SaveSlot(slot)) => {
+                        let thumbnail = Thumbnail::downscale(
+                            &emulator.ppu.borrow().framebuffer,
+                            SCREEN_WIDTH,
+                            SCREEN_HEIGHT,
+                        );
+                        match save_slots.save(slot, emulator.save_state(), thumbnail) {
+                            Ok(()) => {
+                                events.send(EmulatorEvent::SlotSaved(slot)).ok();
+                            }
+                            Err(e) => println!("Failed to save slot {}: {}", slot, e),
+                        }
+                    }
+                    Ok(EmulatorCommand::LoadSlot(slot)) => {
+                        let result = save_slots
+                            .load(slot)
+                            .and_then(|state| emulator.load_state(&state));
+
+                        if let Err(e) = result {
+                            println!("Failed to load slot {}: {}", slot, e);
+                        }
+                    }
+                    Ok(EmulatorCommand::Rewind(active)) => rewinding = active,
+                    Ok(EmulatorCommand::SoftReset) => emulator.soft_reset(),
+                    Ok(EmulatorCommand::PowerCycle) => emulator.power_cycle(),
+                    Ok(EmulatorCommand::RewindSpeed(speed)) => rewind.speed = speed,
+                    Ok(EmulatorCommand::Debug(action)) => {
+                        if let DebugAction::Attach(attached) = action {
+                            debugger_attached = attached;
+                        }
+                        action.apply(&mut emulator);
+
+                        if debugger_attached {
+                            let view = DebugView::capture(&emulator);
+                            events.send(EmulatorEvent::Debug(Box::new(view))).ok();
+                        }
+                    }
+                    Ok(EmulatorCommand::ViewPpu(viewing)) => {
+                        ppu_viewing = viewing;
+
+                        if ppu_viewing {
+                            let view = PpuView::capture(&emulator);
+                            events.send(EmulatorEvent::Ppu(Box::new(view))).ok();
+                        }
+                    }
+                    Ok(EmulatorCommand::Memory(action)) => {
+                        match action {
+                            MemoryAction::View(page) => memory_page = page,
+                            MemoryAction::Poke {
+                                region,
+                                offset,
+                                value,
+                            } => emulator.poke_region(region, offset, value),
+                            MemoryAction::Search {
+                                region,
+                                pattern,
+                                from,
+                            } => {
+                                let found = emulator.search_region(region, &pattern, from);
+                                events.send(EmulatorEvent::MemoryFound(found)).ok();
+                            }
+                        }
+
+                        if let Some((region, start)) = memory_page {
+                            let page = MemoryPage::capture(&emulator, region, start);
+                            events.send(EmulatorEvent::Memory(page)).ok();
+                        }
+                    }
+                    Ok(EmulatorCommand::Cheat(action)) => {
+                        match action {
+                            CheatAction::Add { code, description } => {
+                                if let Err(e) = emulator.add_cheat(&code, &description) {
+                                    println!("Failed to add cheat: {}", e);
+                                }
+                            }
+                            CheatAction::Remove(index) => emulator.remove_cheat(index),
+                            CheatAction::SetEnabled(index, enabled) => {
+                                emulator.set_cheat_enabled(index, enabled)
+                            }
+                        }
+
+                        if let Err(e) = cheats::save_cheats(&cheat_path, emulator.cheats()) {
+                            println!("Failed to save cheats: {}", e);
+                        }
+                        events
+                            .send(EmulatorEvent::Cheats(emulator.cheats().to_vec()))
+                            .ok();
+                    }
+                    Ok(EmulatorCommand::RamSearch(action)) => {
+                        match action {
+                            RamSearchAction::Start { size, signed } => {
+                                ram_search = Some(RamSearch::new(&emulator, size, signed));
+                            }
+                            RamSearchAction::Filter(comparison, operand) => {
+                                if let Some(search) = &mut ram_search {
+                                    search.filter(&emulator, comparison, operand);
+                                }
+                            }
+                            RamSearchAction::View(viewing) => ram_search_viewing = viewing,
+                            // handled by the GUI thread
+                            RamSearchAction::Cheat(_) | RamSearchAction::Watch(_) => {}
+                        }
+
+                        if ram_search_viewing {
+                            let search = ram_search.get_or_insert_with(|| {
+                                RamSearch::new(&emulator, Size::Byte, false)
+                            });
+                            let view = RamSearchView::capture(search, &emulator);
+                            events.send(EmulatorEvent::RamSearch(Box::new(view))).ok();
+                        }
+                    }
+                    Ok(EmulatorCommand::Watch(action)) => {
+                        let changed = !matches!(action, WatchAction::View(_));
+                        match action {
+                            WatchAction::Add {
+                                address,
+                                format,
+                                label,
+                            } => watches
+                                .watches
+                                .push(watch::Watch::new(address, format, &label)),
+                            WatchAction::Remove(index) => {
+                                if index < watches.watches.len() {
+                                    watches.watches.remove(index);
+                                }
+                            }
+                            WatchAction::SetLabel(index, label) => {
+                                if let Some(watch) = watches.watches.get_mut(index) {
+                                    watch.label = label.trim().to_string();
+                                }
+                            }
+                            WatchAction::SetFormat(index, format) => {
+                                watches.set_format(index, format, &emulator)
+                            }
+                            WatchAction::SetHistory(index, history) => {
+                                watches.set_history(index, history)
+                            }
+                            WatchAction::Freeze(index, frozen) => watches.set_frozen(index, frozen),
+                            WatchAction::View(viewing) => watches_viewing = viewing,
+                        }
+
+                        if changed {
+                            if let Err(e) = watches.save(&watch_path) {
+                                println!("Failed to save watches: {}", e);
+                            }
+                        }
+                        watches.refresh(&emulator);
+                        if watches_viewing {
+                            events.send(EmulatorEvent::Watches(watches.clone())).ok();
+                        }
+                    }
+                    Ok(EmulatorCommand::Movie(action)) => {
+                        match action {
+                            MovieAction::Record { from_savestate } => {
+                                emulator.record_movie(from_savestate, &rom_filename)
+                            }
+                            MovieAction::Play { path, read_only } => {
+                                let result = Movie::load(path.as_ref())
+                                    .and_then(|movie| emulator.play_movie(movie, read_only));
+                                if let Err(e) = result {
+                                    println!("Failed to play movie: {}", e);
+                                }
+                            }
+                            MovieAction::SetReadOnly(read_only) => {
+                                emulator.set_movie_read_only(read_only)
+                            }
+                            MovieAction::Stop { path } => {
+                                let read_only =
+                                    emulator.movie().is_some_and(|movie| movie.read_only);
+                                if let Some(movie) = emulator.stop_movie() {
+                                    if !read_only {
+                                        if let Err(e) = movie.save(path.as_ref()) {
+                                            println!("Failed to save movie: {}", e);
+                                        }
+                                    }
+                                }
+                            }
+                            MovieAction::View(viewing) => movie_viewing = viewing,
+                        }
+
+                        if movie_viewing {
+                            events
+                                .send(EmulatorEvent::Movie(MovieStatus::capture(&emulator)))
+                                .ok();
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    // the GUI has quit
+                    Err(TryRecvError::Disconnected) => {
+                        flush_battery(&mut battery, &emulator);
+                        return;
+                    }
+                }
+            }
+
             let run_cycles = last_loop.elapsed().as_secs_f64() / target_cycle_time.as_secs_f64();
 
             last_loop = Instant::now();
 
-            if start.elapsed() >= run_duration {
-                break;
+            // flush battery saves now and then so they survive crashes
+            if last_flush.elapsed() >= BATTERY_FLUSH_INTERVAL {
+                last_flush = Instant::now();
+                flush_battery(&mut battery, &emulator);
             }
 
-            // if there are any cycles left over as decimal, add them to a buffer
-            cycles_buffer += run_cycles % 1.0 as f64;
+            if !reported && start.elapsed() >= run_duration {
+                reported = true;
 
-            // if the buffer gets over 1.0, add the int part to the cycles
-            let mut added_cycles = 0;
-            if cycles_buffer >= 1.0 {
-                added_cycles = cycles_buffer.trunc() as i32; // truncate to int
-                cycles_buffer -= added_cycles as f64;
+                println!(
+                    "Cycles per second: {:2}",
+                    cycles as f64 / start.elapsed().as_secs_f64()
+                );
+
+                if rom_path.is_none() {
+                    println!(
+                        "Acc at end of loop should be 0x12, it is: 0x{:X}",
+                        emulator.cpu.acc
+                    );
+                }
             }
 
-            // run the cycles
-            for _ in 0..run_cycles as i32 + added_cycles {
-                cycles += 1;
-                emulator.cpu.cycle();
+            // the wall clock only decides when frames run, the emulator runs
+            // whole frames so input and state changes land between them. After
+            // a stall the backlog is dropped rather than caught up on, which
+            // would hold off commands and input until it's done.
+            cycles_buffer = (cycles_buffer + run_cycles).min(2.0 * CPU_CYCLES_PER_FRAME as f64);
+            while cycles_buffer >= CPU_CYCLES_PER_FRAME as f64 {
+                cycles_buffer -= CPU_CYCLES_PER_FRAME as f64;
+
+                // frames don't pass while the debugger has stopped the CPU
+                if emulator.is_stopped() && !rewinding {
+                    continue;
+                }
+
+                // snapshots are taken and restored on frame boundaries, the
+                // CPU is paused while rewinding, which also keeps it silent
+                if rewinding {
+                    if let Err(e) = rewind.rewind_frame(&mut emulator) {
+                        println!("Failed to rewind: {}", e);
+                        rewinding = false;
+                    }
+                } else {
+                    let before = emulator.cpu.cycles;
+                    emulator.run_frame();
+                    cycles += emulator.cpu.cycles.wrapping_sub(before);
+                    rewind.on_frame(&emulator);
+                }
+
+                if debugger_attached {
+                    let view = DebugView::capture(&emulator);
+                    events.send(EmulatorEvent::Debug(Box::new(view))).ok();
+                }
+                if let Some((region, start)) = memory_page {
+                    let page = MemoryPage::capture(&emulator, region, start);
+                    events.send(EmulatorEvent::Memory(page)).ok();
+                }
+                if ppu_viewing {
+                    let view = PpuView::capture(&emulator);
+                    events.send(EmulatorEvent::Ppu(Box::new(view))).ok();
+                }
+                if !rewinding {
+                    watches.on_frame(&mut emulator);
+                }
+                if watches_viewing {
+                    events.send(EmulatorEvent::Watches(watches.clone())).ok();
+                }
+                if movie_viewing {
+                    events
+                        .send(EmulatorEvent::Movie(MovieStatus::capture(&emulator)))
+                        .ok();
+                }
+                if let (true, Some(search)) = (ram_search_viewing, &ram_search) {
+                    let view = RamSearchView::capture(search, &emulator);
+                    events.send(EmulatorEvent::RamSearch(Box::new(view))).ok();
+                }
             }
-        }
 
-        println!(
-            "Cycles per second: {:2}",
-            cycles as f64 / start.elapsed().as_secs_f64()
-        );
+            if emulator.is_stopped() != was_stopped {
+                was_stopped = emulator.is_stopped();
 
-        println!(
-            "Acc at end of loop should be 0x12, it is: 0x{:X}",
-            emulator.cpu.acc
-        );
+                if debugger_attached {
+                    let view = DebugView::capture(&emulator);
+                    events.send(EmulatorEvent::Debug(Box::new(view))).ok();
+                }
+            }
+        }
     })
 }
+
+fn load_test_program(emulator: &mut Emulator) {
+    // test loop program, acc should be 0x12 at the end
+    emulator.memory.borrow_mut().write_u16(0x0000, 0x8000);
+    emulator.load(vec![
+        0xA9, 0x12, // 0x8000 LDA immediate, load value 0x12 into accumulator
+        0x4C, 0x00, 0x00, // 0x8002 JMP, jump to the address at 0x0000
+        0xA9, 0x34, // 0x8005 LDA immediate, load value 0x34 into accumulator (skipped)
+    ]);
+}
+
+fn flush_battery(battery: &mut Option<BatterySave>, emulator: &Emulator) {
+    if let Some(battery) = battery {
+        if let Err(e) = battery.flush(&emulator.memory.borrow().prg_ram) {
+            println!("Failed to write battery save: {}", e);
+        }
+    }
+}